@@ -0,0 +1,386 @@
+//! Solves the dynamic programming models written in the modeling language of
+//! the library. The models are interpreted at runtime: there is no need to
+//! write nor to compile a `Problem` implementation to try a new model.
+use std::{
+    alloc::System,
+    fs::File,
+    io::{BufReader, BufWriter},
+    str::FromStr,
+    sync::{atomic::AtomicBool, Arc},
+    time::{Duration, Instant},
+};
+
+use anyhow::Result;
+use libc::{SIGALRM, SIGINT};
+use papier_lns::{
+    DpModel, MddLnsBuilder, ModelSelection, OutputFormat, Problem, ProofRecorder, PureDpBuilder,
+    ResolutionOutcome, ResolutionStatus, ReplayLog, ReplayOutcome, ReplayWriter, CompilationStats,
+    SanityCheckBuilder, SigLimitAllocator, SimpleMddBuilder, Solution, SolverReport,
+    kill_on_soft_limit,
+};
+use rand::SeedableRng;
+use rand_xoshiro::Xoshiro256Plus;
+use structopt::StructOpt;
+
+#[global_allocator]
+static ALLOC: SigLimitAllocator<System> = SigLimitAllocator::new(System, usize::MAX);
+
+/// This program lets you solve a dynamic programming model (written in the
+/// modeling language) with various methods
+#[derive(Debug, StructOpt)]
+enum Args {
+    /// Solve a model with lns+dd
+    Solve {
+        #[structopt(short, long)]
+        /// Path to the model we want to solve
+        fname: String,
+        /// Output the header
+        #[structopt(short = "H", long)]
+        header: bool,
+        #[structopt(short, long, default_value = "100")]
+        width: usize,
+        #[structopt(long, default_value = "20211105")]
+        seed: u64,
+        #[structopt(short, long, default_value = "0.1")]
+        proba: f64,
+        /// optional memory limit in gigabytes
+        #[structopt(short, long)]
+        ram_limit: Option<f64>,
+        /// optional hard memory limit in gigabytes: the run aborts with an
+        /// allocation error rather than exceeding it (beyond the ram limit,
+        /// the diagrams are merely narrowed)
+        #[structopt(long)]
+        hard_ram_limit: Option<f64>,
+        /// optional time limit in seconds
+        #[structopt(short, long)]
+        time_limit: Option<u32>,
+        /// optional initial solution to kickstart the solver
+        #[structopt(short, long)]
+        solution: Option<String>,
+        /// the output format: a table row or a json report
+        #[structopt(long, default_value = "table")]
+        output: OutputFormat,
+        /// optional file where the certificate of the optimum is written
+        /// (when optimality is proved). It is checked by `proofcheck`.
+        #[structopt(long)]
+        proof: Option<String>,
+        /// optional file where the iterations of the lns are logged (they
+        /// can then be replayed one by one with the replay command)
+        #[structopt(long)]
+        replay_log: Option<String>,
+    },
+    /// Replay one iteration of an lns run (logged with solve --replay-log)
+    Replay {
+        #[structopt(short, long)]
+        /// Path to the model that was solved
+        fname: String,
+        /// Path to the replay log
+        #[structopt(short, long)]
+        log: String,
+        /// The iteration to replay
+        #[structopt(short, long)]
+        iteration: usize,
+        /// optional file where the diagram of the iteration is dumped (in
+        /// the graphviz format)
+        #[structopt(long)]
+        dot: Option<String>,
+    },
+    /// Solve a model with a branch and bound dynamic programming
+    Dp {
+        #[structopt(short, long)]
+        /// Path to the model we want to solve
+        fname: String,
+        /// Output the header
+        #[structopt(short = "H", long)]
+        header: bool,
+        /// optional memory limit in gigabytes
+        #[structopt(short, long)]
+        ram_limit: Option<f64>,
+        /// optional time limit in seconds
+        #[structopt(short, long)]
+        time_limit: Option<u32>,
+        /// the output format: a table row or a json report
+        #[structopt(long, default_value = "table")]
+        output: OutputFormat,
+    },
+    /// Check the feasibility of a given solution
+    Check {
+        #[structopt(short, long)]
+        /// Path to the model
+        fname: String,
+        #[structopt(short, long)]
+        /// The solution to check
+        solution: String,
+    },
+    /// Run the sanity checks of a model (on a small instance)
+    Sanity {
+        #[structopt(short, long)]
+        /// Path to the model we want to check
+        fname: String,
+        /// the maximum number of states to explore
+        #[structopt(short, long, default_value = "1000000")]
+        max_states: usize,
+    },
+}
+
+fn main() -> Result<()> {
+    let args = Args::from_args();
+    match args {
+        Args::Solve { fname, header, width, seed, proba, ram_limit, hard_ram_limit, time_limit, solution, output, proof, replay_log } =>
+            solve(&fname, header, width, seed, proba, time_limit, ram_limit, hard_ram_limit, solution, output, proof, replay_log),
+        Args::Replay { fname, log, iteration, dot } => replay(&fname, &log, iteration, dot),
+        Args::Dp { fname, header, ram_limit, time_limit, output } =>
+            dp(&fname, header, time_limit, ram_limit, output),
+        Args::Check { fname, solution } => check(&fname, &solution),
+        Args::Sanity { fname, max_states } => sanity(&fname, max_states),
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn solve(fname: &str, header: bool, width: usize, seed: u64, proba: f64, time_limit: Option<u32>, ram_limit: Option<f64>, hard_ram_limit: Option<f64>, solution: Option<String>, output: OutputFormat, proof: Option<String>, replay_log: Option<String>) -> Result<()> {
+    let kill_switch = setup_kill_switch(time_limit, ram_limit)?;
+    set_hard_ram_limit(ram_limit, hard_ram_limit)?;
+    let model    = DpModel::try_from(File::open(fname)?)?;
+    let instname = instance_name(fname);
+    let start_tm = Instant::now();
+    //
+    let init_sol = solution.map(|s| Solution::from_str(&s)).transpose()?;
+    let init_val = init_sol.as_ref().map(|s| model.evaluate(&model.ordering(), s));
+
+    let recorder = proof.as_ref().map(|_| Arc::new(ProofRecorder::new()));
+
+    let mut mdd = SimpleMddBuilder::default();
+    mdd.problem(&model)
+        .var_ordering(model.ordering())
+        .node_selection(ModelSelection::new(&model))
+        .rng(Xoshiro256Plus::seed_from_u64(seed))
+        .proba(proba)
+        .kill_switch(Arc::clone(&kill_switch))
+        .memory(Arc::new(&ALLOC));
+    if let Some(recorder) = recorder.as_ref() {
+        mdd.proof(Arc::clone(recorder));
+    }
+    let mdd = mdd.build()?;
+
+    let mut solver = MddLnsBuilder::default()
+        .mdd(mdd)
+        .nb_var(model.nb_vars())
+        .width(width)
+        .initial_sol(init_sol)
+        .initial_val(init_val)
+        .start(start_tm)
+        .kill_switch(kill_switch)
+        .memory(Arc::new(&ALLOC));
+    if let Some(fname) = replay_log {
+        let log = ReplayWriter::new(BufWriter::new(File::create(fname)?))
+            .param("proba", proba);
+        solver = solver.replay(Arc::new(log));
+    }
+    let outcome = solver.build()?.minimize();
+    if let (Some(fname), Some(recorder)) = (proof, recorder) {
+        write_certificate(&fname, &recorder, &outcome)?;
+    }
+
+    let mut report = SolverReport::new(instname, "lns", outcome);
+    report.peak_ram_gb = ALLOC.get_peak_gb();
+    report.seed = Some(seed);
+    report
+        .param("width", width)
+        .param("proba", proba)
+        .param("time_limit", time_limit)
+        .param("ram_limit", ram_limit)
+        .param("hard_ram_limit", hard_ram_limit);
+    print_result(output, header, &report);
+
+    Ok(())
+}
+
+fn dp(fname: &str, header: bool, time_limit: Option<u32>, ram_limit: Option<f64>, output: OutputFormat) -> Result<()> {
+    let kill_switch = setup_kill_switch(time_limit, ram_limit)?;
+    stop_on_ram_limit(&kill_switch, ram_limit);
+    let model    = DpModel::try_from(File::open(fname)?)?;
+    let instname = instance_name(fname);
+    let start_tm = Instant::now();
+
+    let outcome = PureDpBuilder::default()
+        .problem(&model)
+        .var_ordering(model.ordering())
+        .start_time(start_tm)
+        .kill_switch(kill_switch)
+        .build()?
+        .minimize();
+
+    let mut report = SolverReport::new(instname, "dp", outcome);
+    report.peak_ram_gb = ALLOC.get_peak_gb();
+    report
+        .param("time_limit", time_limit)
+        .param("ram_limit", ram_limit);
+    print_result(output, header, &report);
+
+    Ok(())
+}
+
+fn check(fname: &str, solution: &str) -> Result<()> {
+    let model    = DpModel::try_from(File::open(fname)?)?;
+    let solution = Solution::from_str(solution)?;
+    let report   = model.check(&model.ordering(), &solution);
+    for violation in report.violations.iter() {
+        println!("{}", violation);
+    }
+    println!("cost {}", report.cost);
+    Ok(())
+}
+
+fn sanity(fname: &str, max_states: usize) -> Result<()> {
+    let model  = DpModel::try_from(File::open(fname)?)?;
+    let report = SanityCheckBuilder::default()
+        .problem(&model)
+        .var_ordering(model.ordering())
+        .max_states(max_states)
+        .build()?
+        .run();
+    println!("states {} -- transitions {} -- optimum {:?}", report.nb_states, report.nb_transitions, report.optimum);
+    for violation in report.violations.iter() {
+        println!("violation: {}", violation);
+    }
+    Ok(())
+}
+
+/// Writes the certificate of the optimum (if it has been proved)
+fn write_certificate(fname: &str, recorder: &ProofRecorder, outcome: &ResolutionOutcome<i64>) -> Result<()> {
+    match (outcome.status, outcome.best_value, outcome.best_sol.as_ref()) {
+        (ResolutionStatus::Closed { .. }, Some(value), Some(solution)) => {
+            let certificate = recorder.certificate(value, solution.clone());
+            certificate.write_to(BufWriter::new(File::create(fname)?))?;
+        }
+        _ => eprintln!("no certificate: optimality has not been proved"),
+    }
+    Ok(())
+}
+
+fn setup_kill_switch(time_limit: Option<u32>, ram_limit: Option<f64>) -> Result<Arc<AtomicBool>> {
+    let kill_switch = Arc::new(AtomicBool::new(false));
+    // ctrl + c   : interrupt program
+    signal_hook::flag::register(SIGINT, Arc::clone(&kill_switch))?;
+    // sigalrm for the timeout
+    signal_hook::flag::register(SIGALRM, Arc::clone(&kill_switch))?;
+
+    if let Some(seconds) = time_limit {
+        unsafe {
+            libc::alarm(seconds);
+        };
+    }
+    if let Some(gigabytes) = ram_limit {
+        ALLOC.set_soft_limit_gb(gigabytes);
+    }
+
+    Ok(kill_switch)
+}
+
+/// Makes the allocator refuse any allocation beyond the hard ram limit (which
+/// may not be lower than the ram limit)
+fn set_hard_ram_limit(ram_limit: Option<f64>, hard_ram_limit: Option<f64>) -> Result<()> {
+    if let Some(gigabytes) = hard_ram_limit {
+        if let Some(soft) = ram_limit.filter(|soft| *soft > gigabytes) {
+            anyhow::bail!("the hard ram limit ({} GB) is lower than the ram limit ({} GB)", gigabytes, soft);
+        }
+        ALLOC.set_hard_limit_gb(gigabytes);
+    }
+    Ok(())
+}
+
+/// The solvers which cannot narrow their search are simply stopped when the
+/// memory exceeds the ram limit
+fn stop_on_ram_limit(kill_switch: &Arc<AtomicBool>, ram_limit: Option<f64>) {
+    if ram_limit.is_some() {
+        kill_on_soft_limit(&ALLOC, Arc::clone(kill_switch), Duration::from_millis(10));
+    }
+}
+
+fn replay(fname: &str, log: &str, iteration: usize, dot: Option<String>) -> Result<()> {
+    let model = DpModel::try_from(File::open(fname)?)?;
+    let log   = ReplayLog::<i64>::read_from(BufReader::new(File::open(log)?))?;
+
+    let mut mdd = SimpleMddBuilder::default()
+        .problem(&model)
+        .var_ordering(model.ordering())
+        .node_selection(ModelSelection::new(&model))
+        .proba(log.param("proba")?.unwrap_or(0.1))
+        .kill_switch(Arc::new(AtomicBool::new(false)))
+        .build()?;
+    let outcome = papier_lns::replay(&mut mdd, &log, iteration)?;
+
+    print_replay(&outcome, &mdd.stats());
+    if let Some(dot) = dot {
+        mdd.write_dot(BufWriter::new(File::create(dot)?))?;
+    }
+    Ok(())
+}
+
+fn print_replay(outcome: &ReplayOutcome<i64>, stats: &CompilationStats) {
+    let entry = &outcome.entry;
+    let show  = |v: Option<i64>| v.map(|v| v.to_string()).unwrap_or_else(|| "N.A.".to_string());
+    println!("iteration {} -- depth {} -- width {} -- incumbent {}",
+        entry.iteration, entry.start_depth, entry.width, show(entry.incumbent));
+    println!("logged   value {} -- exact {}", show(entry.value), entry.exact);
+    println!("replayed value {} -- exact {} -- {}", show(outcome.value), outcome.exact,
+        if outcome.is_faithful() { "faithful" } else { "DIVERGED" });
+    println!("nodes {} -- dropped {} -- layers {} -- widths {:?}",
+        stats.nb_nodes, stats.dropped, stats.widths.len(), stats.widths);
+}
+
+fn instance_name(fname: &str) -> &str {
+    fname
+        .split_terminator(std::path::MAIN_SEPARATOR)
+        .next_back()
+        .unwrap_or("-- no name --")
+}
+
+fn print_header() {
+    // instance | method | status | value | ram in gb | time to best | time to proved | solution
+    println!(
+        "{:>20} | {:>10} | {:>20} | {:>10} | {:>8} | {:>10} | {:>10} | {:<80}",
+        "Instance", "Method", "Status", "Value", "RAM", "Best (s)", "Proved (s)", "Solution"
+    );
+}
+
+fn print_result(output: OutputFormat, header: bool, report: &SolverReport<i64>) {
+    match output {
+        OutputFormat::Json  => println!("{}", report.to_json()),
+        OutputFormat::Table => {
+            if header {
+                print_header();
+            }
+            print_row(report)
+        }
+    }
+}
+
+fn print_row(report: &SolverReport<i64>) {
+    let outcome = &report.outcome;
+    // instance | method | status | value | ram in gb | time to best | time to proved | solution
+    println!(
+        "{:>20} | {:>10} | {:>20} | {:>10} | {:>8.2} | {:>10} | {:>10} | {:<80}",
+        report.instance,
+        report.method,
+        outcome.status.to_str(),
+        outcome
+            .best_value
+            .map(|v| format!("{}", v))
+            .unwrap_or_else(|| "N.A.".to_string()),
+        report.peak_ram_gb,
+        outcome
+            .time_to_best
+            .map(|d| format!("{:.2}", d.as_secs_f32()))
+            .unwrap_or_else(|| "N.A.".to_string()),
+        outcome
+            .time_to_prove
+            .map(|d| format!("{:.2}", d.as_secs_f32()))
+            .unwrap_or_else(|| "N.A.".to_string()),
+        outcome
+            .best_sol
+            .as_ref()
+            .map(|sol| format!("{}", sol))
+            .unwrap_or_else(|| "-- no solution --".to_string())
+    );
+}
@@ -0,0 +1,45 @@
+//! Checks the optimality certificates of the dynamic programming models. This
+//! program shares nothing with the solver but the model: it replays the
+//! certificate against the model and confirms (or refutes) the optimum. The
+//! models of the modeling language have `i64` costs and no propagator: the
+//! certificates recorded otherwise are rejected. (The certificates of the psp
+//! and tsptw examples are checked by their own `check-proof` command.)
+use std::{
+    fs::File,
+    io::BufReader,
+    process::ExitCode,
+};
+
+use anyhow::Result;
+use papier_lns::{check_proof, DpModel, ProofLog};
+use structopt::StructOpt;
+
+/// Checks the certificate of the optimum of a dynamic programming model
+#[derive(Debug, StructOpt)]
+struct Args {
+    /// Path to the model
+    #[structopt(short, long)]
+    model: String,
+    /// Path to the certificate (as written by `dpmodel solve --proof`)
+    #[structopt(short, long)]
+    proof: String,
+}
+
+fn main() -> Result<ExitCode> {
+    let args  = Args::from_args();
+    let model = DpModel::try_from(File::open(&args.model)?)?;
+    let log   = ProofLog::<i64>::read_from(BufReader::new(File::open(&args.proof)?))?;
+
+    match check_proof(&model, &model.ordering(), None, &log) {
+        Ok(summary) => {
+            println!("valid -- optimum {} -- layers {} -- nodes {} -- merges {} -- prunes {} -- skips {}",
+                summary.optimum, summary.nb_layers, summary.nb_nodes, summary.nb_merges,
+                summary.nb_prunes, summary.nb_skips);
+            Ok(ExitCode::SUCCESS)
+        }
+        Err(error) => {
+            println!("invalid -- {}", error);
+            Ok(ExitCode::FAILURE)
+        }
+    }
+}
@@ -1,25 +1,28 @@
 mod psp;
+mod rolling;
 
 use anyhow::Result;
 use libc::SIGALRM;
 use papier_lns::{
-    SimpleMddBuilder, MddLnsBuilder, ResolutionOutcome,
-    SigLimitAllocator, Problem, Solution,
+    SimpleMddBuilder, MddLnsBuilder, AStarBuilder, PureDpBuilder, BeamSearchBuilder, BeamStrategy, SanityCheckBuilder, SolverReport, OutputFormat,
+    SigLimitAllocator, Problem, Solution, ByteBudget, kill_on_soft_limit, ReplayWriter, ReplayLog,
+    ReplayOutcome, CompilationStats, ProofRecorder, ProofLog, ResolutionOutcome, ResolutionStatus,
 };
-use psp::{Psp, RandomizedMinLP};
+use psp::{GreedyRollout, OrderingKind, Psp, PspCodec, PspOrdering, RandomizedMinLP};
 use rand::SeedableRng;
 use rand_xoshiro::Xoshiro256Plus;
 use signal_hook::consts::SIGINT;
 use std::{
     alloc::System,
     fs::File,
+    io::{BufReader, BufWriter},
     str::FromStr,
     sync::{atomic::AtomicBool, Arc},
-    time::Instant,
+    time::{Duration, Instant},
 };
 use structopt::StructOpt;
 
-use crate::psp::LeftToRight;
+use crate::{psp::LeftToRight, rolling::RollingHorizon};
 
 #[global_allocator]
 static ALLOC: SigLimitAllocator<System> = SigLimitAllocator::new(System, usize::MAX);
@@ -60,9 +63,179 @@ enum Args {
         /// optional memory limit in gigabytes
         #[structopt(short, long)]
         ram_limit: Option<f64>,
+        /// optional hard memory limit in gigabytes: the run aborts with an
+        /// allocation error rather than exceeding it (beyond the ram limit,
+        /// the diagrams are merely narrowed)
+        #[structopt(long)]
+        hard_ram_limit: Option<f64>,
         /// optional time limit in seconds
         #[structopt(short, long)]
         time_limit: Option<u32>,
+        /// store the states of the diagram in a compact form (saves memory
+        /// when the width is large)
+        #[structopt(short, long)]
+        compact: bool,
+        /// rank the nodes by the cost of their greedy completion when
+        /// restricting the diagram
+        #[structopt(long)]
+        rollout: bool,
+        /// optional memory budget (in megabytes) of each layer. When it is
+        /// set, the width of a layer depends on the size of its states (and
+        /// `width` merely caps the number of nodes)
+        #[structopt(long)]
+        layer_budget: Option<f64>,
+        /// optional memory budget (in megabytes) of the whole diagram
+        #[structopt(long, conflicts_with = "layer-budget")]
+        diagram_budget: Option<f64>,
+        /// the output format: a table row or a json report
+        #[structopt(long, default_value = "table")]
+        output: OutputFormat,
+        /// optional file where the iterations of the lns are logged (they
+        /// can then be replayed one by one with the replay command)
+        #[structopt(long)]
+        replay_log: Option<String>,
+        /// optional file where the certificate of the optimum is written
+        /// (when optimality is proved). It is checked by `check-proof`.
+        #[structopt(long)]
+        proof: Option<String>,
+        /// the variable ordering: left-to-right, or one of the dynamic
+        /// orderings (smallest-domain or most-constrained) which look at all
+        /// the states of a layer
+        #[structopt(long, default_value = "left-to-right")]
+        ordering: OrderingKind,
+    },
+    /// Check the certificate of an optimum (written by solve --proof)
+    CheckProof {
+        #[structopt(short, long)]
+        /// Path to the problem instance that was solved
+        fname: String,
+        /// Path to the certificate
+        #[structopt(short, long)]
+        proof: String,
+    },
+    /// Replay one iteration of an lns run (logged with solve --replay-log)
+    Replay {
+        #[structopt(short, long)]
+        /// Path to the problem instance that was solved
+        fname: String,
+        /// Path to the replay log
+        #[structopt(short, long)]
+        log: String,
+        /// The iteration to replay
+        #[structopt(short, long)]
+        iteration: usize,
+        /// optional file where the diagram of the iteration is dumped (in
+        /// the graphviz format)
+        #[structopt(long)]
+        dot: Option<String>,
+    },
+    /// Solve an instance with a best first search (A*)
+    Astar {
+        #[structopt(short, long)]
+        /// Path to the problem instance we want to solve
+        fname: String,
+        /// Output the header
+        #[structopt(short = "H", long)]
+        header: bool,
+        /// the weight of the estimate (a weight larger than one quickly finds
+        /// solutions and then keeps improving them until optimality is proved)
+        #[structopt(short, long, default_value = "1.0")]
+        weight: f64,
+        /// optional memory limit in gigabytes
+        #[structopt(short, long)]
+        ram_limit: Option<f64>,
+        /// optional time limit in seconds
+        #[structopt(short, long)]
+        time_limit: Option<u32>,
+        /// the output format: a table row or a json report
+        #[structopt(long, default_value = "table")]
+        output: OutputFormat,
+    },
+    /// Solve an instance with a branch and bound dynamic programming
+    Dp {
+        #[structopt(short, long)]
+        /// Path to the problem instance we want to solve
+        fname: String,
+        /// Output the header
+        #[structopt(short = "H", long)]
+        header: bool,
+        /// optional memory limit in gigabytes
+        #[structopt(short, long)]
+        ram_limit: Option<f64>,
+        /// optional time limit in seconds
+        #[structopt(short, long)]
+        time_limit: Option<u32>,
+        /// optional cap on the number of states kept in the cache (the deepest ones are evicted first)
+        #[structopt(short, long)]
+        cache_limit: Option<usize>,
+        /// the output format: a table row or a json report
+        #[structopt(long, default_value = "table")]
+        output: OutputFormat,
+    },
+    /// Solve an instance with an anytime beam search (no lns)
+    Beam {
+        #[structopt(short, long)]
+        /// Path to the problem instance we want to solve
+        fname: String,
+        /// Output the header
+        #[structopt(short = "H", long)]
+        header: bool,
+        /// the width of the (first) beam
+        #[structopt(short, long, default_value = "1")]
+        width: usize,
+        /// perform an anytime column search instead of doubling the width
+        /// of the beam
+        #[structopt(short, long)]
+        column: bool,
+        /// optional memory limit in gigabytes
+        #[structopt(short, long)]
+        ram_limit: Option<f64>,
+        /// optional time limit in seconds
+        #[structopt(short, long)]
+        time_limit: Option<u32>,
+        /// the output format: a table row or a json report
+        #[structopt(long, default_value = "table")]
+        output: OutputFormat,
+    },
+    /// Solve a (long) instance with a rolling horizon: the periods are
+    /// scheduled window by window, each window being solved with lns+dd
+    Rolling {
+        #[structopt(short, long)]
+        /// Path to the problem instance we want to solve
+        fname: String,
+        /// Output the header
+        #[structopt(short = "H", long)]
+        header: bool,
+        /// the number of periods of each window
+        #[structopt(long, default_value = "40")]
+        window: usize,
+        /// the number of periods committed before the window moves on
+        #[structopt(long, default_value = "20")]
+        step: usize,
+        #[structopt(short, long, default_value = "10000")]
+        width: usize,
+        #[structopt(short, long, default_value = "20211105")]
+        seed: u64,
+        #[structopt(short, long, default_value = "0.1")]
+        proba: f64,
+        /// optional memory limit in gigabytes
+        #[structopt(short, long)]
+        ram_limit: Option<f64>,
+        /// optional time limit in seconds (shared by all the windows)
+        #[structopt(short, long)]
+        time_limit: Option<u32>,
+        /// the output format: a table row or a json report
+        #[structopt(long, default_value = "table")]
+        output: OutputFormat,
+    },
+    /// Run the sanity checks of the model on a (small) instance
+    Sanity {
+        #[structopt(short, long)]
+        /// Path to the problem instance we want to check
+        fname: String,
+        /// the maximum number of states to explore
+        #[structopt(short, long, default_value = "1000000")]
+        max_states: usize,
     }
 }
 
@@ -73,17 +246,71 @@ fn main() -> anyhow::Result<()> {
         Args::Header => { print_header(); Ok(())},
         Args::Greedy { fname } => greedy(&fname),
         Args::Check  { fname, solution } => check(&fname, &solution),
-        Args::Solve  { fname, header, width, seed, proba, ram_limit, time_limit } => 
-            solve(&fname, header, width, seed, proba, time_limit, ram_limit)
+        Args::Solve  { fname, header, width, seed, proba, ram_limit, hard_ram_limit, time_limit, compact, rollout, layer_budget, diagram_budget, output, replay_log, proof, ordering } => 
+            solve(&fname, header, width, seed, proba, time_limit, ram_limit, hard_ram_limit, compact, rollout, layer_budget, diagram_budget, output, replay_log, proof, ordering),
+        Args::CheckProof { fname, proof } => check_proof(&fname, &proof),
+        Args::Replay { fname, log, iteration, dot } => replay(&fname, &log, iteration, dot),
+        Args::Astar  { fname, header, weight, ram_limit, time_limit, output } =>
+            astar(&fname, header, weight, time_limit, ram_limit, output),
+        Args::Dp     { fname, header, ram_limit, time_limit, cache_limit, output } =>
+            dp(&fname, header, time_limit, ram_limit, cache_limit, output),
+        Args::Beam   { fname, header, width, column, ram_limit, time_limit, output } =>
+            beam(&fname, header, width, column, time_limit, ram_limit, output),
+        Args::Rolling { fname, header, window, step, width, seed, proba, ram_limit, time_limit, output } =>
+            rolling(&fname, header, window, step, width, seed, proba, time_limit, ram_limit, output),
+        Args::Sanity { fname, max_states } => sanity(&fname, max_states),
     }
 }
 
 fn check(fname: &str, solution: &str) -> Result<()> {
     let instance = Psp::try_from(File::open(fname)?)?;
     let solution = Solution::from_str(solution)?;
-    let cost     = instance.evaluate(&LeftToRight, &solution);
-    instance.check(&LeftToRight, &solution);
-    println!("cost {}", cost);
+    let report   = instance.check(&LeftToRight, &solution);
+    for violation in report.violations.iter() {
+        println!("{}", violation);
+    }
+    println!("cost {}", report.cost);
+    Ok(())
+}
+
+fn write_certificate(fname: &str, recorder: &ProofRecorder, outcome: &ResolutionOutcome<isize>) -> Result<()> {
+    match (outcome.status, outcome.best_value, outcome.best_sol.as_ref()) {
+        (ResolutionStatus::Closed { .. }, Some(value), Some(solution)) => {
+            let certificate = recorder.certificate(value, solution.clone());
+            certificate.write_to(BufWriter::new(File::create(fname)?))?;
+        }
+        _ => eprintln!("no certificate: optimality has not been proved"),
+    }
+    Ok(())
+}
+
+fn check_proof(fname: &str, proof: &str) -> Result<()> {
+    let instance = Psp::try_from(File::open(fname)?)?;
+    let log      = ProofLog::<isize>::read_from(BufReader::new(File::open(proof)?))?;
+    match papier_lns::check_proof(&instance, &LeftToRight, None, &log) {
+        Ok(summary) => println!("valid -- optimum {} -- layers {} -- nodes {} -- merges {} -- prunes {} -- skips {}",
+            summary.optimum, summary.nb_layers, summary.nb_nodes, summary.nb_merges,
+            summary.nb_prunes, summary.nb_skips),
+        Err(error)  => {
+            println!("invalid -- {}", error);
+            std::process::exit(1);
+        }
+    }
+    Ok(())
+}
+
+fn sanity(fname: &str, max_states: usize) -> Result<()> {
+    let instance = Psp::try_from(File::open(fname)?)?;
+    let report   = SanityCheckBuilder::default()
+        .problem(&instance)
+        .var_ordering(LeftToRight)
+        .max_states(max_states)
+        .build()?
+        .run();
+    println!("states {} -- transitions {} -- optimum {:?}", report.nb_states, report.nb_transitions, report.optimum);
+    for violation in report.violations.iter() {
+        println!("violation: {}", violation);
+    }
     Ok(())
 }
 
@@ -100,8 +327,10 @@ fn greedy(fname: &str) -> Result<()> {
     Ok(())
 }
 
-fn solve(fname: &str, header: bool, width: usize, seed: u64, proba: f64, time_limit: Option<u32>, ram_limit: Option<f64>) -> Result<()> {
+#[allow(clippy::too_many_arguments)]
+fn solve(fname: &str, header: bool, width: usize, seed: u64, proba: f64, time_limit: Option<u32>, ram_limit: Option<f64>, hard_ram_limit: Option<f64>, compact: bool, rollout: bool, layer_budget: Option<f64>, diagram_budget: Option<f64>, output: OutputFormat, replay_log: Option<String>, proof: Option<String>, ordering: OrderingKind) -> Result<()> {
     let kill_switch = setup_kill_switch(time_limit, ram_limit)?;
+    set_hard_ram_limit(ram_limit, hard_ram_limit)?;
     let instance = Psp::try_from(File::open(fname)?)?;
     let instname = instance_name(fname);
     let start_tm = Instant::now();
@@ -110,14 +339,16 @@ fn solve(fname: &str, header: bool, width: usize, seed: u64, proba: f64, time_li
     let init_val = Some(greedy.0);
     let init_sol = greedy.1;
 
-    let mdd = SimpleMddBuilder::default()
-        .problem(&instance)
-        .var_ordering(LeftToRight)
-        .node_selection(RandomizedMinLP::default())
-        .rng(Xoshiro256Plus::seed_from_u64(seed))
-        .proba(proba)
+    let recorder = proof.as_ref().map(|_| Arc::new(ProofRecorder::new()));
+
+    let mut mdd = mdd_builder(&instance, ordering, proba, compact, rollout, layer_budget, diagram_budget)?;
+    mdd.rng(Xoshiro256Plus::seed_from_u64(seed))
         .kill_switch(Arc::clone(&kill_switch))
-        .build()?;
+        .memory(Arc::new(&ALLOC));
+    if let Some(recorder) = recorder.as_ref() {
+        mdd.proof(Arc::clone(recorder));
+    }
+    let mdd = mdd.build()?;
 
     let mut solver = MddLnsBuilder::default()
         .mdd(mdd)
@@ -127,22 +358,239 @@ fn solve(fname: &str, header: bool, width: usize, seed: u64, proba: f64, time_li
         .initial_val(init_val)
         .start(start_tm)
         .kill_switch(kill_switch)
-        .build()?;
+        .memory(Arc::new(&ALLOC));
+    if let Some(fname) = replay_log {
+        let log = ReplayWriter::new(BufWriter::new(File::create(fname)?))
+            .param("ordering", ordering.to_string())
+            .param("proba", proba)
+            .param("compact", compact)
+            .param("rollout", rollout)
+            .param("layer_budget", layer_budget)
+            .param("diagram_budget", diagram_budget);
+        solver = solver.replay(Arc::new(log));
+    }
     //
-    let outcome = solver.minimize();
+    let outcome = solver.build()?.minimize();
+    if let (Some(fname), Some(recorder)) = (proof, recorder) {
+        write_certificate(&fname, &recorder, &outcome)?;
+    }
     
     // ////////////////////////////////////////////////////////////////////////
     // Print the output
     // ////////////////////////////////////////////////////////////////////////
-    if header {
-        print_header();
-    }
-    let ram = ALLOC.get_peak_gb();
-    print_result(instname, ram, outcome);
+    let mut report = SolverReport::new(instname, "lns", outcome);
+    report.peak_ram_gb = ALLOC.get_peak_gb();
+    report.seed = Some(seed);
+    report
+        .param("width", width)
+        .param("ordering", ordering.to_string())
+        .param("proba", proba)
+        .param("compact", compact)
+        .param("rollout", rollout)
+        .param("layer_budget", layer_budget)
+        .param("diagram_budget", diagram_budget)
+        .param("time_limit", time_limit)
+        .param("ram_limit", ram_limit)
+        .param("hard_ram_limit", hard_ram_limit);
+    print_result(output, header, &report);
 
     Ok(())
 } 
 
+/// Configures the mdd used by the lns (everything but its rng and its kill
+/// switch)
+fn mdd_builder(instance: &Psp, ordering: OrderingKind, proba: f64, compact: bool, rollout: bool, layer_budget: Option<f64>, diagram_budget: Option<f64>) -> Result<SimpleMddBuilder<&Psp, PspOrdering<'_>, RandomizedMinLP>> {
+    let mut mdd = SimpleMddBuilder::default();
+    mdd.problem(instance)
+        .var_ordering(PspOrdering::new(ordering, instance))
+        .node_selection(RandomizedMinLP)
+        .proba(proba);
+    if compact {
+        mdd.codec(Arc::new(PspCodec::new(instance)?));
+    }
+    if rollout {
+        mdd.rollout(Arc::new(GreedyRollout::new(instance)));
+    }
+    if let Some(megabytes) = layer_budget {
+        mdd.budget(ByteBudget::per_layer_mb(megabytes));
+    }
+    if let Some(megabytes) = diagram_budget {
+        mdd.budget(ByteBudget::per_diagram_mb(megabytes));
+    }
+    Ok(mdd)
+}
+
+fn replay(fname: &str, log: &str, iteration: usize, dot: Option<String>) -> Result<()> {
+    let instance = Psp::try_from(File::open(fname)?)?;
+    let log      = ReplayLog::<isize>::read_from(BufReader::new(File::open(log)?))?;
+
+    let ordering = log.param::<String>("ordering")?
+        .map(|name| OrderingKind::from_str(&name))
+        .transpose()
+        .map_err(anyhow::Error::msg)?
+        .unwrap_or(OrderingKind::LeftToRight);
+    let mut mdd = mdd_builder(&instance,
+            ordering,
+            log.param("proba")?.unwrap_or(0.1),
+            log.param("compact")?.unwrap_or(false),
+            log.param("rollout")?.unwrap_or(false),
+            log.param("layer_budget")?.flatten(),
+            log.param("diagram_budget")?.flatten())?
+        .kill_switch(Arc::new(AtomicBool::new(false)))
+        .build()?;
+    let outcome = papier_lns::replay(&mut mdd, &log, iteration)?;
+
+    print_replay(&outcome, &mdd.stats());
+    if let Some(dot) = dot {
+        mdd.write_dot(BufWriter::new(File::create(dot)?))?;
+    }
+    Ok(())
+}
+
+fn print_replay(outcome: &ReplayOutcome<isize>, stats: &CompilationStats) {
+    let entry = &outcome.entry;
+    let show  = |v: Option<isize>| v.map(|v| v.to_string()).unwrap_or_else(|| "N.A.".to_string());
+    println!("iteration {} -- depth {} -- width {} -- incumbent {}",
+        entry.iteration, entry.start_depth, entry.width, show(entry.incumbent));
+    println!("logged   value {} -- exact {}", show(entry.value), entry.exact);
+    println!("replayed value {} -- exact {} -- {}", show(outcome.value), outcome.exact,
+        if outcome.is_faithful() { "faithful" } else { "DIVERGED" });
+    println!("nodes {} -- dropped {} -- layers {} -- widths {:?}",
+        stats.nb_nodes, stats.dropped, stats.widths.len(), stats.widths);
+}
+
+fn astar(fname: &str, header: bool, weight: f64, time_limit: Option<u32>, ram_limit: Option<f64>, output: OutputFormat) -> Result<()> {
+    let kill_switch = setup_kill_switch(time_limit, ram_limit)?;
+    stop_on_ram_limit(&kill_switch, ram_limit);
+    let instance = Psp::try_from(File::open(fname)?)?;
+    let instname = instance_name(fname);
+    let start_tm = Instant::now();
+    //
+    let greedy = instance.greedy();
+
+    let solver = AStarBuilder::default()
+        .problem(&instance)
+        .var_ordering(LeftToRight)
+        .start_time(start_tm)
+        .kill_switch(kill_switch)
+        .weight(weight)
+        .initial_val(Some(greedy.0))
+        .initial_sol(greedy.1)
+        .build()?;
+    //
+    let outcome = solver.minimize();
+
+    let mut report = SolverReport::new(instname, "astar", outcome);
+    report.peak_ram_gb = ALLOC.get_peak_gb();
+    report
+        .param("weight", weight)
+        .param("time_limit", time_limit)
+        .param("ram_limit", ram_limit);
+    print_result(output, header, &report);
+
+    Ok(())
+}
+
+fn dp(fname: &str, header: bool, time_limit: Option<u32>, ram_limit: Option<f64>, cache_limit: Option<usize>, output: OutputFormat) -> Result<()> {
+    let kill_switch = setup_kill_switch(time_limit, ram_limit)?;
+    stop_on_ram_limit(&kill_switch, ram_limit);
+    let instance = Psp::try_from(File::open(fname)?)?;
+    let instname = instance_name(fname);
+    let start_tm = Instant::now();
+    //
+    let greedy = instance.greedy();
+
+    let mut solver = PureDpBuilder::default();
+    solver.problem(&instance)
+        .var_ordering(LeftToRight)
+        .start_time(start_tm)
+        .kill_switch(kill_switch)
+        .initial_val(Some(greedy.0))
+        .initial_sol(greedy.1);
+    if let Some(limit) = cache_limit {
+        solver.cache_limit(limit);
+    }
+    let outcome = solver.build()?.minimize();
+
+    let mut report = SolverReport::new(instname, "dp", outcome);
+    report.peak_ram_gb = ALLOC.get_peak_gb();
+    report
+        .param("cache_limit", cache_limit)
+        .param("time_limit", time_limit)
+        .param("ram_limit", ram_limit);
+    print_result(output, header, &report);
+
+    Ok(())
+}
+
+fn beam(fname: &str, header: bool, width: usize, column: bool, time_limit: Option<u32>, ram_limit: Option<f64>, output: OutputFormat) -> Result<()> {
+    let kill_switch = setup_kill_switch(time_limit, ram_limit)?;
+    stop_on_ram_limit(&kill_switch, ram_limit);
+    let instance = Psp::try_from(File::open(fname)?)?;
+    let instname = instance_name(fname);
+    let start_tm = Instant::now();
+
+    let solver = BeamSearchBuilder::default()
+        .problem(&instance)
+        .var_ordering(LeftToRight)
+        .node_selection(RandomizedMinLP)
+        .start_time(start_tm)
+        .kill_switch(kill_switch)
+        .width(width)
+        .strategy(if column { BeamStrategy::Column } else { BeamStrategy::Doubling })
+        .build()?;
+    // publish the solutions as they are found
+    let outcome = solver.minimize_with_cond(|value, sol| {
+        eprintln!("{:>10.2} | {:>10} | {}", start_tm.elapsed().as_secs_f32(), value, sol);
+        false
+    });
+
+    let mut report = SolverReport::new(instname, "beam", outcome);
+    report.peak_ram_gb = ALLOC.get_peak_gb();
+    report
+        .param("width", width)
+        .param("strategy", if column { "column" } else { "doubling" })
+        .param("time_limit", time_limit)
+        .param("ram_limit", ram_limit);
+    print_result(output, header, &report);
+
+    Ok(())
+}
+
+#[allow(clippy::too_many_arguments)]
+fn rolling(fname: &str, header: bool, window: usize, step: usize, width: usize, seed: u64, proba: f64, time_limit: Option<u32>, ram_limit: Option<f64>, output: OutputFormat) -> Result<()> {
+    let kill_switch = setup_kill_switch(time_limit, ram_limit)?;
+    stop_on_ram_limit(&kill_switch, ram_limit);
+    let instance = Psp::try_from(File::open(fname)?)?;
+    let instname = instance_name(fname);
+
+    let outcome = RollingHorizon {
+        psp: &instance,
+        window,
+        step,
+        width,
+        seed,
+        proba,
+        time_limit: time_limit.map(|seconds| Duration::from_secs(seconds as u64)),
+        start: Instant::now(),
+        kill_switch,
+    }.minimize();
+
+    let mut report = SolverReport::new(instname, "rolling", outcome);
+    report.peak_ram_gb = ALLOC.get_peak_gb();
+    report.seed = Some(seed);
+    report
+        .param("window", window)
+        .param("step", step)
+        .param("width", width)
+        .param("proba", proba)
+        .param("time_limit", time_limit)
+        .param("ram_limit", ram_limit);
+    print_result(output, header, &report);
+
+    Ok(())
+}
+
 fn setup_kill_switch(time_limit: Option<u32>, ram_limit: Option<f64>) -> Result<Arc<AtomicBool>> {
     let kill_switch = Arc::new(AtomicBool::new(false));
     // ctrl + c   : interrupt program
@@ -156,16 +604,36 @@ fn setup_kill_switch(time_limit: Option<u32>, ram_limit: Option<f64>) -> Result<
         };
     }
     if let Some(gigabytes) = ram_limit {
-        ALLOC.set_limit_gb(gigabytes);
+        ALLOC.set_soft_limit_gb(gigabytes);
     }
 
     Ok(kill_switch)
 }
 
+/// Makes the allocator refuse any allocation beyond the hard ram limit (which
+/// may not be lower than the ram limit)
+fn set_hard_ram_limit(ram_limit: Option<f64>, hard_ram_limit: Option<f64>) -> Result<()> {
+    if let Some(gigabytes) = hard_ram_limit {
+        if let Some(soft) = ram_limit.filter(|soft| *soft > gigabytes) {
+            anyhow::bail!("the hard ram limit ({} GB) is lower than the ram limit ({} GB)", gigabytes, soft);
+        }
+        ALLOC.set_hard_limit_gb(gigabytes);
+    }
+    Ok(())
+}
+
+/// The solvers which cannot narrow their search are simply stopped when the
+/// memory exceeds the ram limit
+fn stop_on_ram_limit(kill_switch: &Arc<AtomicBool>, ram_limit: Option<f64>) {
+    if ram_limit.is_some() {
+        kill_on_soft_limit(&ALLOC, Arc::clone(kill_switch), Duration::from_millis(10));
+    }
+}
+
 fn instance_name(fname: &str) -> &str {
     fname
         .split_terminator(std::path::MAIN_SEPARATOR)
-        .last()
+        .next_back()
         .unwrap_or("-- no name --")
 }
 
@@ -177,18 +645,31 @@ fn print_header() {
     );
 }
 
-fn print_result(instance: &str, ram: f64, outcome: ResolutionOutcome) {
+fn print_result(output: OutputFormat, header: bool, report: &SolverReport<isize>) {
+    match output {
+        OutputFormat::Json  => println!("{}", report.to_json()),
+        OutputFormat::Table => {
+            if header {
+                print_header();
+            }
+            print_row(report)
+        }
+    }
+}
+
+fn print_row(report: &SolverReport<isize>) {
+    let outcome = &report.outcome;
     // instance | method | status | value | ram in gb | time to best | time to proved | solution
     println!(
         "{:>20} | {:>10} | {:>20} | {:>10} | {:>8.2} | {:>10} | {:>10} | {:<80}",
-        instance,
-        "lns",
+        report.instance,
+        report.method,
         outcome.status.to_str(),
         outcome
             .best_value
             .map(|v| format!("{}", v))
             .unwrap_or_else(|| "N.A.".to_string()),
-        ram,
+        report.peak_ram_gb,
         outcome
             .time_to_best
             .map(|d| format!("{:.2}", d.as_secs_f32()))
@@ -199,6 +680,7 @@ fn print_result(instance: &str, ram: f64, outcome: ResolutionOutcome) {
             .unwrap_or_else(|| "N.A.".to_string()),
         outcome
             .best_sol
+            .as_ref()
             .map(|sol| format!("{}", sol))
             .unwrap_or_else(|| "-- no solution --".to_string())
     );
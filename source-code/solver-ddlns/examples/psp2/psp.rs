@@ -1,12 +1,14 @@
 use std::{
     cell::RefCell,
-    fmt::Debug,
+    fmt::{Debug, Display},
     fs::File,
     io::{BufRead, BufReader, Lines, Read},
+    str::FromStr,
 };
 
 use papier_lns::{
-    Decision, Matrix, NodeSelectionHeuristic, Problem, SelectableNode, Solution, Var,
+    Cost, Decision, FreeVariables, Matrix, MostConstrained, NodeSelectionHeuristic, Problem,
+    RolloutPolicy, SelectableNode, SmallestUnionDomain, Solution, StateCodec, StateSize, Var,
     VariableOrdering,
 };
 
@@ -22,6 +24,17 @@ pub struct State {
     // delivered.
     u: Vec<i32>,
 }
+impl State {
+    /// The number of periods which are yet to be scheduled
+    pub fn time(&self) -> usize {
+        self.time
+    }
+}
+impl StateSize for State {
+    fn state_size(&self) -> usize {
+        std::mem::size_of::<Self>() + self.u.capacity() * std::mem::size_of::<i32>()
+    }
+}
 
 #[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
 pub struct LeftToRight;
@@ -29,7 +42,7 @@ impl VariableOrdering for LeftToRight {
     type State = State;
 
     fn next(&self, states: &mut dyn Iterator<Item = &Self::State>) -> Option<Var> {
-        let time = states.next().unwrap().time;
+        let time = states.next()?.time;
         if time > 0 {
             Some(Var::new(time - 1))
         } else {
@@ -38,10 +51,83 @@ impl VariableOrdering for LeftToRight {
     }
 }
 
+/// The periods are scheduled from the last one to the first one: the only
+/// free variable of a state is the period that precedes its time. Hence, the
+/// generic dynamic orderings branch on the same periods as `LeftToRight`; they
+/// merely tell which of its values the layer has in common.
+impl FreeVariables for Psp {
+    type State = State;
+
+    fn for_each_free_var(&self, state: &State, mut f: impl FnMut(Var)) {
+        if state.time > 0 {
+            f(Var::new(state.time - 1))
+        }
+    }
+}
+
+/// The variable orderings that can be selected on the command line
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OrderingKind {
+    LeftToRight,
+    SmallestDomain,
+    MostConstrained,
+}
+impl FromStr for OrderingKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "left-to-right"    => Ok(Self::LeftToRight),
+            "smallest-domain"  => Ok(Self::SmallestDomain),
+            "most-constrained" => Ok(Self::MostConstrained),
+            _ => Err(format!("unknown ordering {} (expected left-to-right, smallest-domain or most-constrained)", s)),
+        }
+    }
+}
+impl Display for OrderingKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::LeftToRight     => write!(f, "left-to-right"),
+            Self::SmallestDomain  => write!(f, "smallest-domain"),
+            Self::MostConstrained => write!(f, "most-constrained"),
+        }
+    }
+}
+
+/// The variable ordering selected on the command line
+#[derive(Debug, Clone, Copy)]
+pub enum PspOrdering<'a> {
+    LeftToRight(LeftToRight),
+    SmallestDomain(SmallestUnionDomain<&'a Psp>),
+    MostConstrained(MostConstrained<&'a Psp>),
+}
+impl<'a> PspOrdering<'a> {
+    pub fn new(kind: OrderingKind, psp: &'a Psp) -> Self {
+        match kind {
+            OrderingKind::LeftToRight     => Self::LeftToRight(LeftToRight),
+            OrderingKind::SmallestDomain  => Self::SmallestDomain(SmallestUnionDomain::new(psp)),
+            OrderingKind::MostConstrained => Self::MostConstrained(MostConstrained::new(psp)),
+        }
+    }
+}
+impl VariableOrdering for PspOrdering<'_> {
+    type State = State;
+
+    fn next(&self, states: &mut dyn Iterator<Item = &State>) -> Option<Var> {
+        match self {
+            Self::LeftToRight(ordering)     => ordering.next(states),
+            Self::SmallestDomain(ordering)  => ordering.next(states),
+            Self::MostConstrained(ordering) => ordering.next(states),
+        }
+    }
+}
+
 #[derive(Clone, Default)]
 pub struct RandomizedMinLP;
 impl NodeSelectionHeuristic for RandomizedMinLP {
-    fn compare<S: papier_lns::NodeSource>(
+    type State = State;
+
+    fn compare<S: papier_lns::NodeSource<State = State>>(
         &self,
         _dd: &S,
         na: &S::Node,
@@ -52,7 +138,7 @@ impl NodeSelectionHeuristic for RandomizedMinLP {
         a.cmp(&b)
     }
 
-    fn is_mandatory<S: papier_lns::NodeSource>(
+    fn is_mandatory<S: papier_lns::NodeSource<State = State>>(
         &self,
         dd: &S,
         node: &S::Node,
@@ -71,11 +157,48 @@ impl NodeSelectionHeuristic for RandomizedMinLP {
     }
 }
 
+/// Stores the states in a compact form: the time and deadlines are encoded on
+/// two bytes and the last item on a single one (there are at most 32 items).
+#[derive(Debug, Clone, Copy)]
+pub struct PspCodec;
+impl PspCodec {
+    pub fn new(psp: &Psp) -> Result<Self, PspError> {
+        if psp.nb_periods > i16::MAX as usize {
+            Err(PspError::TooLarge("nb periods"))
+        } else {
+            Ok(Self)
+        }
+    }
+}
+impl StateCodec for PspCodec {
+    type State = State;
+
+    fn encode(&self, state: &State, into: &mut Vec<u8>) {
+        into.extend_from_slice(&(state.time as u16).to_le_bytes());
+        into.push(state.k as i8 as u8);
+        for deadline in state.u.iter() {
+            into.extend_from_slice(&(*deadline as i16).to_le_bytes());
+        }
+    }
+
+    fn decode(&self, encoded: &[u8]) -> State {
+        let time = u16::from_le_bytes([encoded[0], encoded[1]]) as usize;
+        let k    = encoded[2] as i8 as i32;
+        let u    = encoded[3..]
+            .chunks_exact(2)
+            .map(|x| i16::from_le_bytes([x[0], x[1]]) as i32)
+            .collect();
+        State { time, k, u }
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct Psp {
+    #[allow(dead_code)]
     pub optimum: Option<usize>,
     pub nb_periods: usize,
     pub nb_items: usize,
+    #[allow(dead_code)]
     pub nb_orders: usize,
     pub changeover_cost: Matrix<usize>,
     pub stocking_cost: Vec<usize>,
@@ -90,6 +213,7 @@ pub struct Psp {
 
 impl Problem for Psp {
     type State = State;
+    type Cost = isize;
 
     fn nb_vars(&self) -> usize {
         self.nb_periods
@@ -120,7 +244,13 @@ impl Problem for Psp {
         let mut next = state.clone();
         next.time -= 1;
         next.k = item as i32;
-        next.u[item] = self.prev_demand[(item, state.u[item] as usize)];
+        // an item without pending order may only be produced by an infeasible
+        // solution (e.g. one that is being checked): it stays without order
+        next.u[item] = if state.u[item] < 0 {
+            BOT
+        } else {
+            self.prev_demand[(item, state.u[item] as usize)]
+        };
         next
     }
 
@@ -132,7 +262,7 @@ impl Problem for Psp {
         } else {
             self.changeover_cost[(item, state.k as usize)]
         };
-        let stocking = self.stocking_cost[item] * (state.u[item] as usize - time);
+        let stocking = self.stocking_cost[item] * (state.u[item].max(time as i32) as usize - time);
         (changeover + stocking) as isize
     }
 
@@ -166,6 +296,37 @@ impl Problem for Psp {
             (stock + mst) as isize
         }
     }
+
+    fn state_summary(&self, state: &Self::State) -> String {
+        if state.k == BOT {
+            format!("period {:>4} ; nothing produced yet", state.time)
+        } else {
+            format!("period {:>4} ; next produced item {:>3}", state.time, state.k)
+        }
+    }
+
+    fn violation_reason(&self, _state: &Self::State, decision: Decision) -> String {
+        let time = decision.var.id();
+        let item = decision.val;
+        if item < 0 || item as usize >= self.nb_items {
+            format!("there is no item {}", item)
+        } else {
+            format!("item {} has no pending order due at or after period {}", item, time)
+        }
+    }
+
+    fn decision_details(&self, state: &Self::State, decision: Decision) -> String {
+        let time = decision.var.id();
+        let item = decision.val as usize;
+        let changeover = if state.k == BOT {
+            0
+        } else {
+            self.changeover_cost[(item, state.k as usize)]
+        };
+        let due = state.u[item];
+        format!("order due at {:>4} ; changeover = {:>6} ; stocking = {:>6}",
+            due, changeover, self.stocking_cost[item] * (due.max(time as i32) as usize - time))
+    }
 }
 
 impl Psp {
@@ -291,18 +452,7 @@ impl Psp {
 
         for time in (0..nb_vars).rev() {
             let var = Var::new(time);
-            let mut dec: Option<Decision> = None;
-            self.for_each_in_domain(&state, var, |d| {
-                if let Some(kept) = dec {
-                    if self.stocking_cost[d.val as usize] > self.stocking_cost[kept.val as usize] {
-                        dec = Some(d);
-                    }
-                } else {
-                    dec = Some(d);
-                }
-            });
-
-            if let Some(dec) = dec {
+            if let Some(dec) = self.choose(&state, var) {
                 solution.push(dec);
                 cost = cost.saturating_add(self.transition_cost(&state, dec));
                 state = self.transition(&state, dec);
@@ -313,6 +463,42 @@ impl Psp {
         (cost, Some(Solution::from(solution.iter().copied())))
     }
 }
+/// The greedy policy (schedule the most expensive item to store) can also be
+/// used to complete the partial solutions during the restrictions
+impl RolloutPolicy for Psp {
+    type State = State;
+
+    fn choose(&self, state: &State, var: Var) -> Option<Decision> {
+        most_expensive_to_store(&self.stocking_cost, state, var)
+    }
+}
+/// The same greedy policy, detached from the instance: unlike the instance,
+/// it has no scratch buffers. Hence it can be shared with the mdd.
+#[derive(Debug, Clone)]
+pub struct GreedyRollout {
+    stocking_cost: Vec<usize>,
+}
+impl GreedyRollout {
+    pub fn new(psp: &Psp) -> Self {
+        Self { stocking_cost: psp.stocking_cost.clone() }
+    }
+}
+impl RolloutPolicy for GreedyRollout {
+    type State = State;
+
+    fn choose(&self, state: &State, var: Var) -> Option<Decision> {
+        most_expensive_to_store(&self.stocking_cost, state, var)
+    }
+}
+/// Picks the item of the domain of `var` which is the most expensive to store
+/// (the first one in case of ties). The domain is the same as in `Psp`: the
+/// items having a pending order at or after the period of `var`.
+fn most_expensive_to_store(stocking_cost: &[usize], state: &State, var: Var) -> Option<Decision> {
+    (0..stocking_cost.len())
+        .filter(|item| state.u[*item] >= var.id() as i32)
+        .reduce(|kept, item| if stocking_cost[item] > stocking_cost[kept] { item } else { kept })
+        .map(|item| Decision::new(var, item as isize))
+}
 /*** BELOW THIS LINE IS THE CODE TO PARSE INSTANCE FILES *********************/
 #[derive(Debug, thiserror::Error)]
 pub enum PspError {
@@ -322,6 +508,8 @@ pub enum PspError {
     Missing(&'static str),
     #[error("expected int {0}")]
     ParseInt(#[from] std::num::ParseIntError),
+    #[error("too large for compact states: {0}")]
+    TooLarge(&'static str),
 }
 impl TryFrom<File> for Psp {
     type Error = PspError;
@@ -0,0 +1,288 @@
+//! A rolling horizon decomposition of the PSP. The periods of a long instance
+//! are scheduled window by window (from the last period to the first one, as
+//! in the dp model). Each window is solved by `MddLns` while the periods that
+//! precede it are aggregated into the estimate of the state reached at the
+//! end of the window. Only the first few periods of each window are committed
+//! before the window moves on; the committed decisions are then stitched into
+//! a full solution.
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread::JoinHandle,
+    time::{Duration, Instant},
+};
+
+use papier_lns::{
+    Decision, MddLnsBuilder, Problem, ResolutionOutcome, ResolutionStatus, RolloutPolicy,
+    SimpleMddBuilder, Solution, Var, VariableOrdering,
+};
+use rand::SeedableRng;
+use rand_xoshiro::Xoshiro256Plus;
+
+use crate::psp::{LeftToRight, Psp, RandomizedMinLP, State};
+
+// ----------------------------------------------------------------------------
+// Window subproblem
+// ----------------------------------------------------------------------------
+/// The periods `end .. start.time()` of a psp, starting from the given state.
+/// The variables of a window are numbered from zero (the variable `j` is the
+/// period `end + j`) and the cost of its last decision includes the estimate
+/// of the periods that remain to be scheduled after the window.
+pub struct Window<'a> {
+    psp: &'a Psp,
+    start: State,
+    end: usize,
+}
+impl<'a> Window<'a> {
+    pub fn new(psp: &'a Psp, start: State, end: usize) -> Self {
+        Self { psp, start, end }
+    }
+    fn global(&self, decision: Decision) -> Decision {
+        Decision::new(Var::new(decision.var.id() + self.end), decision.val)
+    }
+    fn local(&self, decision: Decision) -> Decision {
+        Decision::new(Var::new(decision.var.id() - self.end), decision.val)
+    }
+    /// Schedules the window with the greedy policy of the psp
+    pub fn greedy(&self) -> (isize, Option<Solution>) {
+        let mut state     = self.initial_state();
+        let mut cost      = self.initial_value();
+        let mut decisions = vec![];
+        while let Some(var) = WindowOrdering(self.end).next(&mut std::iter::once(&state)) {
+            if let Some(decision) = self.psp.choose(&state, self.global_var(var)) {
+                let decision = self.local(decision);
+                cost  = cost.saturating_add(self.transition_cost(&state, decision));
+                state = self.transition(&state, decision);
+                decisions.push(decision);
+            } else {
+                return (isize::MAX, None);
+            }
+        }
+        (cost, Some(Solution::from(decisions.into_iter())))
+    }
+    fn global_var(&self, var: Var) -> Var {
+        Var::new(var.id() + self.end)
+    }
+}
+impl Problem for Window<'_> {
+    type State = State;
+    type Cost = isize;
+
+    fn nb_vars(&self) -> usize {
+        self.start.time() - self.end
+    }
+    fn initial_state(&self) -> State {
+        self.start.clone()
+    }
+    fn initial_value(&self) -> isize {
+        0
+    }
+    fn for_each_in_domain(&self, state: &State, var: Var, mut f: impl FnMut(Decision)) {
+        self.psp.for_each_in_domain(state, self.global_var(var), |d| f(self.local(d)))
+    }
+    fn transition(&self, state: &State, decision: Decision) -> State {
+        self.psp.transition(state, self.global(decision))
+    }
+    fn transition_cost(&self, state: &State, decision: Decision) -> isize {
+        let decision = self.global(decision);
+        let cost     = self.psp.transition_cost(state, decision);
+        if state.time() == self.end + 1 {
+            // the rest of the horizon is aggregated into its estimate
+            let next = self.psp.transition(state, decision);
+            cost.saturating_add(self.psp.estimate(&next))
+        } else {
+            cost
+        }
+    }
+    fn estimate(&self, state: &State) -> isize {
+        if state.time() == self.end {
+            0
+        } else {
+            self.psp.estimate(state)
+        }
+    }
+}
+
+/// Schedules the periods of a window from the last one to the first one
+#[derive(Debug, Clone, Copy)]
+pub struct WindowOrdering(usize);
+impl VariableOrdering for WindowOrdering {
+    type State = State;
+
+    fn next(&self, states: &mut dyn Iterator<Item = &State>) -> Option<Var> {
+        let time = states.next()?.time();
+        if time > self.0 {
+            Some(Var::new(time - 1 - self.0))
+        } else {
+            None
+        }
+    }
+}
+
+// ----------------------------------------------------------------------------
+// Rolling horizon solver
+// ----------------------------------------------------------------------------
+pub struct RollingHorizon<'a> {
+    pub psp: &'a Psp,
+    /// The number of periods of each window
+    pub window: usize,
+    /// The number of periods committed before the window moves on
+    pub step: usize,
+    /// The parameters of the lns solving each window
+    pub width: usize,
+    pub seed: u64,
+    pub proba: f64,
+    /// The time budget shared by all the windows (each window gets an even
+    /// share of the time that is left when it starts)
+    pub time_limit: Option<Duration>,
+    pub start: Instant,
+    /// Stops the whole resolution. The periods that have not been scheduled
+    /// yet are then completed greedily.
+    pub kill_switch: Arc<AtomicBool>,
+}
+/// The time granted to the lns of the windows whose greedy schedule fails once
+/// the resolution has been stopped. All these windows share that time: the
+/// fallback deadline is set when the first of them starts.
+const FALLBACK_TIME: Duration = Duration::from_secs(1);
+
+/// What the resolution of a window yields
+struct WindowOutcome {
+    solution: Option<Solution>,
+    /// Tells whether the lns improved the greedy schedule of the window
+    improved: bool,
+    /// Tells whether the lns proved its schedule to be optimal
+    closed: bool,
+}
+
+impl RollingHorizon<'_> {
+    /// Schedules the whole horizon. The outcome is only closed when a single
+    /// window spans the horizon and its lns proved its optimality. It is
+    /// improved when the lns improved the greedy schedule of some window.
+    pub fn minimize(&self) -> ResolutionOutcome<isize> {
+        let mut state     = self.psp.initial_state();
+        let mut decisions = vec![];
+        let mut improved  = false;
+        let mut closed    = false;
+        let mut fallback  = None;
+
+        while state.time() > 0 {
+            let end = state.time().saturating_sub(self.window.max(2));
+            // the lns needs at least two variables: the last periods join the
+            // previous window rather than forming a window of their own
+            let end = if end < 2 { 0 } else { end };
+
+            let window = Window::new(self.psp, state.clone(), end);
+            let greedy = window.greedy();
+            let solved = if !self.kill_switch.load(Ordering::Relaxed) {
+                self.solve_window(&window, greedy, self.share(&window), Some(&self.kill_switch))
+            } else if greedy.1.is_some() {
+                WindowOutcome { solution: greedy.1, improved: false, closed: false }
+            } else {
+                // the resolution is stopped but the window still needs a schedule
+                let deadline = *fallback.get_or_insert_with(|| Instant::now() + FALLBACK_TIME);
+                let left     = deadline.saturating_duration_since(Instant::now());
+                self.solve_window(&window, greedy, Some(left), None)
+            };
+            improved |= solved.improved;
+            closed    = solved.closed && end == 0 && decisions.is_empty();
+            let Some(solution) = solved.solution else {
+                return self.outcome(None, improved, false);
+            };
+            // commit the first periods of the window (all of them for the last one)
+            let commit = if end == 0 { window.nb_vars() } else { self.step.clamp(1, window.nb_vars()) };
+            for _ in 0..commit {
+                let var      = Var::new(state.time() - 1 - end);
+                let decision = window.global(Decision::new(var, solution[var]));
+                state = self.psp.transition(&state, decision);
+                decisions.push(decision);
+            }
+        }
+        self.outcome(Some(Solution::from(decisions.into_iter())), improved, closed)
+    }
+
+    /// Solves the window with an lns starting from its greedy schedule. The
+    /// lns stops once the given time is up or as soon as the given kill switch
+    /// turns on. Each window draws its random numbers from its own seed.
+    fn solve_window(&self, window: &Window, greedy: (isize, Option<Solution>), share: Option<Duration>, kill_switch: Option<&Arc<AtomicBool>>) -> WindowOutcome {
+        let (init_val, init_sol) = greedy;
+        let init_val = init_sol.as_ref().map(|_| init_val);
+
+        let stop  = Arc::new(AtomicBool::new(false));
+        let timer = Self::start_timer(Arc::clone(&stop), share, kill_switch.cloned());
+        let seed  = self.seed ^ (window.end as u64).wrapping_mul(0x9e37_79b9_7f4a_7c15);
+
+        let mdd = SimpleMddBuilder::default()
+            .problem(window)
+            .var_ordering(WindowOrdering(window.end))
+            .node_selection(RandomizedMinLP)
+            .rng(Xoshiro256Plus::seed_from_u64(seed))
+            .proba(self.proba)
+            .kill_switch(Arc::clone(&stop))
+            .build()
+            .expect("all the mandatory fields are set");
+        let outcome = MddLnsBuilder::default()
+            .mdd(mdd)
+            .nb_var(window.nb_vars())
+            .width(self.width)
+            .initial_sol(init_sol)
+            .initial_val(init_val)
+            .start(Instant::now())
+            .kill_switch(Arc::clone(&stop))
+            .build()
+            .expect("all the mandatory fields are set")
+            .minimize();
+
+        stop.store(true, Ordering::Relaxed);
+        let _ = timer.join();
+        let (improved, closed) = match outcome.status {
+            ResolutionStatus::Open { improved }   => (improved, false),
+            ResolutionStatus::Closed { improved } => (improved, true),
+        };
+        WindowOutcome { solution: outcome.best_sol, improved, closed }
+    }
+
+    /// The time granted to the given window (if the resolution is time limited)
+    fn share(&self, window: &Window) -> Option<Duration> {
+        self.time_limit.map(|limit| {
+            let left    = limit.saturating_sub(self.start.elapsed());
+            let periods = window.start.time().saturating_sub(self.window);
+            let windows = 1 + periods.div_ceil(self.step.max(1));
+            left / windows as u32
+        })
+    }
+
+    /// Spawns a thread that stops the window once its time is up (or as soon
+    /// as the given kill switch turns on)
+    fn start_timer(stop: Arc<AtomicBool>, share: Option<Duration>, kill_switch: Option<Arc<AtomicBool>>) -> JoinHandle<()> {
+        let started = Instant::now();
+        std::thread::spawn(move || {
+            while !stop.load(Ordering::Relaxed) {
+                let timeout = share.is_some_and(|share| started.elapsed() >= share);
+                let killed  = kill_switch.as_ref().is_some_and(|k| k.load(Ordering::Relaxed));
+                if timeout || killed {
+                    stop.store(true, Ordering::Relaxed);
+                }
+                std::thread::sleep(Duration::from_millis(5));
+            }
+        })
+    }
+
+    fn outcome(&self, solution: Option<Solution>, improved: bool, closed: bool) -> ResolutionOutcome<isize> {
+        let value   = solution.as_ref().map(|sol| self.psp.evaluate(&LeftToRight, sol));
+        let elapsed = self.start.elapsed();
+        let status  = if closed {
+            ResolutionStatus::Closed { improved }
+        } else {
+            ResolutionStatus::Open { improved }
+        };
+        ResolutionOutcome {
+            status,
+            best_value: value,
+            best_sol: solution,
+            time_to_best: value.map(|_| elapsed),
+            time_to_prove: closed.then_some(elapsed),
+        }
+    }
+}
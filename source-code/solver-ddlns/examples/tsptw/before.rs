@@ -27,6 +27,7 @@ impl Before {
         Self{pred}
     }
     /// must x be before y ?
+    #[allow(dead_code)]
     pub fn is_before(&self, x: usize, y: usize) -> bool {
         self.pred[y].contains(x)
     }
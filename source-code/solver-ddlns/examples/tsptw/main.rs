@@ -2,17 +2,18 @@
 use std::{
     alloc::System,
     fs::File,
+    io::{BufReader, BufWriter},
     sync::{atomic::AtomicBool, Arc},
-    time::Instant, num::ParseIntError,
+    time::{Duration, Instant}, num::ParseIntError,
 };
 
 use anyhow::Result;
 use libc::{SIGALRM, SIGINT};
-use papier_lns::{MddLnsBuilder, ResolutionOutcome, SigLimitAllocator, Solution, Problem, SimpleMddBuilder, Var, Decision};
+use papier_lns::{MddLnsBuilder, AStarBuilder, BeamSearchBuilder, BeamStrategy, SanityCheckBuilder, SolverReport, OutputFormat, SigLimitAllocator, Solution, Problem, SimpleMddBuilder, Var, Decision, ByteBudget, kill_on_soft_limit, ReplayWriter, ReplayLog, ReplayOutcome, CompilationStats, ProofRecorder, ProofLog, ResolutionOutcome, ResolutionStatus};
 use rand::SeedableRng;
 use rand_xoshiro::Xoshiro256Plus;
 use structopt::StructOpt;
-use tsptw::{LeftToRight, RandomizedMinLP, Tsptw};
+use tsptw::{LeftToRight, RandomizedMinLP, TourDistance, Tsptw, Value};
 
 #[global_allocator]
 static ALLOC: SigLimitAllocator<System> = SigLimitAllocator::new(System, usize::MAX);
@@ -40,12 +41,136 @@ pub enum Args {
         /// optional memory limit in gigabytes
         #[structopt(short, long)]
         ram_limit: Option<f64>,
+        /// optional hard memory limit in gigabytes: the run aborts with an
+        /// allocation error rather than exceeding it (beyond the ram limit,
+        /// the diagrams are merely narrowed)
+        #[structopt(long)]
+        hard_ram_limit: Option<f64>,
         /// optional time limit in seconds
         #[structopt(short, long)]
         time_limit: Option<u32>,
         /// optional initial solution to kickstart the solver
         #[structopt(short, long)]
-        solution: Option<String>
+        solution: Option<String>,
+        /// optional number of clusters. When it is set, the restriction keeps
+        /// the best nodes of each cluster so as to preserve some diversity
+        #[structopt(short, long)]
+        clusters: Option<usize>,
+        /// discard the states from which some city can no longer be reached
+        /// in time
+        #[structopt(long)]
+        propagate: bool,
+        /// optional memory budget (in megabytes) of each layer. When it is
+        /// set, the width of a layer depends on the size of its states (and
+        /// `width` merely caps the number of nodes)
+        #[structopt(long)]
+        layer_budget: Option<f64>,
+        /// optional memory budget (in megabytes) of the whole diagram
+        #[structopt(long, conflicts_with = "layer-budget")]
+        diagram_budget: Option<f64>,
+        /// the output format: a table row or a json report
+        #[structopt(long, default_value = "table")]
+        output: OutputFormat,
+        /// optional file where the iterations of the lns are logged (they
+        /// can then be replayed one by one with the replay command)
+        #[structopt(long)]
+        replay_log: Option<String>,
+        /// optional file where the certificate of the optimum is written
+        /// (when optimality is proved). It is checked by `check-proof`.
+        #[structopt(long)]
+        proof: Option<String>,
+    },
+    /// Check the certificate of an optimum (written by solve --proof)
+    CheckProof {
+        #[structopt(short, long)]
+        fname: String,
+        /// Path to the certificate
+        #[structopt(short, long)]
+        proof: String,
+        /// the certificate was recorded with the propagator of the tsptw
+        /// (solve --propagate)
+        #[structopt(long)]
+        propagate: bool,
+    },
+    /// Replay one iteration of an lns run (logged with solve --replay-log)
+    Replay {
+        #[structopt(short, long)]
+        fname: String,
+        /// Path to the replay log
+        #[structopt(short, long)]
+        log: String,
+        /// The iteration to replay
+        #[structopt(short, long)]
+        iteration: usize,
+        /// optional file where the diagram of the iteration is dumped (in
+        /// the graphviz format)
+        #[structopt(long)]
+        dot: Option<String>,
+    },
+    /// Solve an instance with a best first search (A*)
+    Astar {
+        #[structopt(short, long)]
+        fname: String,
+        /// Output the header
+        #[structopt(short = "H", long)]
+        header: bool,
+        /// the weight of the estimate (a weight larger than one quickly finds
+        /// solutions and then keeps improving them until optimality is proved)
+        #[structopt(short, long, default_value = "1.0")]
+        weight: f64,
+        /// optional memory limit in gigabytes
+        #[structopt(short, long)]
+        ram_limit: Option<f64>,
+        /// optional time limit in seconds
+        #[structopt(short, long)]
+        time_limit: Option<u32>,
+        /// optional initial solution to kickstart the solver
+        #[structopt(short, long)]
+        solution: Option<String>,
+        /// discard the states from which some city can no longer be reached
+        /// in time
+        #[structopt(long)]
+        propagate: bool,
+        /// the output format: a table row or a json report
+        #[structopt(long, default_value = "table")]
+        output: OutputFormat,
+    },
+    /// Solve an instance with an anytime beam search (no lns). This is also a
+    /// way to find initial solutions for the lns.
+    Beam {
+        #[structopt(short, long)]
+        fname: String,
+        /// Output the header
+        #[structopt(short = "H", long)]
+        header: bool,
+        /// the width of the (first) beam
+        #[structopt(short, long, default_value = "1")]
+        width: usize,
+        /// perform an anytime column search instead of doubling the width
+        /// of the beam
+        #[structopt(short, long)]
+        column: bool,
+        /// optional memory limit in gigabytes
+        #[structopt(short, long)]
+        ram_limit: Option<f64>,
+        /// optional time limit in seconds
+        #[structopt(short, long)]
+        time_limit: Option<u32>,
+        /// discard the states from which some city can no longer be reached
+        /// in time
+        #[structopt(long)]
+        propagate: bool,
+        /// the output format: a table row or a json report
+        #[structopt(long, default_value = "table")]
+        output: OutputFormat,
+    },
+    /// Run the sanity checks of the model on a (small) instance
+    Sanity {
+        #[structopt(short, long)]
+        fname: String,
+        /// the maximum number of states to explore
+        #[structopt(short, long, default_value = "1000000")]
+        max_states: usize,
     },
     Check {
         #[structopt(short, long)]
@@ -64,8 +189,16 @@ pub enum Args {
 fn main() -> Result<()> {
     let args = Args::from_args();
     match args {
-        Args::Solve{fname, header, width, seed, proba, ram_limit, time_limit, solution} => 
-            solve(fname, header, width, seed, proba, ram_limit, time_limit, solution),
+        Args::Solve{fname, header, width, seed, proba, ram_limit, hard_ram_limit, time_limit, solution, clusters, propagate, layer_budget, diagram_budget, output, replay_log, proof} => 
+            solve(fname, header, width, seed, proba, ram_limit, hard_ram_limit, time_limit, solution, clusters, propagate, layer_budget, diagram_budget, output, replay_log, proof),
+        Args::CheckProof{fname, proof, propagate} => check_proof(fname, proof, propagate),
+        Args::Replay{fname, log, iteration, dot} => replay(fname, log, iteration, dot),
+        Args::Astar{fname, header, weight, ram_limit, time_limit, solution, propagate, output} =>
+            astar(fname, header, weight, ram_limit, time_limit, solution, propagate, output),
+        Args::Beam{fname, header, width, column, ram_limit, time_limit, propagate, output} =>
+            beam(fname, header, width, column, ram_limit, time_limit, propagate, output),
+        Args::Sanity{fname, max_states} =>
+            sanity(fname, max_states),
         Args::Check{fname, solution} => 
             check(fname, solution),
         Args::Detail{fname, solution} => 
@@ -77,7 +210,24 @@ fn detail(fname: String, solution: String) -> Result<()> {
     let inst     = Tsptw::try_from(File::open(&fname)?)?;
     let solution = try_solution_from_std_tour(&solution)?;
     let var_ord  = LeftToRight(inst.n_cities);
-    inst.details(&var_ord, &solution);
+    for record in inst.details(&var_ord, &solution) {
+        println!("{}", record);
+    }
+    Ok(())
+}
+
+fn sanity(fname: String, max_states: usize) -> Result<()> {
+    let inst   = Tsptw::try_from(File::open(&fname)?)?;
+    let report = SanityCheckBuilder::default()
+        .problem(&inst)
+        .var_ordering(LeftToRight(inst.n_cities))
+        .max_states(max_states)
+        .build()?
+        .run();
+    println!("states {} -- transitions {} -- optimum {:?}", report.nb_states, report.nb_transitions, report.optimum);
+    for violation in report.violations.iter() {
+        println!("violation: {}", violation);
+    }
     Ok(())
 }
 
@@ -85,15 +235,17 @@ fn check(fname: String, solution: String) -> Result<()> {
     let inst     = Tsptw::try_from(File::open(&fname)?)?;
     let solution = try_solution_from_std_tour(&solution)?;
     let var_ord  = LeftToRight(inst.n_cities);
-    let cost     = inst.evaluate(&var_ord, &solution);
-    println!("cost: {:.4}", cost as f64 / 10000.0);
-    
-    inst.check(&var_ord, &solution);
+    let report   = inst.check(&var_ord, &solution);
+    println!("cost: {}", report.cost);
+    for violation in report.violations.iter() {
+        println!("{}", violation);
+    }
 
     Ok(())
 }
 
-fn solve(fname: String, header: bool, width: usize, seed: u64, proba: f64, ram_limit: Option<f64>, time_limit: Option<u32>, solution: Option<String>) -> Result<()> {
+#[allow(clippy::too_many_arguments)]
+fn solve(fname: String, header: bool, width: usize, seed: u64, proba: f64, ram_limit: Option<f64>, hard_ram_limit: Option<f64>, time_limit: Option<u32>, solution: Option<String>, clusters: Option<usize>, propagate: bool, layer_budget: Option<f64>, diagram_budget: Option<f64>, output: OutputFormat, replay_log: Option<String>, proof: Option<String>) -> Result<()> {
     let kill_switch = Arc::new(AtomicBool::new(false));
     // ctrl + c   : interrupt program
     signal_hook::flag::register(SIGINT, Arc::clone(&kill_switch))?;
@@ -109,23 +261,26 @@ fn solve(fname: String, header: bool, width: usize, seed: u64, proba: f64, ram_l
         };
     }
     if let Some(gigabytes) = ram_limit {
-        ALLOC.set_limit_gb(gigabytes);
+        ALLOC.set_soft_limit_gb(gigabytes);
     }
+    set_hard_ram_limit(ram_limit, hard_ram_limit)?;
 
     let start_tm = Instant::now();
     let instname = instance_name(&fname);
     let init_sol = solution.map(|s| try_solution_from_std_tour(&s).expect("Cannot parse solution"));
     let init_val = init_sol.as_ref().map(|s| inst.evaluate(&LeftToRight(n), s));
 
+    let recorder = proof.as_ref().map(|_| Arc::new(ProofRecorder::new()));
+
     // there is no good method to find an initial solution with this problem
-    let mdd = SimpleMddBuilder::default()
-        .problem(&inst)
-        .var_ordering(LeftToRight(n))
-        .node_selection(RandomizedMinLP::new(&inst))
-        .rng(Xoshiro256Plus::seed_from_u64(seed))
-        .proba(proba)
+    let mut mdd = mdd_builder(&inst, proba, clusters, propagate, layer_budget, diagram_budget);
+    mdd.rng(Xoshiro256Plus::seed_from_u64(seed))
         .kill_switch(Arc::clone(&kill_switch))
-        .build()?;
+        .memory(Arc::new(&ALLOC));
+    if let Some(recorder) = recorder.as_ref() {
+        mdd.proof(Arc::clone(recorder));
+    }
+    let mdd = mdd.build()?;
     
     let mut solver = MddLnsBuilder::default()
         .mdd(mdd)
@@ -135,24 +290,242 @@ fn solve(fname: String, header: bool, width: usize, seed: u64, proba: f64, ram_l
         .initial_val(init_val)
         .start(start_tm)
         .kill_switch(kill_switch)
-        .build()?;
+        .memory(Arc::new(&ALLOC));
+    if let Some(fname) = replay_log {
+        let log = ReplayWriter::new(BufWriter::new(File::create(fname)?))
+            .param("proba", proba)
+            .param("clusters", clusters)
+            .param("propagate", propagate)
+            .param("layer_budget", layer_budget)
+            .param("diagram_budget", diagram_budget);
+        solver = solver.replay(Arc::new(log));
+    }
     //
     //let outcome = solver.minimize_with_cond(|o| {println!("{}", o); false});
-    let outcome = solver.minimize();
+    let outcome = solver.build()?.minimize();
+    if let (Some(fname), Some(recorder)) = (proof, recorder) {
+        write_certificate(&fname, &recorder, &outcome)?;
+    }
 
     // ////////////////////////////////////////////////////////////////////////
     // Print the output
     // ////////////////////////////////////////////////////////////////////////
-    if header {
-        print_header();
+    let mut report = SolverReport::new(&instname, "lns", outcome);
+    report.peak_ram_gb = ALLOC.get_peak_gb();
+    report.seed = Some(seed);
+    report
+        .param("width", width)
+        .param("proba", proba)
+        .param("clusters", clusters)
+        .param("propagate", propagate)
+        .param("layer_budget", layer_budget)
+        .param("diagram_budget", diagram_budget)
+        .param("time_limit", time_limit)
+        .param("ram_limit", ram_limit)
+        .param("hard_ram_limit", hard_ram_limit);
+    print_result(output, header, &report);
+
+    Ok(())
+}
+
+fn write_certificate(fname: &str, recorder: &ProofRecorder, outcome: &ResolutionOutcome<Value>) -> Result<()> {
+    match (outcome.status, outcome.best_value, outcome.best_sol.as_ref()) {
+        (ResolutionStatus::Closed { .. }, Some(value), Some(solution)) => {
+            let certificate = recorder.certificate(value, solution.clone());
+            certificate.write_to(BufWriter::new(File::create(fname)?))?;
+        }
+        _ => eprintln!("no certificate: optimality has not been proved"),
+    }
+    Ok(())
+}
+
+fn check_proof(fname: String, proof: String, propagate: bool) -> Result<()> {
+    let inst       = Tsptw::try_from(File::open(&fname)?)?;
+    let log        = ProofLog::<Value>::read_from(BufReader::new(File::open(&proof)?))?;
+    let propagator = propagate.then(|| inst.propagator());
+    match papier_lns::check_proof(&inst, &LeftToRight(inst.n_cities), propagator.as_ref(), &log) {
+        Ok(summary) => println!("valid -- optimum {} -- layers {} -- nodes {} -- merges {} -- prunes {} -- skips {} -- discards {}",
+            summary.optimum, summary.nb_layers, summary.nb_nodes, summary.nb_merges,
+            summary.nb_prunes, summary.nb_skips, summary.nb_discards),
+        Err(error)  => {
+            println!("invalid -- {}", error);
+            std::process::exit(1);
+        }
+    }
+    Ok(())
+}
+
+/// Configures the mdd used by the lns (everything but its rng and its kill
+/// switch)
+fn mdd_builder(inst: &Tsptw, proba: f64, clusters: Option<usize>, propagate: bool, layer_budget: Option<f64>, diagram_budget: Option<f64>) -> SimpleMddBuilder<&Tsptw, LeftToRight, RandomizedMinLP<'_>> {
+    let mut mdd = SimpleMddBuilder::default();
+    mdd.problem(inst)
+        .var_ordering(LeftToRight(inst.n_cities))
+        .node_selection(RandomizedMinLP::new(inst))
+        .proba(proba);
+    if let Some(clusters) = clusters {
+        mdd.diversity(Arc::new(TourDistance::new(inst)))
+            .nb_clusters(clusters);
+    }
+    if propagate {
+        mdd.propagator(Arc::new(inst.propagator()));
+    }
+    if let Some(megabytes) = layer_budget {
+        mdd.budget(ByteBudget::per_layer_mb(megabytes));
+    }
+    if let Some(megabytes) = diagram_budget {
+        mdd.budget(ByteBudget::per_diagram_mb(megabytes));
+    }
+    mdd
+}
+
+fn replay(fname: String, log: String, iteration: usize, dot: Option<String>) -> Result<()> {
+    let inst = Tsptw::try_from(File::open(&fname)?)?;
+    let log  = ReplayLog::<Value>::read_from(BufReader::new(File::open(&log)?))?;
+
+    let mut mdd = mdd_builder(&inst,
+            log.param("proba")?.unwrap_or(0.1),
+            log.param("clusters")?.flatten(),
+            log.param("propagate")?.unwrap_or(false),
+            log.param("layer_budget")?.flatten(),
+            log.param("diagram_budget")?.flatten())
+        .kill_switch(Arc::new(AtomicBool::new(false)))
+        .build()?;
+    let outcome = papier_lns::replay(&mut mdd, &log, iteration)?;
+
+    print_replay(&outcome, &mdd.stats());
+    if let Some(dot) = dot {
+        mdd.write_dot(BufWriter::new(File::create(dot)?))?;
+    }
+    Ok(())
+}
+
+#[allow(clippy::too_many_arguments)]
+fn astar(fname: String, header: bool, weight: f64, ram_limit: Option<f64>, time_limit: Option<u32>, solution: Option<String>, propagate: bool, output: OutputFormat) -> Result<()> {
+    let kill_switch = Arc::new(AtomicBool::new(false));
+    // ctrl + c   : interrupt program
+    signal_hook::flag::register(SIGINT, Arc::clone(&kill_switch))?;
+    // sigalrm for the timeout
+    signal_hook::flag::register(SIGALRM, Arc::clone(&kill_switch))?;
+
+    let inst = Tsptw::try_from(File::open(&fname)?)?;
+    let n = inst.n_cities;
+
+    if let Some(seconds) = time_limit {
+        unsafe {
+            libc::alarm(seconds);
+        };
+    }
+    if let Some(gigabytes) = ram_limit {
+        ALLOC.set_soft_limit_gb(gigabytes);
+    }
+    stop_on_ram_limit(&kill_switch, ram_limit);
+
+    let start_tm = Instant::now();
+    let instname = instance_name(&fname);
+    let init_sol = solution.map(|s| try_solution_from_std_tour(&s).expect("Cannot parse solution"));
+    let init_val = init_sol.as_ref().map(|s| inst.evaluate(&LeftToRight(n), s));
+
+    let mut solver = AStarBuilder::default();
+    solver.problem(&inst)
+        .var_ordering(LeftToRight(n))
+        .start_time(start_tm)
+        .kill_switch(kill_switch)
+        .weight(weight)
+        .initial_val(init_val)
+        .initial_sol(init_sol);
+    if propagate {
+        solver.propagator(Arc::new(inst.propagator()));
+    }
+    let outcome = solver.build()?.minimize();
+
+    let mut report = SolverReport::new(&instname, "astar", outcome);
+    report.peak_ram_gb = ALLOC.get_peak_gb();
+    report
+        .param("weight", weight)
+        .param("propagate", propagate)
+        .param("time_limit", time_limit)
+        .param("ram_limit", ram_limit);
+    print_result(output, header, &report);
+
+    Ok(())
+}
+
+#[allow(clippy::too_many_arguments)]
+fn beam(fname: String, header: bool, width: usize, column: bool, ram_limit: Option<f64>, time_limit: Option<u32>, propagate: bool, output: OutputFormat) -> Result<()> {
+    let kill_switch = Arc::new(AtomicBool::new(false));
+    // ctrl + c   : interrupt program
+    signal_hook::flag::register(SIGINT, Arc::clone(&kill_switch))?;
+    // sigalrm for the timeout
+    signal_hook::flag::register(SIGALRM, Arc::clone(&kill_switch))?;
+
+    let inst = Tsptw::try_from(File::open(&fname)?)?;
+    let n = inst.n_cities;
+
+    if let Some(seconds) = time_limit {
+        unsafe {
+            libc::alarm(seconds);
+        };
+    }
+    if let Some(gigabytes) = ram_limit {
+        ALLOC.set_soft_limit_gb(gigabytes);
     }
-    let ram = ALLOC.get_peak_gb();
-    print_result(&instname, ram, outcome);
+    stop_on_ram_limit(&kill_switch, ram_limit);
 
+    let start_tm = Instant::now();
+    let instname = instance_name(&fname);
+
+    let mut solver = BeamSearchBuilder::default();
+    solver.problem(&inst)
+        .var_ordering(LeftToRight(n))
+        .node_selection(RandomizedMinLP::new(&inst))
+        .start_time(start_tm)
+        .kill_switch(kill_switch)
+        .width(width)
+        .strategy(if column { BeamStrategy::Column } else { BeamStrategy::Doubling });
+    if propagate {
+        solver.propagator(Arc::new(inst.propagator()));
+    }
+    // publish the solutions as they are found
+    let outcome = solver.build()?.minimize_with_cond(|value, sol| {
+        eprintln!("{:>10.2} | {:>10} | {}", start_tm.elapsed().as_secs_f32(), value, solution_as_std_tour(sol));
+        false
+    });
+
+    let mut report = SolverReport::new(&instname, "beam", outcome);
+    report.peak_ram_gb = ALLOC.get_peak_gb();
+    report
+        .param("width", width)
+        .param("strategy", if column { "column" } else { "doubling" })
+        .param("propagate", propagate)
+        .param("time_limit", time_limit)
+        .param("ram_limit", ram_limit);
+    print_result(output, header, &report);
+
+    Ok(())
+}
+
+/// Makes the allocator refuse any allocation beyond the hard ram limit (which
+/// may not be lower than the ram limit)
+fn set_hard_ram_limit(ram_limit: Option<f64>, hard_ram_limit: Option<f64>) -> Result<()> {
+    if let Some(gigabytes) = hard_ram_limit {
+        if let Some(soft) = ram_limit.filter(|soft| *soft > gigabytes) {
+            anyhow::bail!("the hard ram limit ({} GB) is lower than the ram limit ({} GB)", gigabytes, soft);
+        }
+        ALLOC.set_hard_limit_gb(gigabytes);
+    }
     Ok(())
 }
 
 
+/// The solvers which cannot narrow their search are simply stopped when the
+/// memory exceeds the ram limit
+fn stop_on_ram_limit(kill_switch: &Arc<AtomicBool>, ram_limit: Option<f64>) {
+    if ram_limit.is_some() {
+        kill_on_soft_limit(&ALLOC, Arc::clone(kill_switch), Duration::from_millis(10));
+    }
+}
+
 fn instance_name(fname: &str) -> String {
     let it = fname
         .split_terminator(std::path::MAIN_SEPARATOR)
@@ -181,18 +554,31 @@ fn print_header() {
     );
 }
 
-fn print_result(instance: &str, ram: f64, outcome: ResolutionOutcome) {
+fn print_result(output: OutputFormat, header: bool, report: &SolverReport<Value>) {
+    match output {
+        OutputFormat::Json  => println!("{}", report.to_json()),
+        OutputFormat::Table => {
+            if header {
+                print_header();
+            }
+            print_row(report)
+        }
+    }
+}
+
+fn print_row(report: &SolverReport<Value>) {
+    let outcome = &report.outcome;
     // instance | method | status | value | ram in gb | time to best | time to proved | solution
     println!(
         "{:>20} | {:>10} | {:>15} | {:>10} | {:>8.2} | {:>10} | {:>10} | {:<80}",
-        instance,
-        "lns",
+        report.instance,
+        report.method,
         outcome.status.to_str(),
         outcome
             .best_value
-            .map(|v| format!("{:>10.2}", v as f32 / 10000.0))
+            .map(|v| format!("{:>10}", v))
             .unwrap_or_else(|| "N.A.".to_string()),
-        ram,
+        report.peak_ram_gb,
         outcome
             .time_to_best
             .map(|d| format!("{:.2}", d.as_secs_f32()))
@@ -203,11 +589,24 @@ fn print_result(instance: &str, ram: f64, outcome: ResolutionOutcome) {
             .unwrap_or_else(|| "N.A.".to_string()),
         outcome
             .best_sol
-            .map(|sol| solution_as_std_tour(&sol))
+            .as_ref()
+            .map(solution_as_std_tour)
             .unwrap_or_else(|| "-- no solution --".to_string())
     );
 }
 
+fn print_replay(outcome: &ReplayOutcome<Value>, stats: &CompilationStats) {
+    let entry = &outcome.entry;
+    let show  = |v: Option<Value>| v.map(|v| v.to_string()).unwrap_or_else(|| "N.A.".to_string());
+    println!("iteration {} -- depth {} -- width {} -- incumbent {}",
+        entry.iteration, entry.start_depth, entry.width, show(entry.incumbent));
+    println!("logged   value {} -- exact {}", show(entry.value), entry.exact);
+    println!("replayed value {} -- exact {} -- {}", show(outcome.value), outcome.exact,
+        if outcome.is_faithful() { "faithful" } else { "DIVERGED" });
+    println!("nodes {} -- dropped {} -- layers {} -- widths {:?}",
+        stats.nb_nodes, stats.dropped, stats.widths.len(), stats.widths);
+}
+
 fn solution_as_std_tour(sol: &Solution) -> String {
     let mut out = String::new();
     for d in sol.iter() {
@@ -1,11 +1,12 @@
 use std::{
     fs::File,
     io::{BufRead, BufReader, Lines, Read},
-    num::{ParseFloatError, ParseIntError}, intrinsics::transmute,
+    num::ParseIntError,
 };
 
 use papier_lns::{
-    Decision, Matrix, NodeSelectionHeuristic, Problem, SelectableNode, Var, VariableOrdering,
+    Cost, DeadEnd, Decision, Fixed, Matrix, NodeSelectionHeuristic, ParseFixedError, Problem,
+    Propagator, SelectableNode, StateDistance, StateSize, Var, VariableOrdering,
 };
 
 use crate::{BitSet256, before::Before};
@@ -17,17 +18,42 @@ pub enum TsptwError {
     #[error("n_cities is not a valid number {0}")]
     NbCities(ParseIntError),
     #[error("matrix coefficient ({0},{1}) = {2}")]
-    MatrixCoeff(usize, usize, ParseFloatError),
+    MatrixCoeff(usize, usize, TicksError),
     #[error("start of time window {0}")]
-    TwStart(ParseFloatError),
+    TwStart(TicksError),
     #[error("stop of time window {0}")]
-    TwStop(ParseFloatError),
+    TwStop(TicksError),
     #[error("no tw start (line: {0})")]
     NoTwStart(usize),
     #[error("no tw stop (line: {0})")]
     NoTwStop(usize),
 }
 
+/// The instances give the distances and time windows with (at most) five
+/// decimals. Internally, these are integer numbers of 10^-5 units (ticks).
+pub type Value = Fixed<5>;
+
+/// Converts a number of ticks to the value it stands for
+pub fn value(ticks: usize) -> Value {
+    Value::from_units(ticks as i64)
+}
+
+/// A distance or a time of the instance file is not a valid number of ticks
+#[derive(Debug, thiserror::Error)]
+pub enum TicksError {
+    #[error("{0}")]
+    Parse(#[from] ParseFixedError),
+    #[error("{0} is negative")]
+    Negative(String),
+}
+
+/// Parses a decimal number of the instance file as a number of ticks
+fn ticks(text: &str) -> Result<usize, TicksError> {
+    // negative data makes no sense in a tsptw
+    let units = text.parse::<Value>()?.units();
+    usize::try_from(units).map_err(|_| TicksError::Negative(text.to_string()))
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct TimeWindow {
     pub start: usize,
@@ -54,9 +80,12 @@ pub struct State {
     pub current: usize,
     pub visit: BitSet256,
 }
+/// The states of the tsptw own no heap data
+impl StateSize for State {}
 
 impl Problem for Tsptw {
     type State = State;
+    type Cost = Value;
 
     fn nb_vars(&self) -> usize {
         self.n_cities
@@ -75,8 +104,8 @@ impl Problem for Tsptw {
         }
     }
 
-    fn initial_value(&self) -> isize {
-        0
+    fn initial_value(&self) -> Value {
+        Value::ZERO
     }
 
     fn for_each_in_domain(&self, state: &Self::State, var: Var, mut f: impl FnMut(Decision)) {
@@ -110,14 +139,14 @@ impl Problem for Tsptw {
         }
     }
 
-    fn transition_cost(&self, state: &Self::State, decision: Decision) -> isize {
+    fn transition_cost(&self, state: &Self::State, decision: Decision) -> Value {
         let destination = decision.val as usize;
-        self.distance[(state.current, destination)] as isize
+        value(self.distance[(state.current, destination)])
     }
 
-    fn estimate(&self, state: &Self::State) -> isize {
+    fn estimate(&self, state: &Self::State) -> Value {
         if state.visit == BitSet256::singleton(DEPOT) {
-            self.distance[(state.current, DEPOT)] as isize
+            value(self.distance[(state.current, DEPOT)])
         } else {
             // 3 steps in this estimate: 
             // find the distance to closest neighbor.
@@ -126,7 +155,7 @@ impl Problem for Tsptw {
             // nodes to the depot
             // 
             // at each time, we can detect infeasibility and hence return a
-            // prohibitive (= + inf, = isize::MAX) estimate for this state; 
+            // prohibitive (= + inf, = Value::MAX) estimate for this state; 
             // thereby meaning the state should not be explored
 
             let mut cities = state.visit;
@@ -190,27 +219,46 @@ impl Problem for Tsptw {
                 }
             }
 
-            min_dist_1.saturating_add(mst).saturating_add(min_dist_3) as isize
+            value(min_dist_1.saturating_add(mst).saturating_add(min_dist_3))
         }
     }
 
-    fn on_violation(&self, state: &Self::State, decision: Decision) {
+    fn state_summary(&self, state: &Self::State) -> String {
+        format!("at city {:>3} ; time = {:>9}", state.current, value(state.time))
+    }
+
+    fn violation_reason(&self, state: &Self::State, decision: Decision) -> String {
+        if !self.exists(decision.val) {
+            return format!("city {} does not exist", decision.val);
+        }
         let src  = state.current;
         let dst  = decision.val as usize;
         let time = state.time;
         let tw   = self.time_window[dst];
         let dist = self.distance[(src, dst)];
+        let arr  = tw.start.max(time + dist);
 
-        println!("violation x{:<4} <-- {:>3} || dept time = {:>9.4} ; dist = {:>9.4} ; arrival = {:>9.4} ; tw = [{:>9.4} - {:>9.4}]", 
-            decision.var.id(), decision.val,
-            time as f64 / 10000.0,
-            dist as f64 / 10000.0,
-            tw.start.max(time + dist) as f64 / 10000.0,
-            tw.start as f64 / 10000.0, tw.stop as f64 / 10000.0,
-        );
+        let mut pending = state.visit;
+        pending.remove(DEPOT);
+        pending.remove(dst);
+        if !state.visit.contains(dst) {
+            format!("city {} has already been visited", dst)
+        } else if dst == DEPOT && !pending.is_empty() {
+            "the tour goes back to the depot before visiting all cities".to_string()
+        } else if arr > tw.stop {
+            format!("arrival = {:>9} is after the end of the time window [{:>9} - {:>9}]",
+                value(arr), value(tw.start), value(tw.stop))
+        } else if self.before.any_before(pending, dst) {
+            format!("some pending city must be visited before {}", dst)
+        } else {
+            format!("city {} cannot be visited from this state", dst)
+        }
     }
 
-    fn decision_details(&self, state: &Self::State, decision: Decision) {
+    fn decision_details(&self, state: &Self::State, decision: Decision) -> String {
+        if !self.exists(decision.val) {
+            return format!("city {} does not exist", decision.val);
+        }
         let src  = state.current;
         let dst  = decision.val as usize;
         let time = state.time;
@@ -218,21 +266,33 @@ impl Problem for Tsptw {
         let dist = self.distance[(src, dst)];
         let arr  = tw.start.max(time + dist);
 
-        print!("arrival = {:>9.4} ; tw = [{:>9.4} - {:>9.4}]; depart = {:>9.4} ; dist = {:>9.4} ", 
-            arr as f64 / 10000.0,
-            tw.start as f64 / 10000.0, tw.stop as f64 / 10000.0,
-            time as f64 / 10000.0,
-            dist as f64 / 10000.0,
-        );
-        if arr > tw.stop {
-            println!(" !! violation");
-        } else {
-            println!();
-        }
+        format!("arrival = {:>9} ; tw = [{:>9} - {:>9}]; depart = {:>9} ; dist = {:>9}",
+            value(arr),
+            value(tw.start), value(tw.stop),
+            value(time),
+            value(dist),
+        )
     }
 }
 
 impl Tsptw {
+    /// The state is a dead end as soon as one of the cities that remain to be
+    /// visited can no longer be reached before its time window closes.
+    pub fn propagator(&self) -> Propagator<State> {
+        let distance    = self.distance.clone();
+        let time_window = self.time_window.clone();
+        Propagator::new().with(DeadEnd::new(move |state: &State| {
+            state.visit.iter()
+                .skip(1) // skip depot
+                .any(|city| state.time + distance[(state.current, city)] > time_window[city].stop)
+        }))
+    }
+
+    /// Tells whether a decision value designates one of the cities
+    fn exists(&self, city: isize) -> bool {
+        usize::try_from(city).is_ok_and(|city| city < self.n_cities)
+    }
+
     fn can_visit(&self, state: &State, next: usize) -> bool {
         let mut cities = state.visit;
         cities.remove(DEPOT);
@@ -252,7 +312,7 @@ impl VariableOrdering for LeftToRight {
     type State = State;
 
     fn next(&self, states: &mut dyn Iterator<Item = &Self::State>) -> Option<Var> {
-        let to_visit = states.next().unwrap().visit.len();
+        let to_visit = states.next()?.visit.len();
         let varid = self.0 - to_visit;
         if varid < self.0 {
             Some(Var::new(varid))
@@ -262,6 +322,31 @@ impl VariableOrdering for LeftToRight {
     }
 }
 
+/// Two states are far apart when they have not visited the same cities, when
+/// they are not in the same city, or when they are there at different times.
+#[derive(Clone, Copy)]
+pub struct TourDistance {
+    horizon: f64,
+}
+impl TourDistance {
+    pub fn new(inst: &Tsptw) -> Self {
+        let horizon = inst.time_window[DEPOT].stop.max(1) as f64;
+        Self { horizon }
+    }
+}
+impl StateDistance for TourDistance {
+    type State = State;
+
+    fn distance(&self, a: &State, b: &State) -> f64 {
+        let mut visit = a.visit;
+        visit.diff(&b.visit);
+        let visit = visit.len() as f64;
+        let city  = if a.current == b.current { 0.0 } else { 1.0 };
+        let time  = (a.time as f64 - b.time as f64).abs() / self.horizon;
+        visit + city + time
+    }
+}
+
 #[derive(Clone, Copy)]
 pub struct RandomizedMinLP<'a> {
     inst: &'a Tsptw
@@ -287,7 +372,9 @@ impl <'a> RandomizedMinLP<'a> {
     }
 }
 impl NodeSelectionHeuristic for RandomizedMinLP<'_> {
-    fn compare<S: papier_lns::NodeSource>(
+    type State = State;
+
+    fn compare<S: papier_lns::NodeSource<State = State>>(
         &self,
         _dd: &S,
         na: &S::Node,
@@ -304,36 +391,34 @@ impl NodeSelectionHeuristic for RandomizedMinLP<'_> {
         ta.cmp(&tb).then(va.cmp(&vb))
         */
         /**/
-        unsafe {
-            let sa : &State = transmute(na.state());
-            let sb : &State = transmute(nb.state());
-
-            let ca = sa.current;
-            let cb = sb.current;
-            let twa= self.inst.time_window[ca];
-            let twb= self.inst.time_window[cb];
-
-            let oa     = self.total_openness(sa);
-            let ob     = self.total_openness(sb);
-
-            let va     = na.value();
-            let ea     = na.estimate();
-            let vb     = nb.value();
-            let eb     = nb.estimate();
-            let ta     = va.saturating_add(ea);
-            let tb     = vb.saturating_add(eb);
-
-            oa.cmp(&ob).reverse()
-            .then(ta.cmp(&tb))
-            .then(twa.stop.cmp(&twb.stop))
-            .then(va.cmp(&vb))
-            .then(twa.start.cmp(&twb.start))
-            .then(ea.cmp(&eb))
-        }
+        let sa = na.state();
+        let sb = nb.state();
+
+        let ca = sa.current;
+        let cb = sb.current;
+        let twa= self.inst.time_window[ca];
+        let twb= self.inst.time_window[cb];
+
+        let oa     = self.total_openness(sa);
+        let ob     = self.total_openness(sb);
+
+        let va     = na.value();
+        let ea     = na.estimate();
+        let vb     = nb.value();
+        let eb     = nb.estimate();
+        let ta     = va.saturating_add(ea);
+        let tb     = vb.saturating_add(eb);
+
+        oa.cmp(&ob).reverse()
+        .then(ta.cmp(&tb))
+        .then(twa.stop.cmp(&twb.stop))
+        .then(va.cmp(&vb))
+        .then(twa.start.cmp(&twb.start))
+        .then(ea.cmp(&eb))
         /**/
     }
 
-    fn is_mandatory<S: papier_lns::NodeSource>(
+    fn is_mandatory<S: papier_lns::NodeSource<State = State>>(
         &self,
         dd: &S,
         node: &S::Node,
@@ -395,13 +480,9 @@ impl<B: BufRead> TryFrom<Lines<B>> for Tsptw {
             }
             // The next 'nb_nodes' lines represent the distances matrix
             else if (1..=nb_nodes).contains(&lc) {
-                let i = (lc - 1) as usize;
+                let i = lc - 1;
                 for (j, distance) in line.split_whitespace().enumerate() {
-                    let distance = distance
-                        .to_string()
-                        .parse::<f32>()
-                        .map_err(|e| TsptwError::MatrixCoeff(i, j, e))?;
-                    let distance = (distance * 10000.0) as usize;
+                    let distance = ticks(distance).map_err(|e| TsptwError::MatrixCoeff(i, j, e))?;
                     distances[(i, j)] = distance;
                 }
             }
@@ -409,20 +490,17 @@ impl<B: BufRead> TryFrom<Lines<B>> for Tsptw {
             else {
                 let mut tokens = line.split_whitespace();
                 let earliest = if let Some(earliest) = tokens.next() {
-                    earliest.parse::<f32>().map_err(TsptwError::TwStart)?
+                    ticks(earliest).map_err(TsptwError::TwStart)?
                 } else {
                     return Err(TsptwError::NoTwStart(lc));
                 };
 
                 let latest = if let Some(latest) = tokens.next() {
-                    latest.parse::<f32>().map_err(TsptwError::TwStop)?
+                    ticks(latest).map_err(TsptwError::TwStop)?
                 } else {
                     return Err(TsptwError::NoTwStop(lc));
                 };
 
-                let earliest = (earliest * 10000.0) as usize;
-                let latest = (latest * 10000.0) as usize;
-
                 let timewind = TimeWindow {
                     start: earliest,
                     stop: latest,
@@ -0,0 +1,105 @@
+/*
+ * C API of papier_lns. It lets a foreign program solve a dynamic programming
+ * model with the lns+dd solver of the library (build the crate to get the
+ * `libpapier_lns` shared library).
+ *
+ * The model is given as a set of callbacks. The states are opaque byte
+ * buffers: the library only hashes and compares them. A callback producing a
+ * state (or a domain) fills the buffer (or domain) it is given with
+ * `pl_buffer_write` (or `pl_domain_push`). The variables are decided in order:
+ * the variable decided at depth d is d.
+ *
+ *     pl_callbacks cb = { &data, nb_vars, initial_state, NULL,
+ *                         domain, transition, cost, NULL };
+ *     pl_solver *solver = pl_solver_new(&cb);
+ *     pl_solver_set_width(solver, 100);
+ *     pl_solver_set_time_limit(solver, 10.0);
+ *
+ *     pl_outcome outcome;
+ *     if (pl_solver_run(solver, &outcome) == PL_OK && outcome.has_value) {
+ *         ... outcome.best_value, outcome.solution[0 .. outcome.solution_len]
+ *     }
+ *     pl_solver_free(solver);
+ *
+ * The lns only stops once it has proved optimality: a time limit should
+ * always be set. The callbacks must not unwind (throw) through the library.
+ * Conversely, no panic of the library unwinds into the caller: the functions
+ * report PL_ERR_PANIC instead (pl_solver_new returns NULL).
+ */
+#ifndef PAPIER_LNS_H
+#define PAPIER_LNS_H
+
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+#define PL_API_VERSION 1
+
+/* return codes */
+#define PL_OK          0
+#define PL_ERR_NULL    1 /* a required pointer was null        */
+#define PL_ERR_INVALID 2 /* an argument was out of its range   */
+#define PL_ERR_PANIC   3 /* the library panicked: only free the solver */
+
+/* resolution status */
+#define PL_OPEN   0 /* the optimality of the best solution is not proved */
+#define PL_CLOSED 1 /* the best solution is optimal (or none exists)    */
+
+typedef struct pl_buffer pl_buffer;
+typedef struct pl_domain pl_domain;
+typedef struct pl_solver pl_solver;
+
+/* The model. `initial_value` and `estimate` are optional (NULL): the initial
+ * value then is zero and the states have no estimate. The callbacks receive
+ * `user_data` as their first argument. */
+typedef struct pl_callbacks {
+    void *user_data;
+    size_t  (*nb_vars)(void *user_data);
+    void    (*initial_state)(void *user_data, pl_buffer *out);
+    int64_t (*initial_value)(void *user_data);
+    void    (*domain)(void *user_data, const uint8_t *state, size_t len, size_t var, pl_domain *out);
+    void    (*transition)(void *user_data, const uint8_t *state, size_t len, size_t var, int64_t val, pl_buffer *out);
+    int64_t (*cost)(void *user_data, const uint8_t *state, size_t len, size_t var, int64_t val);
+    int64_t (*estimate)(void *user_data, const uint8_t *state, size_t len);
+} pl_callbacks;
+
+/* The outcome of a resolution. `solution` holds the value of each variable;
+ * it is owned by the solver and remains valid until the next call to
+ * `pl_solver_run` or `pl_solver_free`. */
+typedef struct pl_outcome {
+    int      status;        /* PL_OPEN or PL_CLOSED                 */
+    bool     improved;      /* whether a solution was found         */
+    bool     has_value;
+    int64_t  best_value;
+    double   time_to_best;  /* in seconds (negative when unknown)   */
+    double   time_to_prove; /* in seconds (negative when unknown)   */
+    size_t   solution_len;
+    const int64_t *solution;
+} pl_outcome;
+
+uint32_t pl_api_version(void);
+
+int pl_buffer_write(pl_buffer *buffer, const uint8_t *data, size_t len);
+int pl_domain_push(pl_domain *domain, int64_t value);
+
+/* returns NULL when a mandatory callback is missing (or on a panic) */
+pl_solver *pl_solver_new(const pl_callbacks *callbacks);
+void pl_solver_free(pl_solver *solver);
+
+int pl_solver_set_width(pl_solver *solver, size_t width);             /* default 100, > 0 */
+int pl_solver_set_seed(pl_solver *solver, uint64_t seed);             /* default 0    */
+int pl_solver_set_proba(pl_solver *solver, double proba);             /* default 0.1  */
+int pl_solver_set_time_limit(pl_solver *solver, double seconds);      /* default none */
+
+/* the lns needs at least two variables (PL_ERR_INVALID otherwise) */
+int pl_solver_run(pl_solver *solver, pl_outcome *outcome);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* PAPIER_LNS_H */
@@ -0,0 +1,250 @@
+//! This module provides a compact storage for the states of a layer. When the
+//! diagrams get really wide (say 10^6 nodes), cloning the full blown states
+//! in the hash map used to detect the duplicate states becomes the dominant
+//! memory cost. The arena below stores the encoded states one after the other
+//! in a single buffer, and it remembers the hash of each state so that it never
+//! needs to be computed again (not even when the index grows).
+
+use std::hash::Hasher;
+
+use rustc_hash::FxHasher;
+
+/// The identifier of a state interned in some arena.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct StateId(u32);
+impl StateId {
+    /// The identifier of the `id`-th state interned in an arena
+    pub fn new(id: usize) -> Self {
+        Self(id as u32)
+    }
+    pub fn id(self) -> usize {
+        self.0 as usize
+    }
+}
+
+/// Marks an empty slot of the index
+const EMPTY: u32 = u32::MAX;
+
+/// An interning arena for encoded states.
+#[derive(Debug, Clone, Default)]
+pub struct StateArena {
+    /// The bytes of all the encoded states, stored contiguously
+    bytes: Vec<u8>,
+    /// The position in `bytes` where each state stops (it starts where the
+    /// previous one stops)
+    ends: Vec<usize>,
+    /// The precomputed hash of each state
+    hashes: Vec<u64>,
+    /// An open addressing (linear probing) index mapping the hashes onto the
+    /// identifiers of the states. Its length is always zero or a power of two.
+    index: Vec<u32>,
+}
+
+impl StateArena {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Returns the number of distinct states in the arena
+    pub fn len(&self) -> usize {
+        self.ends.len()
+    }
+    /// Returns true iff no state has been interned in the arena
+    pub fn is_empty(&self) -> bool {
+        self.ends.is_empty()
+    }
+    /// Forgets all the interned states but keeps the allocated memory
+    pub fn clear(&mut self) {
+        self.bytes.clear();
+        self.ends.clear();
+        self.hashes.clear();
+        self.index.iter_mut().for_each(|slot| *slot = EMPTY);
+    }
+    /// Returns the encoded state having the given id
+    pub fn get(&self, id: StateId) -> &[u8] {
+        let stop  = self.ends[id.id()];
+        let start = if id.0 == 0 { 0 } else { self.ends[id.id() - 1] };
+        &self.bytes[start..stop]
+    }
+    /// Returns the precomputed hash of the state having the given id
+    pub fn hash_of(&self, id: StateId) -> u64 {
+        self.hashes[id.id()]
+    }
+    /// Iterates over all the states of the arena in the order they were interned
+    pub fn iter(&self) -> impl Iterator<Item = (StateId, &[u8])> + '_ {
+        (0..self.len()).map(move |i| {
+            let id = StateId(i as u32);
+            (id, self.get(id))
+        })
+    }
+    /// Returns the approximate number of bytes used by the arena
+    pub fn memory_usage(&self) -> usize {
+        self.bytes.capacity()
+            + self.ends.capacity()   * std::mem::size_of::<usize>()
+            + self.hashes.capacity() * std::mem::size_of::<u64>()
+            + self.index.capacity()  * std::mem::size_of::<u32>()
+    }
+    /// Returns the identifier of the given encoded state if it has already
+    /// been interned in the arena
+    pub fn find(&self, encoded: &[u8]) -> Option<StateId> {
+        if self.index.is_empty() {
+            return None;
+        }
+        let hash = Self::hash(encoded);
+        let mask = self.index.len() - 1;
+        let mut slot = Self::home(hash, self.index.len());
+        loop {
+            let cur = self.index[slot];
+            if cur == EMPTY {
+                return None;
+            }
+            let id = StateId(cur);
+            if self.hashes[id.id()] == hash && self.get(id) == encoded {
+                return Some(id);
+            }
+            slot = (slot + 1) & mask;
+        }
+    }
+    /// Interns the given encoded state. This method returns the identifier of
+    /// the state and a flag telling whether the state was not already present
+    /// in the arena (true means it has just been added).
+    pub fn intern(&mut self, encoded: &[u8]) -> (StateId, bool) {
+        match self.entry(encoded) {
+            ArenaEntry::Occupied(id) => (id, false),
+            ArenaEntry::Vacant(slot) => (slot.insert(), true),
+        }
+    }
+    /// Looks the given encoded state up, and returns either its identifier
+    /// (when it is already interned) or the slot where it may be interned.
+    /// Either way, the state is hashed only once.
+    pub fn entry<'a, 'b>(&'a mut self, encoded: &'b [u8]) -> ArenaEntry<'a, 'b> {
+        if (self.len() + 1) * 4 > self.index.len() * 3 {
+            self.grow();
+        }
+        let hash = Self::hash(encoded);
+        let mask = self.index.len() - 1;
+        let mut slot = Self::home(hash, self.index.len());
+        loop {
+            let cur = self.index[slot];
+            if cur == EMPTY {
+                return ArenaEntry::Vacant(VacantState { arena: self, encoded, hash, slot });
+            }
+            let id = StateId(cur);
+            if self.hashes[id.id()] == hash && self.get(id) == encoded {
+                return ArenaEntry::Occupied(id);
+            }
+            slot = (slot + 1) & mask;
+        }
+    }
+
+    /// Doubles the size of the index. Thanks to the precomputed hashes, this
+    /// never requires to rehash the states.
+    fn grow(&mut self) {
+        let size = (self.index.len() * 2).max(16);
+        self.index.clear();
+        self.index.resize(size, EMPTY);
+        let mask = size - 1;
+        for (i, hash) in self.hashes.iter().enumerate() {
+            let mut slot = Self::home(*hash, size);
+            while self.index[slot] != EMPTY {
+                slot = (slot + 1) & mask;
+            }
+            self.index[slot] = i as u32;
+        }
+    }
+
+    /// The preferred slot of a hash in an index of the given size. It uses the
+    /// high bits of the hash since these are the best mixed by FxHasher.
+    fn home(hash: u64, size: usize) -> usize {
+        (hash >> (64 - size.trailing_zeros())) as usize
+    }
+
+    fn hash(encoded: &[u8]) -> u64 {
+        let mut hasher = FxHasher::default();
+        hasher.write(encoded);
+        hasher.finish()
+    }
+}
+
+/// The outcome of `StateArena::entry`
+pub enum ArenaEntry<'a, 'b> {
+    /// The state is already interned with the given identifier
+    Occupied(StateId),
+    /// The state is not interned yet
+    Vacant(VacantState<'a, 'b>),
+}
+
+/// The free slot of the index where a state may be interned
+pub struct VacantState<'a, 'b> {
+    arena: &'a mut StateArena,
+    encoded: &'b [u8],
+    hash: u64,
+    slot: usize,
+}
+impl VacantState<'_, '_> {
+    /// Interns the state and returns its identifier
+    pub fn insert(self) -> StateId {
+        let arena = self.arena;
+        let id    = StateId(arena.len() as u32);
+        arena.bytes.extend_from_slice(self.encoded);
+        arena.ends.push(arena.bytes.len());
+        arena.hashes.push(self.hash);
+        arena.index[self.slot] = id.0;
+        id
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ArenaEntry, StateArena, StateId};
+
+    #[test]
+    fn an_entry_is_vacant_until_its_state_is_inserted() {
+        let mut arena = StateArena::new();
+        assert_eq!(None, arena.find(b"abc"));
+
+        // a vacant entry that is dropped interns nothing
+        assert!(matches!(arena.entry(b"abc"), ArenaEntry::Vacant(_)));
+        assert!(arena.is_empty());
+
+        let ArenaEntry::Vacant(slot) = arena.entry(b"abc") else { panic!("abc is not interned yet") };
+        let abc = slot.insert();
+        assert!(matches!(arena.entry(b"abc"), ArenaEntry::Occupied(id) if id == abc));
+        // the empty state and the prefixes are distinct states
+        assert_eq!((StateId::new(1), true),  arena.intern(b""));
+        assert_eq!((StateId::new(2), true),  arena.intern(b"ab"));
+        assert_eq!((abc, false), arena.intern(b"abc"));
+
+        assert_eq!(3, arena.len());
+        assert_eq!(b"abc", arena.get(abc));
+        assert_eq!(b"",    arena.get(StateId::new(1)));
+        assert_eq!(vec![&b"abc"[..], b"", b"ab"], arena.iter().map(|(_, s)| s).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn the_index_grows_at_three_quarters_of_its_load() {
+        let mut arena = StateArena::new();
+        let states = (0_u32..1000).map(|i| i.to_le_bytes()).collect::<Vec<_>>();
+        for (i, state) in states.iter().enumerate() {
+            assert_eq!((StateId::new(i), true), arena.intern(state));
+            if i == 11 {
+                assert_eq!(16, arena.index.len());
+            }
+            if i == 12 {
+                assert_eq!(32, arena.index.len());
+            }
+        }
+        assert_eq!(2048, arena.index.len());
+        // growing never loses a state nor its hash
+        for (i, state) in states.iter().enumerate() {
+            assert_eq!(Some(StateId::new(i)), arena.find(state));
+            assert_eq!(StateArena::hash(state), arena.hash_of(StateId::new(i)));
+        }
+
+        // clearing forgets the states but keeps the index
+        arena.clear();
+        assert!(arena.is_empty());
+        assert_eq!(2048, arena.index.len());
+        assert_eq!(None, arena.find(&states[0]));
+        assert_eq!((StateId::new(0), true), arena.intern(&states[999]));
+    }
+}
@@ -0,0 +1,340 @@
+//! This module implements a best-first search (A*) solver. Unlike `PureDp`
+//! which explores the state space depth first, this solver always expands the
+//! open state having the smallest `value + estimate`. When the estimate of the
+//! problem is admissible (it never overestimates the remaining cost), the
+//! search proves the optimality of the best solution it finds.
+//!
+//! The estimate may be inflated by a weight (weighted A*). In that case, the
+//! search greedily dives towards the terminal states and quickly finds good
+//! solutions. It then goes on as an anytime algorithm: it keeps expanding the
+//! open states and prunes those whose (non weighted) bound cannot improve the
+//! incumbent, until the open list is exhausted and optimality is proved.
+
+use std::{
+    cmp::Reverse,
+    collections::BinaryHeap,
+    hash::Hash,
+    rc::Rc,
+    sync::{atomic::AtomicBool, Arc},
+    time::Instant,
+};
+
+use derive_builder::Builder;
+use rustc_hash::FxHashMap;
+
+use crate::{
+    Cost, Decision, Problem, Propagator, ResolutionOutcome, ResolutionStatus, Solution, Var,
+    VariableOrdering,
+};
+
+// ----------------------------------------------------------------------------
+// Best first search (A*) implem from problem description
+// ----------------------------------------------------------------------------
+#[derive(Debug, Builder)]
+pub struct AStar<P, V>
+where
+    P: Problem,
+    V: VariableOrdering<State = P::State>,
+    P::State: PartialEq + Eq + Hash,
+{
+    /// The problem that must be solved
+    problem: P,
+    /// The variable ordering that will be imposed on the problem
+    var_ordering: V,
+
+    /// When did we start working on this instance ?
+    start_time: Instant,
+    /// An atomic boolean acting as a kill switch. Whenever this flag turns true,
+    /// the progress must stop and return the best known solution asap.
+    kill_switch: Arc<AtomicBool>,
+    /// The weight of the estimate when ordering the open states. A weight of
+    /// 1.0 gives the plain A*, a larger weight trades the quality of the first
+    /// solutions for the speed at which they are found.
+    #[builder(default = "1.0")]
+    weight: f64,
+    /// An optional initial solution and its value. These are used to prune
+    /// the open states from the start.
+    #[builder(default)]
+    initial_val: Option<P::Cost>,
+    #[builder(default)]
+    initial_sol: Option<Solution>,
+    /// An optional set of constraints used to filter the domains and to
+    /// discard the dead-end states
+    #[builder(default, setter(strip_option))]
+    propagator: Option<Arc<Propagator<P::State>>>,
+}
+
+/// A node of the search. Nodes are never modified once created: whenever a
+/// better path to some state is found, a new node is created for that state.
+struct SearchNode<S, C> {
+    state: Rc<S>,
+    value: C,
+    /// The (non weighted) estimate of the state
+    estimate: C,
+    /// The node this one was created from and the decision that lead to it
+    parent: Option<(usize, Decision)>,
+}
+
+/// The key used to order the open list: the nodes having the smallest
+/// (weighted) priority come first and the ties are broken in favor of the
+/// nodes having the largest value (these are the closest to a terminal).
+type OpenEntry<C> = (Reverse<C>, C, Reverse<usize>);
+
+impl<P, V> AStar<P, V>
+where
+    P: Problem,
+    V: VariableOrdering<State = P::State>,
+    P::State: PartialEq + Eq + Hash,
+{
+    pub fn minimize(&self) -> ResolutionOutcome<P::Cost> {
+        let mut nodes: Vec<SearchNode<P::State, P::Cost>> = vec![];
+        let mut open  = BinaryHeap::<OpenEntry<P::Cost>>::new();
+        // the node holding the best known path to each state
+        let mut best_path = FxHashMap::<Rc<P::State>, usize>::default();
+
+        let mut best_val  = self.initial_val.unwrap_or(P::Cost::MAX);
+        let mut best_node = None;
+        let mut ttb       = None;
+
+        let initial  = Rc::new(self.problem.initial_state());
+        let value    = self.problem.initial_value();
+        let estimate = self.problem.estimate(&initial);
+        if value.saturating_add(estimate) < best_val {
+            nodes.push(SearchNode { state: Rc::clone(&initial), value, estimate, parent: None });
+            best_path.insert(initial, 0);
+            open.push((Reverse(self.priority(value, estimate)), value, Reverse(0)));
+        }
+
+        while let Some((Reverse(priority), value, Reverse(id))) = open.pop() {
+            if self.killed() {
+                break;
+            }
+            let state = Rc::clone(&nodes[id].state);
+            // a better path to this state has been found since it was pushed
+            if best_path.get(&state) != Some(&id) {
+                continue;
+            }
+            let bound = value.saturating_add(nodes[id].estimate);
+            if bound >= best_val {
+                // with a weight of one, the open list is sorted by bound hence
+                // no other open state can improve the incumbent
+                if self.weight == 1.0 && priority >= best_val {
+                    break;
+                }
+                continue;
+            }
+
+            let Some(var) = self.var_ordering.next(&mut std::iter::once(state.as_ref())) else {
+                // only the initial state can be terminal when popped: the
+                // other terminal states are dealt with when they are reached
+                best_val  = value;
+                best_node = Some(id);
+                ttb       = Some(self.start_time.elapsed());
+                continue;
+            };
+
+            self.for_each_in_domain(state.as_ref(), var, |decision| {
+                let next = self.problem.transition(state.as_ref(), decision);
+                if self.is_dead_end(&next) {
+                    return;
+                }
+                let cost  = self.problem.transition_cost(state.as_ref(), decision);
+                let value = value.saturating_add(cost);
+                if best_path.get(&next).is_some_and(|n| nodes[*n].value <= value) {
+                    return;
+                }
+
+                let is_terminal = self.var_ordering.next(&mut std::iter::once(&next)).is_none();
+                let estimate    = if is_terminal { P::Cost::ZERO } else { self.problem.estimate(&next) };
+                if value.saturating_add(estimate) >= best_val {
+                    return;
+                }
+
+                let next = Rc::new(next);
+                let nid  = nodes.len();
+                nodes.push(SearchNode { state: Rc::clone(&next), value, estimate, parent: Some((id, decision)) });
+                best_path.insert(next, nid);
+
+                if is_terminal {
+                    best_val  = value;
+                    best_node = Some(nid);
+                    ttb       = Some(self.start_time.elapsed());
+                } else {
+                    open.push((Reverse(self.priority(value, estimate)), value, Reverse(nid)));
+                }
+            });
+        }
+
+        let killed   = self.killed();
+        let improved = best_node.is_some();
+        let status   = if killed {
+            ResolutionStatus::Open { improved }
+        } else {
+            ResolutionStatus::Closed { improved }
+        };
+        let best_sol = if let Some(id) = best_node {
+            let mut decisions = vec![];
+            let mut curr = nodes[id].parent;
+            while let Some((from, decision)) = curr {
+                decisions.push(decision);
+                curr = nodes[from].parent;
+            }
+            Some(Solution::from(decisions.iter().rev().copied()))
+        } else {
+            self.initial_sol.clone()
+        };
+
+        ResolutionOutcome {
+            status,
+            best_value: if improved { Some(best_val) } else { self.initial_val },
+            best_sol,
+            time_to_best: ttb,
+            time_to_prove: if killed { None } else { Some(self.start_time.elapsed()) },
+        }
+    }
+
+    /// This function evaluates the kill switch
+    fn killed(&self) -> bool {
+        self.kill_switch.load(std::sync::atomic::Ordering::Relaxed)
+    }
+
+    /// The priority of a node in the open list
+    fn priority(&self, value: P::Cost, estimate: P::Cost) -> P::Cost {
+        if self.weight == 1.0 {
+            value.saturating_add(estimate)
+        } else {
+            // the conversion from a float saturates, hence this never overflows
+            value.saturating_add(P::Cost::from_f64(self.weight * estimate.to_f64()))
+        }
+    }
+
+    /// Iterates over the domain of `var` (filtered by the propagator if any)
+    fn for_each_in_domain(&self, state: &P::State, var: Var, f: impl FnMut(Decision)) {
+        if let Some(propagator) = self.propagator.as_ref() {
+            propagator.for_each_in_domain(&self.problem, state, var, f)
+        } else {
+            self.problem.for_each_in_domain(state, var, f)
+        }
+    }
+
+    /// Tells whether the propagator (if any) detects the state is a dead end
+    fn is_dead_end(&self, state: &P::State) -> bool {
+        self.propagator.as_ref().is_some_and(|p| p.is_dead_end(state))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::{atomic::AtomicBool, Arc},
+        time::Instant,
+    };
+
+    use crate::testing::{InOrder, Knapsack};
+    use crate::{Decision, Problem, ResolutionStatus, Var, VariableOrdering};
+
+    use super::{AStar, AStarBuilder};
+
+    /// A tiny graph whose estimate is admissible but not consistent: the
+    /// estimate of `B` is exact whereas that of `X` is zero. Hence, `X` is
+    /// first expanded through `A` and it must be re-opened once the cheaper
+    /// path through `B` is found.
+    ///
+    ///   S -1-> A -1-> X -5-> T
+    ///   S -1-> B -0-> X
+    #[derive(Debug, Clone, Copy)]
+    struct Detour;
+    const S: usize = 0;
+    const A: usize = 1;
+    const B: usize = 2;
+    const X: usize = 3;
+    const T: usize = 4;
+    const DEPTH: [usize; 5] = [0, 1, 1, 2, 3];
+
+    impl Problem for Detour {
+        type State = usize;
+        type Cost = isize;
+
+        fn nb_vars(&self) -> usize {
+            3
+        }
+        fn initial_state(&self) -> usize {
+            S
+        }
+        fn initial_value(&self) -> isize {
+            0
+        }
+        fn for_each_in_domain(&self, state: &usize, var: Var, mut f: impl FnMut(Decision)) {
+            let nb_vals = match *state { S => 2, T => 0, _ => 1 };
+            (0..nb_vals).for_each(|val| f(Decision::new(var, val)))
+        }
+        fn transition(&self, state: &usize, decision: Decision) -> usize {
+            match (*state, decision.val) {
+                (S, 0) => A,
+                (S, _) => B,
+                (X, _) => T,
+                _      => X,
+            }
+        }
+        fn transition_cost(&self, state: &usize, _decision: Decision) -> isize {
+            match *state { B => 0, X => 5, _ => 1 }
+        }
+        fn estimate(&self, state: &usize) -> isize {
+            if *state == B { 5 } else { 0 }
+        }
+    }
+    #[derive(Debug, Clone, Copy)]
+    struct ByDepth;
+    impl VariableOrdering for ByDepth {
+        type State = usize;
+
+        fn next(&self, states: &mut dyn Iterator<Item = &usize>) -> Option<Var> {
+            let state = *states.next()?;
+            (state != T).then(|| Var::new(DEPTH[state]))
+        }
+    }
+
+    fn solver<P, V>(problem: P, var_ordering: V) -> AStarBuilder<P, V>
+    where
+        P: Problem + Clone,
+        V: VariableOrdering<State = P::State> + Clone,
+    {
+        let mut builder = AStarBuilder::default();
+        builder
+            .problem(problem)
+            .var_ordering(var_ordering)
+            .start_time(Instant::now())
+            .kill_switch(Arc::new(AtomicBool::new(false)));
+        builder
+    }
+
+    #[test]
+    fn a_state_reached_by_a_cheaper_path_is_reopened() {
+        let solver: AStar<_, _> = solver(Detour, ByDepth).build().unwrap();
+        let outcome = solver.minimize();
+        assert!(matches!(outcome.status, ResolutionStatus::Closed { improved: true }));
+        // without re-opening X, the search would settle for 1 + 1 + 5
+        assert_eq!(Some(6), outcome.best_value);
+        let sol = outcome.best_sol.expect("a solution");
+        assert_eq!(vec![1, 0, 0], sol.iter().map(|d| d.val).collect::<Vec<_>>());
+        assert_eq!(6, Detour.evaluate(&ByDepth, &sol));
+    }
+
+    #[test]
+    fn weighted_astar_still_proves_the_optimum() {
+        let problem = Knapsack::small();
+        let optimum = problem.brute_force();
+        for weight in [1.0, 1.5, 4.0] {
+            let solver: AStar<_, _> = solver(&problem, InOrder(problem.nb_vars())).weight(weight).build().unwrap();
+            let outcome = solver.minimize();
+            assert!(matches!(outcome.status, ResolutionStatus::Closed { improved: true }), "weight {}", weight);
+            assert_eq!(Some(optimum), outcome.best_value, "weight {}", weight);
+        }
+
+        // nothing improves an optimal initial solution
+        let solver: AStar<_, _> = solver(&problem, InOrder(problem.nb_vars())).initial_val(Some(optimum)).build().unwrap();
+        let outcome = solver.minimize();
+        assert!(matches!(outcome.status, ResolutionStatus::Closed { improved: false }));
+        assert_eq!(Some(optimum), outcome.best_value);
+    }
+}
@@ -2,14 +2,22 @@ use std::{
     cmp::Ordering,
     fmt::Display,
     hash::Hash,
+    marker::PhantomData,
     ops::{Deref, Index},
     time::Duration, num::ParseIntError, str::FromStr,
 };
 
+use rand_xoshiro::Xoshiro256Plus;
+use rustc_hash::{FxHashMap, FxHashSet};
+use serde::{Deserialize, Serialize};
+
+use crate::{Constraint, Cost};
+
 // ----------------------------------------------------------------------------
 /// Variable
 // ----------------------------------------------------------------------------
-#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(transparent)]
 pub struct Var(usize);
 impl Var {
     pub fn new(x: usize) -> Self {
@@ -35,9 +43,16 @@ impl Decision {
 // ----------------------------------------------------------------------------
 /// Solution
 // ----------------------------------------------------------------------------
-#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Solution {
+    /// The value assigned to each variable (indexed by variable id)
+    #[serde(rename = "values")]
     data: Vec<isize>,
+    /// The order in which the variables have been branched on. It is empty
+    /// when that order is unknown (e.g. when the solution is parsed from a
+    /// string), in which case the variable ordering decides.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    order: Vec<Var>,
 }
 impl Solution {
     pub fn iter(&self) -> impl ExactSizeIterator<Item = Decision> + '_ {
@@ -47,6 +62,52 @@ impl Solution {
             .enumerate()
             .map(|(i, val)| Decision { var: Var(i), val })
     }
+    /// Returns the variable that was branched on at the given depth (if known)
+    pub fn var_at(&self, depth: usize) -> Option<Var> {
+        self.order.get(depth).copied()
+    }
+    /// Tells whether the order in which the variables were branched on is known
+    pub fn has_order(&self) -> bool {
+        !self.order.is_empty()
+    }
+    /// Returns the variable to branch on at the given depth when replaying
+    /// this solution. The recorded order is followed when it is known: a
+    /// dynamic variable ordering might otherwise pick a different variable
+    /// than it did when the solution was found.
+    pub fn next_var<S>(
+        &self,
+        var_ord: &dyn VariableOrdering<State = S>,
+        depth: usize,
+        state: &S,
+    ) -> Option<Var> {
+        if self.has_order() {
+            self.var_at(depth)
+        } else {
+            var_ord.next(&mut std::iter::once(state))
+        }
+    }
+}
+// The identity of a solution only depends on the assigned values
+impl PartialEq for Solution {
+    fn eq(&self, other: &Self) -> bool {
+        self.data == other.data
+    }
+}
+impl Eq for Solution {}
+impl PartialOrd for Solution {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+impl Ord for Solution {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.data.cmp(&other.data)
+    }
+}
+impl Hash for Solution {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.data.hash(state)
+    }
 }
 impl Index<Var> for Solution {
     type Output = isize;
@@ -58,13 +119,16 @@ impl<T> From<T> for Solution
 where
     T: Iterator<Item = Decision>,
 {
+    /// The decisions are expected in the order in which they were made
     fn from(it: T) -> Self {
         let decisions = it.collect::<Vec<Decision>>();
         let mut data  = vec![0; decisions.len()];
+        let mut order = Vec::with_capacity(decisions.len());
         for d in decisions {
             data[d.var.0] = d.val;
+            order.push(d.var);
         }
-        Self { data }
+        Self { data, order }
     }
 }
 
@@ -77,7 +141,7 @@ impl FromStr for Solution {
             let decision  = token.parse::<isize>()?;
             data.push(decision);
         }
-        Ok(Self {data})
+        Ok(Self {data, order: vec![]})
     }
 }
 
@@ -92,7 +156,8 @@ impl Display for Solution {
 // ----------------------------------------------------------------------------
 /// Resolution status
 // ----------------------------------------------------------------------------
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "state", rename_all = "lowercase")]
 pub enum ResolutionStatus {
     /// I haven't proved optimality yet
     Open{improved: bool},
@@ -114,14 +179,27 @@ impl ResolutionStatus {
 /// Resolution outcome: the result of attempting to solve the problem with a
 /// given method
 // ----------------------------------------------------------------------------
-#[derive(Clone)]
-pub struct ResolutionOutcome {
+#[derive(Clone, Serialize, Deserialize)]
+pub struct ResolutionOutcome<C> {
     pub status: ResolutionStatus,
-    pub best_value: Option<isize>,
+    pub best_value: Option<C>,
     pub best_sol: Option<Solution>,
+    /// The durations are (de)serialized as a number of seconds
+    #[serde(with = "crate::report::seconds")]
     pub time_to_best: Option<Duration>,
+    #[serde(with = "crate::report::seconds")]
     pub time_to_prove: Option<Duration>,
 }
+impl<C: Cost> ResolutionOutcome<C> {
+    /// The best known lower bound on the optimal value. Only a closed
+    /// resolution knows its bound: it is the optimal value itself.
+    pub fn best_bound(&self) -> Option<C> {
+        match self.status {
+            ResolutionStatus::Closed { .. } => self.best_value,
+            ResolutionStatus::Open { .. } => None,
+        }
+    }
+}
 // ----------------------------------------------------------------------------
 /// Variable Ordering
 // ----------------------------------------------------------------------------
@@ -131,69 +209,218 @@ pub trait VariableOrdering {
     fn next(&self, state: &mut dyn Iterator<Item = &Self::State>) -> Option<Var>;
 }
 // ----------------------------------------------------------------------------
+/// State Codec: an optional means to store the states in a compact form
+// ----------------------------------------------------------------------------
+pub trait StateCodec {
+    type State;
+
+    /// Appends the compact representation of the given state to `into`. Two
+    /// states must be encoded the same way iff they are equal.
+    fn encode(&self, state: &Self::State, into: &mut Vec<u8>);
+    /// Reconstructs a full blown state from its compact representation
+    fn decode(&self, encoded: &[u8]) -> Self::State;
+}
+// ----------------------------------------------------------------------------
+/// State Size: the memory footprint of a state. It lets the diagrams be
+/// restricted according to a memory budget rather than a number of nodes.
+// ----------------------------------------------------------------------------
+pub trait StateSize {
+    /// Returns the number of bytes used by the state, including the data it
+    /// owns on the heap. By default, only the inline size is accounted for.
+    fn state_size(&self) -> usize {
+        std::mem::size_of_val(self)
+    }
+}
+// ----------------------------------------------------------------------------
+/// State Distance: tells how different two states are. This is used to keep
+/// some diversity among the nodes that survive a restriction.
+// ----------------------------------------------------------------------------
+pub trait StateDistance {
+    type State;
+
+    /// Returns a non negative distance between the two states. It must be
+    /// zero when the two states are equal.
+    fn distance(&self, a: &Self::State, b: &Self::State) -> f64;
+}
+// ----------------------------------------------------------------------------
+/// Rollout Policy: a cheap (typically greedy) policy used to complete the
+/// partial solutions in order to assess the quality of a node
+// ----------------------------------------------------------------------------
+pub trait RolloutPolicy {
+    type State;
+
+    /// Picks the value that should be assigned to `var` when the problem is
+    /// in the given state. The returned decision must belong to the domain of
+    /// `var`. None means the policy cannot complete the solution from there.
+    fn choose(&self, state: &Self::State, var: Var) -> Option<Decision>;
+}
+// ----------------------------------------------------------------------------
 /// Problem definition
 // ----------------------------------------------------------------------------
 pub trait Problem {
     type State: PartialEq + Eq + Hash;
+    /// The type of the costs (isize, i64, i128, fixed point decimals, ...)
+    type Cost: Cost;
 
     fn nb_vars(&self) -> usize;
     fn initial_state(&self) -> Self::State;
-    fn initial_value(&self) -> isize;
+    fn initial_value(&self) -> Self::Cost;
     //
     fn for_each_in_domain(&self, state: &Self::State, var: Var, f: impl FnMut(Decision));
 
     fn transition(&self, state: &Self::State, decision: Decision) -> Self::State;
-    fn transition_cost(&self, state: &Self::State, decision: Decision) -> isize;
+    fn transition_cost(&self, state: &Self::State, decision: Decision) -> Self::Cost;
 
     // rough lower bound
-    fn estimate(&self, _state: &Self::State) -> isize {
-        isize::MIN
+    fn estimate(&self, _state: &Self::State) -> Self::Cost {
+        Self::Cost::MIN
     }
 
-    fn evaluate(&self, var_ord: &dyn VariableOrdering<State=Self::State>, sol: &Solution) -> isize {
+    fn evaluate(&self, var_ord: &dyn VariableOrdering<State=Self::State>, sol: &Solution) -> Self::Cost {
         let mut state = self.initial_state();
         let mut cost = self.initial_value();
-        while let Some(var) = var_ord.next(&mut std::iter::once(&state)) {
+        let mut depth = 0;
+        while let Some(var) = sol.next_var(var_ord, depth, &state) {
             let val = sol[var];
             let decision = Decision::new(var, val);
             cost += self.transition_cost(&state, decision);
             state = self.transition(&state, decision);
+            depth += 1;
         }
         cost
     }
 
-    fn details(&self, var_ord: &dyn VariableOrdering<State=Self::State>, sol: &Solution) {
+    /// Returns a record describing each decision of the given solution
+    fn details(&self, var_ord: &dyn VariableOrdering<State=Self::State>, sol: &Solution) -> Vec<DecisionRecord<Self::Cost>> {
+        let mut records = vec![];
         let mut state = self.initial_state();
-        while let Some(var) = var_ord.next(&mut std::iter::once(&state)) {
+        let mut depth = 0;
+        while let Some(var) = sol.next_var(var_ord, depth, &state) {
             let val = sol[var];
             let decision = Decision::new(var, val);
-            self.decision_details(&state, decision);
+            let violation = if self.is_in_domain(&state, decision) {
+                None
+            } else {
+                Some(self.violation_reason(&state, decision))
+            };
+            records.push(DecisionRecord {
+                depth,
+                decision,
+                state: self.state_summary(&state),
+                cost: self.transition_cost(&state, decision),
+                details: self.decision_details(&state, decision),
+                violation,
+            });
             state = self.transition(&state, decision);
+            depth += 1;
         }
+        records
     }
 
-    fn check(&self, var_ord: &dyn VariableOrdering<State=Self::State>, sol: &Solution) {
+    /// Checks the feasibility of the given solution and evaluates its cost
+    fn check(&self, var_ord: &dyn VariableOrdering<State=Self::State>, sol: &Solution) -> CheckReport<Self::Cost> {
+        let mut violations = vec![];
         let mut state = self.initial_state();
-        while let Some(var) = var_ord.next(&mut std::iter::once(&state)) {
+        let mut cost  = self.initial_value();
+        let mut depth = 0;
+        while let Some(var) = sol.next_var(var_ord, depth, &state) {
             let val = sol[var];
             let decision = Decision::new(var, val);
-            let mut in_domain = false;
-            self.for_each_in_domain(&state, decision.var, |d| in_domain |= d == decision);
-            
-            if !in_domain {
-                self.on_violation(&state, decision);
+            if !self.is_in_domain(&state, decision) {
+                violations.push(Violation {
+                    depth,
+                    decision,
+                    state: self.state_summary(&state),
+                    reason: self.violation_reason(&state, decision),
+                });
             }
-
+            cost += self.transition_cost(&state, decision);
             state = self.transition(&state, decision);
+            depth += 1;
         }
+        CheckReport { cost, violations }
+    }
+
+    /// Tells whether the decision belongs to the domain of its variable
+    fn is_in_domain(&self, state: &Self::State, decision: Decision) -> bool {
+        let mut in_domain = false;
+        self.for_each_in_domain(state, decision.var, |d| in_domain |= d == decision);
+        in_domain
+    }
+
+    /// A short human readable summary of the given state
+    fn state_summary(&self, _state: &Self::State) -> String {
+        String::new()
     }
 
-    fn on_violation(&self, _state: &Self::State, decision: Decision) {
-        println!("violation x{} <-- {}", decision.var.id(), decision.val);
+    /// Explains why the decision cannot be taken from the given state
+    fn violation_reason(&self, _state: &Self::State, decision: Decision) -> String {
+        format!("{} is not in the domain of x{}", decision.val, decision.var.id())
     }
 
-    fn decision_details(&self, _state: &Self::State, decision: Decision) {
-        println!("x{} <-- {}", decision.var.id(), decision.val);
+    /// Problem specific details about the decision taken from the given state
+    fn decision_details(&self, _state: &Self::State, _decision: Decision) -> String {
+        String::new()
+    }
+}
+
+// ----------------------------------------------------------------------------
+/// Check reports: the structured outcome of checking a solution
+// ----------------------------------------------------------------------------
+/// A decision of a solution which violates the constraints of the problem
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Violation {
+    /// The position of the decision in the solution
+    pub depth: usize,
+    pub decision: Decision,
+    /// A summary of the state from which the decision was taken
+    pub state: String,
+    /// Why the decision violates the constraints of the problem
+    pub reason: String,
+}
+impl Display for Violation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "violation x{:<4} <-- {:>3} || {} || {}",
+            self.decision.var.id(), self.decision.val, self.state, self.reason)
+    }
+}
+
+/// The result of checking a solution
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CheckReport<C> {
+    /// The cost of the solution (as per `evaluate`)
+    pub cost: C,
+    pub violations: Vec<Violation>,
+}
+impl<C> CheckReport<C> {
+    /// Returns true iff the solution violates no constraint
+    pub fn is_feasible(&self) -> bool {
+        self.violations.is_empty()
+    }
+}
+
+/// The description of one decision of a solution
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DecisionRecord<C> {
+    /// The position of the decision in the solution
+    pub depth: usize,
+    pub decision: Decision,
+    /// A summary of the state from which the decision was taken
+    pub state: String,
+    /// The cost of the transition
+    pub cost: C,
+    /// Problem specific details about the decision
+    pub details: String,
+    /// Why the decision violates the constraints (if it does)
+    pub violation: Option<String>,
+}
+impl<C> Display for DecisionRecord<C> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "x{:<4} <-- {:>3} || {} || {}", self.decision.var.id(), self.decision.val, self.state, self.details)?;
+        if let Some(reason) = self.violation.as_ref() {
+            write!(f, " !! {}", reason)?;
+        }
+        Ok(())
     }
 }
 
@@ -202,19 +429,27 @@ pub trait Problem {
 // ----------------------------------------------------------------------------
 pub trait Mdd {
     type State: Eq + PartialEq + Hash;
+    type Cost: Cost;
 
-    fn get_best_value(&self) -> Option<isize>;
+    fn get_best_value(&self) -> Option<Self::Cost>;
     fn get_best_solution(&self) -> Option<Solution>;
     fn is_exact(&self) -> bool;
-    fn exact(&mut self) -> Option<isize>;
+    fn exact(&mut self) -> Option<Self::Cost>;
 
     fn restricted(
         &mut self,
         max_width: usize,
-        best_val: isize,
+        best_val: Self::Cost,
         best_sol: &Option<Solution>,
         start_depth: usize,
-    ) -> Option<isize>;
+    ) -> Option<Self::Cost>;
+
+    /// The current state of the random number generator (if the mdd uses
+    /// one). Restoring it makes the next compilation reproducible.
+    fn rng_state(&self) -> Option<Xoshiro256Plus> {
+        None
+    }
+    fn set_rng_state(&mut self, _rng: Xoshiro256Plus) {}
 }
 
 // ----------------------------------------------------------------------------
@@ -223,28 +458,34 @@ pub trait Mdd {
 pub trait NodeSource {
     type State;
     type Node: SelectableNode<State = Self::State>;
-    type Path: Iterator<Item = Decision>;
+    type Path<'a>: Iterator<Item = Decision> where Self: 'a;
 
     /// Returns the path from root to this node within the given source structure
-    /// `within` is typically the mdd from which the selectable node originates
-    fn path(&self, node: &Self::Node) -> Self::Path;
+    /// `within` is typically the mdd from which the selectable node originates.
+    /// The returned path borrows the source, hence it cannot outlive it.
+    fn path(&self, node: &Self::Node) -> Self::Path<'_>;
 }
 pub trait SelectableNode {
     type State;
+    type Cost: Cost;
 
     /// Returns the state of the node
     fn state(&self) -> &Self::State;
     /// Tells the best objective value of the problem when considered at this
     /// specific node
-    fn value(&self) -> isize;
+    fn value(&self) -> Self::Cost;
     /// Estimates the best objective on the remaining sub problem
-    fn estimate(&self) -> isize;
+    fn estimate(&self) -> Self::Cost;
 }
 pub trait NodeSelectionHeuristic {
+    /// The type of the states held by the nodes this heuristic compares. 
+    /// Knowing it lets a problem specific heuristic inspect the node states.
+    type State;
+
     /// An optional method that tells whether or not we want to force the
     /// node compilation proceedure to keep the given node while expanding further
     /// layers
-    fn is_mandatory<S: NodeSource>(
+    fn is_mandatory<S: NodeSource<State = Self::State>>(
         &self,
         _dd: &S,
         _node: &S::Node,
@@ -258,25 +499,29 @@ pub trait NodeSelectionHeuristic {
     /// diagram after a restriction is performed. An earlier rank
     /// (Ordering::Less) means that the node is more likely to stay in the dd
     /// after restriction occured.
-    fn compare<S: NodeSource>(&self, dd: &S, na: &S::Node, nb: &S::Node) -> Ordering;
+    fn compare<S: NodeSource<State = Self::State>>(&self, dd: &S, na: &S::Node, nb: &S::Node) -> Ordering;
 }
 
-#[derive(Default, Debug, Clone, Copy)]
-pub struct MinLP;
-impl NodeSelectionHeuristic for MinLP {
-    fn compare<S: NodeSource>(&self, _dd: &S, na: &S::Node, nb: &S::Node) -> Ordering {
+/// Keeps the nodes having the smallest (longest path) value
+pub struct MinLP<T>(PhantomData<T>);
+impl<T> NodeSelectionHeuristic for MinLP<T> {
+    type State = T;
+
+    fn compare<S: NodeSource<State = T>>(&self, _dd: &S, na: &S::Node, nb: &S::Node) -> Ordering {
         na.value().cmp(&nb.value())
     }
 }
 
-#[derive(Debug, Clone, Copy)]
-pub struct KeepThemAll;
-impl NodeSelectionHeuristic for KeepThemAll {
-    fn compare<S: NodeSource>(&self, _dd: &S, na: &S::Node, nb: &S::Node) -> Ordering {
+/// Never drops any node from the diagram (all nodes are mandatory)
+pub struct KeepThemAll<T>(PhantomData<T>);
+impl<T> NodeSelectionHeuristic for KeepThemAll<T> {
+    type State = T;
+
+    fn compare<S: NodeSource<State = T>>(&self, _dd: &S, na: &S::Node, nb: &S::Node) -> Ordering {
         // never ever occurs
         na.value().cmp(&nb.value())
     }
-    fn is_mandatory<S: NodeSource>(
+    fn is_mandatory<S: NodeSource<State = T>>(
         &self,
         _dd: &S,
         _node: &S::Node,
@@ -287,6 +532,258 @@ impl NodeSelectionHeuristic for KeepThemAll {
     }
 }
 
+// The heuristics above carry no data: they only need a phantom state type.
+// Deriving these traits would needlessly require the state to implement them.
+macro_rules! stateless_heuristic {
+    ($name: ident) => {
+        impl<T> $name<T> {
+            pub fn new() -> Self {
+                Self(PhantomData)
+            }
+        }
+        impl<T> Default for $name<T> {
+            fn default() -> Self {
+                Self::new()
+            }
+        }
+        impl<T> Clone for $name<T> {
+            fn clone(&self) -> Self {
+                *self
+            }
+        }
+        impl<T> Copy for $name<T> {}
+        impl<T> std::fmt::Debug for $name<T> {
+            fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                write!(f, stringify!($name))
+            }
+        }
+    };
+}
+stateless_heuristic!(MinLP);
+stateless_heuristic!(KeepThemAll);
+
+// ----------------------------------------------------------------------------
+/// Side constraints: a decorator which adds ad-hoc restrictions (forbidden
+/// decisions, fixed variables, precedences between values such as "city 7
+/// must be visited before city 12") to any problem without touching its model
+// ----------------------------------------------------------------------------
+/// The state of a constrained problem: the state of the wrapped problem along
+/// with the precedences whose `before` value has already been assigned
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ConstrainedState<S> {
+    pub inner: S,
+    /// reached[i] is true iff the `before` value of the i-th precedence has
+    /// been assigned by some decision
+    reached: Vec<bool>,
+}
+impl<S: StateSize> StateSize for ConstrainedState<S> {
+    fn state_size(&self) -> usize {
+        self.inner.state_size() + std::mem::size_of::<Vec<bool>>() + self.reached.capacity()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ConstrainedProblem<P> {
+    problem: P,
+    forbidden: FxHashSet<Decision>,
+    forced: FxHashMap<Var, isize>,
+    /// The (before, after) pairs of values: no decision may assign the value
+    /// `after` before some decision has assigned the value `before`
+    precedences: Vec<(isize, isize)>,
+}
+impl<P: Problem> ConstrainedProblem<P> {
+    pub fn new(problem: P) -> Self {
+        Self {
+            problem,
+            forbidden: FxHashSet::default(),
+            forced: FxHashMap::default(),
+            precedences: vec![],
+        }
+    }
+    /// Forbids the given value for the given variable
+    pub fn forbid(mut self, var: Var, val: isize) -> Self {
+        self.forbidden.insert(Decision::new(var, val));
+        self
+    }
+    /// Fixes the value of the given variable
+    pub fn force(mut self, var: Var, val: isize) -> Self {
+        self.forced.insert(var, val);
+        self
+    }
+    /// Requires the value `before` to be assigned prior to the value `after`
+    pub fn precede(mut self, before: isize, after: isize) -> Self {
+        self.precedences.push((before, after));
+        self
+    }
+    /// Returns the wrapped problem
+    pub fn problem(&self) -> &P {
+        &self.problem
+    }
+    /// Explains which side constraint (if any) rules the decision out
+    fn side_violation(&self, state: &ConstrainedState<P::State>, decision: Decision) -> Option<String> {
+        if let Some(val) = self.forced.get(&decision.var) {
+            if *val != decision.val {
+                return Some(format!("x{} is fixed to {}", decision.var.id(), val));
+            }
+        }
+        if self.forbidden.contains(&decision) {
+            return Some(format!("{} is forbidden for x{}", decision.val, decision.var.id()));
+        }
+        self.precedences.iter().zip(state.reached.iter())
+            .find(|((_, after), reached)| *after == decision.val && !**reached)
+            .map(|((before, after), _)| format!("{} must be assigned before {}", before, after))
+    }
+}
+
+impl<P: Problem> Problem for ConstrainedProblem<P> {
+    type State = ConstrainedState<P::State>;
+    type Cost = P::Cost;
+
+    fn nb_vars(&self) -> usize {
+        self.problem.nb_vars()
+    }
+    fn initial_state(&self) -> Self::State {
+        ConstrainedState {
+            inner: self.problem.initial_state(),
+            reached: vec![false; self.precedences.len()],
+        }
+    }
+    fn initial_value(&self) -> Self::Cost {
+        self.problem.initial_value()
+    }
+    fn for_each_in_domain(&self, state: &Self::State, var: Var, mut f: impl FnMut(Decision)) {
+        self.problem.for_each_in_domain(&state.inner, var, |d| {
+            if self.side_violation(state, d).is_none() {
+                f(d)
+            }
+        })
+    }
+    fn transition(&self, state: &Self::State, decision: Decision) -> Self::State {
+        let mut reached = state.reached.clone();
+        for ((before, _), r) in self.precedences.iter().zip(reached.iter_mut()) {
+            *r |= *before == decision.val;
+        }
+        ConstrainedState {
+            inner: self.problem.transition(&state.inner, decision),
+            reached,
+        }
+    }
+    fn transition_cost(&self, state: &Self::State, decision: Decision) -> Self::Cost {
+        self.problem.transition_cost(&state.inner, decision)
+    }
+    // the side constraints can only make the remaining cost larger
+    fn estimate(&self, state: &Self::State) -> Self::Cost {
+        self.problem.estimate(&state.inner)
+    }
+    fn state_summary(&self, state: &Self::State) -> String {
+        self.problem.state_summary(&state.inner)
+    }
+    fn violation_reason(&self, state: &Self::State, decision: Decision) -> String {
+        if self.problem.is_in_domain(&state.inner, decision) {
+            self.side_violation(state, decision)
+                .unwrap_or_else(|| format!("{} is not in the domain of x{}", decision.val, decision.var.id()))
+        } else {
+            self.problem.violation_reason(&state.inner, decision)
+        }
+    }
+    fn decision_details(&self, state: &Self::State, decision: Decision) -> String {
+        self.problem.decision_details(&state.inner, decision)
+    }
+}
+
+/// An adapter which lets the variable ordering, node selection heuristic,
+/// state distance, rollout policy or constraints of some problem be used with
+/// the constrained version of that problem (e.g. `Constrained(LeftToRight)`,
+/// or `Propagator::new().with(Constrained(propagator))` to reuse a whole
+/// propagator).
+#[derive(Debug, Clone, Copy)]
+pub struct Constrained<T>(pub T);
+
+impl<V: VariableOrdering> VariableOrdering for Constrained<V> {
+    type State = ConstrainedState<V::State>;
+
+    fn next(&self, states: &mut dyn Iterator<Item = &Self::State>) -> Option<Var> {
+        self.0.next(&mut states.map(|s| &s.inner))
+    }
+}
+impl<H: NodeSelectionHeuristic> NodeSelectionHeuristic for Constrained<H> {
+    type State = ConstrainedState<H::State>;
+
+    fn is_mandatory<S: NodeSource<State = Self::State>>(
+        &self,
+        dd: &S,
+        node: &S::Node,
+        last_var: Var,
+        best_sol: &Option<Solution>,
+    ) -> bool {
+        self.0.is_mandatory(&InnerSource(dd), &InnerNode(node), last_var, best_sol)
+    }
+    fn compare<S: NodeSource<State = Self::State>>(&self, dd: &S, na: &S::Node, nb: &S::Node) -> Ordering {
+        self.0.compare(&InnerSource(dd), &InnerNode(na), &InnerNode(nb))
+    }
+}
+impl<D: StateDistance> StateDistance for Constrained<D> {
+    type State = ConstrainedState<D::State>;
+
+    fn distance(&self, a: &Self::State, b: &Self::State) -> f64 {
+        self.0.distance(&a.inner, &b.inner)
+    }
+}
+/// The wrapped policy knows nothing about the side constraints: the diagram
+/// rejects the decisions that violate them (which ends the rollout).
+impl<R: RolloutPolicy> RolloutPolicy for Constrained<R> {
+    type State = ConstrainedState<R::State>;
+
+    fn choose(&self, state: &Self::State, var: Var) -> Option<Decision> {
+        self.0.choose(&state.inner, var)
+    }
+}
+impl<C: Constraint> Constraint for Constrained<C> {
+    type State = ConstrainedState<C::State>;
+
+    fn allows(&self, state: &Self::State, decision: Decision) -> bool {
+        self.0.allows(&state.inner, decision)
+    }
+    fn is_dead_end(&self, state: &Self::State) -> bool {
+        self.0.is_dead_end(&state.inner)
+    }
+}
+
+/// Views a source of constrained nodes as a source of nodes of the wrapped
+/// problem
+struct InnerSource<'a, S>(&'a S);
+struct InnerNode<'a, N>(&'a N);
+
+impl<'a, T, N> SelectableNode for InnerNode<'a, N>
+where
+    N: SelectableNode<State = ConstrainedState<T>>,
+{
+    type State = T;
+    type Cost = N::Cost;
+
+    fn state(&self) -> &T {
+        &self.0.state().inner
+    }
+    fn value(&self) -> N::Cost {
+        self.0.value()
+    }
+    fn estimate(&self) -> N::Cost {
+        self.0.estimate()
+    }
+}
+impl<'a, T, S> NodeSource for InnerSource<'a, S>
+where
+    S: NodeSource<State = ConstrainedState<T>>,
+{
+    type State = T;
+    type Node = InnerNode<'a, S::Node>;
+    type Path<'b> = S::Path<'a> where Self: 'b;
+
+    fn path(&self, node: &Self::Node) -> Self::Path<'_> {
+        self.0.path(node.0)
+    }
+}
+
 // ----------------------------------------------------------------------------
 // Boilerplate to make any reference to a problem into a problem itself
 // ----------------------------------------------------------------------------
@@ -296,6 +793,7 @@ where
     D: Deref<Target = P>,
 {
     type State = P::State;
+    type Cost = P::Cost;
 
     fn nb_vars(&self) -> usize {
         self.deref().nb_vars()
@@ -303,7 +801,7 @@ where
     fn initial_state(&self) -> Self::State {
         self.deref().initial_state()
     }
-    fn initial_value(&self) -> isize {
+    fn initial_value(&self) -> Self::Cost {
         self.deref().initial_value()
     }
     fn for_each_in_domain(&self, state: &Self::State, var: Var, f: impl FnMut(Decision)) {
@@ -312,13 +810,179 @@ where
     fn transition(&self, state: &Self::State, decision: Decision) -> Self::State {
         self.deref().transition(state, decision)
     }
-    fn transition_cost(&self, state: &Self::State, decision: Decision) -> isize {
+    fn transition_cost(&self, state: &Self::State, decision: Decision) -> Self::Cost {
         self.deref().transition_cost(state, decision)
     }
-    fn estimate(&self, state: &Self::State) -> isize {
+    fn estimate(&self, state: &Self::State) -> Self::Cost {
         self.deref().estimate(state)
     }
-    fn evaluate(&self, var_ord: &dyn VariableOrdering<State=Self::State>, sol: &Solution) -> isize {
+    fn evaluate(&self, var_ord: &dyn VariableOrdering<State=Self::State>, sol: &Solution) -> Self::Cost {
         self.deref().evaluate(var_ord, sol)
     }
+    fn details(&self, var_ord: &dyn VariableOrdering<State=Self::State>, sol: &Solution) -> Vec<DecisionRecord<Self::Cost>> {
+        self.deref().details(var_ord, sol)
+    }
+    fn check(&self, var_ord: &dyn VariableOrdering<State=Self::State>, sol: &Solution) -> CheckReport<Self::Cost> {
+        self.deref().check(var_ord, sol)
+    }
+    fn is_in_domain(&self, state: &Self::State, decision: Decision) -> bool {
+        self.deref().is_in_domain(state, decision)
+    }
+    fn state_summary(&self, state: &Self::State) -> String {
+        self.deref().state_summary(state)
+    }
+    fn violation_reason(&self, state: &Self::State, decision: Decision) -> String {
+        self.deref().violation_reason(state, decision)
+    }
+    fn decision_details(&self, state: &Self::State, decision: Decision) -> String {
+        self.deref().decision_details(state, decision)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{atomic::AtomicBool, Arc};
+
+    use crate::testing::{InOrder, Knapsack, KnapsackState};
+    use crate::{Mdd, SimpleMddBuilder};
+
+    use super::{
+        CheckReport, Constrained, ConstrainedProblem, Decision, DecisionRecord, MinLP, Problem,
+        RolloutPolicy, Solution, Var, VariableOrdering,
+    };
+
+    /// A knapsack that forbids taking the first item even though it belongs
+    /// to the domain: only `is_in_domain` and `violation_reason` tell so.
+    struct Picky(Knapsack);
+    impl Problem for Picky {
+        type State = KnapsackState;
+        type Cost = isize;
+
+        fn nb_vars(&self) -> usize {
+            self.0.nb_vars()
+        }
+        fn initial_state(&self) -> KnapsackState {
+            self.0.initial_state()
+        }
+        fn initial_value(&self) -> isize {
+            self.0.initial_value()
+        }
+        fn for_each_in_domain(&self, state: &KnapsackState, var: Var, f: impl FnMut(Decision)) {
+            self.0.for_each_in_domain(state, var, f)
+        }
+        fn transition(&self, state: &KnapsackState, decision: Decision) -> KnapsackState {
+            self.0.transition(state, decision)
+        }
+        fn transition_cost(&self, state: &KnapsackState, decision: Decision) -> isize {
+            self.0.transition_cost(state, decision)
+        }
+        fn is_in_domain(&self, state: &KnapsackState, decision: Decision) -> bool {
+            decision != Decision::new(Var::new(0), 1) && self.0.is_in_domain(state, decision)
+        }
+        fn violation_reason(&self, _state: &KnapsackState, _decision: Decision) -> String {
+            "the first item is never taken".to_string()
+        }
+    }
+
+    fn check<P: Problem>(problem: P, var_ord: &dyn VariableOrdering<State = P::State>, sol: &Solution) -> (CheckReport<P::Cost>, Vec<DecisionRecord<P::Cost>>) {
+        (problem.check(var_ord, sol), problem.details(var_ord, sol))
+    }
+
+    #[test]
+    fn a_reference_to_a_problem_checks_the_solutions_like_the_problem() {
+        let problem = Picky(Knapsack::new(10, &[3, 4], &[5, 6]));
+        let var_ord = InOrder(2);
+        let good    = Solution::from([Decision::new(Var::new(0), 0), Decision::new(Var::new(1), 1)].into_iter());
+        let bad     = Solution::from([Decision::new(Var::new(0), 1), Decision::new(Var::new(1), 1)].into_iter());
+
+        let (report, records) = check(&problem, &var_ord, &good);
+        assert!(report.is_feasible());
+        assert_eq!(-6, report.cost);
+        assert!(records.iter().all(|r| r.violation.is_none()));
+
+        for (report, records) in [check(&problem, &var_ord, &bad), check(Box::new(&problem), &var_ord, &bad)] {
+            assert_eq!(1, report.violations.len());
+            assert_eq!("the first item is never taken", report.violations[0].reason);
+            assert_eq!(Some("the first item is never taken"), records[0].violation.as_deref());
+            assert!(records[1].violation.is_none());
+        }
+        assert!(!(&&problem).is_in_domain(&problem.initial_state(), Decision::new(Var::new(0), 1)));
+    }
+
+    fn domain<P: Problem>(problem: &P, state: &P::State, var: usize) -> Vec<isize> {
+        let mut values = vec![];
+        problem.for_each_in_domain(state, Var::new(var), |d| values.push(d.val));
+        values
+    }
+
+    #[test]
+    fn the_side_constraints_filter_the_domains_and_explain_the_violations() {
+        let problem = ConstrainedProblem::new(Knapsack::new(10, &[3, 4, 5], &[5, 6, 7]))
+            .force(Var::new(0), 1)
+            .forbid(Var::new(1), 1)
+            .precede(1, 0);
+        let root = problem.initial_state();
+        assert_eq!(vec![1], domain(&problem, &root, 0));
+        // the value 0 may only be assigned once some item has been taken
+        assert_eq!(Vec::<isize>::new(), domain(&problem, &root, 1));
+        assert_eq!(vec![1], domain(&problem, &root, 2));
+
+        let taken = problem.transition(&root, Decision::new(Var::new(0), 1));
+        assert_eq!(vec![0], domain(&problem, &taken, 1));
+        assert_eq!(vec![0, 1], domain(&problem, &taken, 2));
+
+        assert_eq!("x0 is fixed to 1", problem.violation_reason(&root, Decision::new(Var::new(0), 0)));
+        assert_eq!("1 is forbidden for x1", problem.violation_reason(&taken, Decision::new(Var::new(1), 1)));
+        assert_eq!("1 must be assigned before 0", problem.violation_reason(&root, Decision::new(Var::new(2), 0)));
+        // the reasons of the wrapped problem come first
+        let full = problem.transition(&taken, Decision::new(Var::new(2), 1));
+        assert_eq!("1 is not in the domain of x1", problem.violation_reason(&full, Decision::new(Var::new(1), 1)));
+
+        let var_ord = Constrained(InOrder(3));
+        let sol = Solution::from([0, 1, 1].into_iter().enumerate().map(|(i, v)| Decision::new(Var::new(i), v)));
+        let report = problem.check(&var_ord, &sol);
+        assert_eq!(2, report.violations.len());
+        assert_eq!(vec![0, 1], report.violations.iter().map(|v| v.depth).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn a_constrained_problem_is_solved_with_the_adapted_heuristics() {
+        let knapsack = Knapsack::small();
+        let problem  = ConstrainedProblem::new(knapsack.clone())
+            .force(Var::new(7), 0)
+            .forbid(Var::new(3), 1);
+        // forcing an item out is the same as making it too heavy to fit
+        let mut weight = knapsack.weight.clone();
+        weight[3] = knapsack.capacity + 1;
+        weight[7] = knapsack.capacity + 1;
+        let expected = Knapsack::new(knapsack.capacity, &weight, &knapsack.profit).brute_force();
+        assert!(expected > knapsack.brute_force());
+
+        let mut mdd = SimpleMddBuilder::default()
+            .problem(&problem)
+            .var_ordering(Constrained(InOrder(knapsack.nb_vars())))
+            .node_selection(Constrained(MinLP::new()))
+            .kill_switch(Arc::new(AtomicBool::new(false)))
+            .proba(0.0)
+            .build()
+            .unwrap();
+        assert_eq!(Some(expected), mdd.exact());
+        let sol = mdd.get_best_solution().expect("a solution");
+        assert!(problem.check(&Constrained(InOrder(knapsack.nb_vars())), &sol).is_feasible());
+        assert_eq!((0, 0), (sol[Var::new(3)], sol[Var::new(7)]));
+
+        // the rollout adapter ignores the side constraints: the diagram rejects
+        // the decisions that violate them
+        struct TakeIt;
+        impl RolloutPolicy for TakeIt {
+            type State = KnapsackState;
+
+            fn choose(&self, _state: &KnapsackState, var: Var) -> Option<Decision> {
+                Some(Decision::new(var, 1))
+            }
+        }
+        let chosen = Constrained(TakeIt).choose(&problem.initial_state(), Var::new(7));
+        assert_eq!(Some(Decision::new(Var::new(7), 1)), chosen);
+        assert!(!problem.is_in_domain(&problem.initial_state(), chosen.unwrap()));
+    }
 }
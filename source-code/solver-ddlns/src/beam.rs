@@ -0,0 +1,486 @@
+//! This module implements a standalone anytime beam search solver (no LNS is
+//! involved). It is meant to be used as a baseline to compare `MddLns` with,
+//! and to quickly produce initial solutions for the problems which have no
+//! greedy heuristic. Two strategies are available:
+//!
+//! * Doubling: a series of beam searches of width 1, 2, 4, ... is performed.
+//!   The search stops when a beam did not need to drop any node: in that
+//!   case, it has explored the complete (deduplicated) state space and the
+//!   best solution is optimal.
+//! * Column: the anytime column search. It is a complete search which expands
+//!   `width` nodes of the deepest non empty layer at a time, and which keeps
+//!   the nodes that were not expanded in per-layer open lists. It backtracks
+//!   to these open lists when it reaches the end of a column.
+//!
+//! In both cases, the nodes of a layer are ranked with the node selection
+//! heuristic of the model, the states that cannot improve the incumbent (as
+//! per their estimate) are pruned, and each improving solution is published
+//! as soon as it is found.
+
+use std::{
+    hash::Hash,
+    marker::PhantomData,
+    rc::Rc,
+    sync::{atomic::AtomicBool, Arc},
+    time::{Duration, Instant},
+};
+
+use derive_builder::Builder;
+use rustc_hash::FxHashMap;
+
+use crate::{
+    Cost, Decision, NodeSelectionHeuristic, NodeSource, Problem, Propagator, ResolutionOutcome,
+    ResolutionStatus, SelectableNode, Solution, Var, VariableOrdering,
+};
+
+/// The strategy followed by the beam search solver
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BeamStrategy {
+    /// Restart with a beam twice as wide after each complete beam search
+    Doubling,
+    /// Anytime column search with a beam of constant width
+    Column,
+}
+
+// ----------------------------------------------------------------------------
+// Anytime beam search implem from problem description
+// ----------------------------------------------------------------------------
+#[derive(Debug, Builder)]
+pub struct BeamSearch<P, V, N>
+where
+    P: Problem,
+    V: VariableOrdering<State = P::State>,
+    N: NodeSelectionHeuristic<State = P::State>,
+    P::State: PartialEq + Eq + Hash,
+{
+    /// The problem that must be solved
+    problem: P,
+    /// The variable ordering that will be imposed on the problem
+    var_ordering: V,
+    /// The heuristic used to rank the nodes of a layer
+    node_selection: N,
+
+    /// When did we start working on this instance ?
+    start_time: Instant,
+    /// An atomic boolean acting as a kill switch. Whenever this flag turns true,
+    /// the progress must stop and return the best known solution asap.
+    kill_switch: Arc<AtomicBool>,
+    /// The width of the (first) beam
+    #[builder(default = "1")]
+    width: usize,
+    #[builder(default = "BeamStrategy::Doubling")]
+    strategy: BeamStrategy,
+    /// An optional initial solution and its value. The value is used as an
+    /// initial bound to prune the nodes.
+    #[builder(default)]
+    initial_val: Option<P::Cost>,
+    #[builder(default)]
+    initial_sol: Option<Solution>,
+    /// An optional set of constraints used to filter the domains and to
+    /// discard the dead-end states
+    #[builder(default, setter(strip_option))]
+    propagator: Option<Arc<Propagator<P::State>>>,
+}
+
+/// The nodes developed by the search. Only the parent links are stored: they
+/// are needed to rebuild the solutions and the paths (for the node selection).
+struct Beam<S, C> {
+    parents: Vec<Option<(usize, Decision)>>,
+    _phantom: PhantomData<(S, C)>,
+}
+
+/// A node which is waiting to be expanded
+struct Candidate<S, C> {
+    id: usize,
+    state: Rc<S>,
+    value: C,
+    estimate: C,
+}
+
+/// The best solution found so far
+struct Incumbent<C> {
+    value: C,
+    solution: Option<Solution>,
+    time: Option<Duration>,
+}
+
+impl<P, V, N> BeamSearch<P, V, N>
+where
+    P: Problem,
+    V: VariableOrdering<State = P::State>,
+    N: NodeSelectionHeuristic<State = P::State>,
+    P::State: PartialEq + Eq + Hash,
+{
+    pub fn minimize(&self) -> ResolutionOutcome<P::Cost> {
+        self.minimize_with_cond(|_, _| false)
+    }
+
+    /// Same as minimize, but `f` is called with each improving solution as
+    /// soon as it is found. The search stops when `f` returns true.
+    pub fn minimize_with_cond<F>(&self, mut f: F) -> ResolutionOutcome<P::Cost>
+    where
+        F: FnMut(P::Cost, &Solution) -> bool,
+    {
+        let mut incumbent = Incumbent {
+            value: self.initial_val.unwrap_or(P::Cost::MAX),
+            solution: None,
+            time: None,
+        };
+        let proved = match self.strategy {
+            BeamStrategy::Doubling => self.doubling(&mut incumbent, &mut f),
+            BeamStrategy::Column => self.column(&mut incumbent, &mut f),
+        };
+
+        let improved = incumbent.solution.is_some();
+        let status   = if proved {
+            ResolutionStatus::Closed { improved }
+        } else {
+            ResolutionStatus::Open { improved }
+        };
+        let time_to_prove = if proved { Some(self.start_time.elapsed()) } else { None };
+        if improved {
+            ResolutionOutcome {
+                status,
+                best_value: Some(incumbent.value),
+                best_sol: incumbent.solution,
+                time_to_best: incumbent.time,
+                time_to_prove,
+            }
+        } else {
+            ResolutionOutcome {
+                status,
+                best_value: self.initial_val,
+                best_sol: self.initial_sol.clone(),
+                time_to_best: None,
+                time_to_prove,
+            }
+        }
+    }
+
+    /// Performs beam searches of increasing width. Returns true iff the
+    /// optimality of the incumbent has been proved.
+    fn doubling<F>(&self, incumbent: &mut Incumbent<P::Cost>, f: &mut F) -> bool
+    where
+        F: FnMut(P::Cost, &Solution) -> bool,
+    {
+        let mut width = self.width.max(1);
+        let mut beam  = Beam::default();
+        let mut seen  = FxHashMap::default();
+        loop {
+            beam.clear();
+            seen.clear();
+            let mut layer = self.root(&mut beam, incumbent).into_iter().collect::<Vec<_>>();
+            let mut complete = true;
+
+            while !layer.is_empty() {
+                if self.killed() {
+                    return false;
+                }
+                let Some(var) = self.var_ordering.next(&mut layer.iter().map(|c| c.state.as_ref())) else {
+                    if self.terminal(&beam, &layer, incumbent, f) {
+                        return false;
+                    }
+                    break;
+                };
+                let mut next = self.expand(&mut beam, &layer, var, incumbent, &mut seen);
+                if next.len() > width {
+                    complete = false;
+                    self.select(&beam, &mut next, width, incumbent);
+                    next.truncate(width);
+                }
+                layer = next;
+            }
+
+            if complete {
+                return true;
+            }
+            width = width.saturating_mul(2);
+        }
+    }
+
+    /// Performs an anytime column search. Returns true iff the optimality of
+    /// the incumbent has been proved.
+    fn column<F>(&self, incumbent: &mut Incumbent<P::Cost>, f: &mut F) -> bool
+    where
+        F: FnMut(P::Cost, &Solution) -> bool,
+    {
+        let width    = self.width.max(1);
+        let mut beam = Beam::default();
+        let mut seen = FxHashMap::default();
+        // the open list of each layer
+        let mut open = vec![self.root(&mut beam, incumbent).into_iter().collect::<Vec<_>>()];
+
+        while let Some(depth) = open.iter().rposition(|l| !l.is_empty()) {
+            if self.killed() {
+                return false;
+            }
+            // drop the nodes which have been superseded or cannot improve the
+            // incumbent anymore
+            open[depth].retain(|c| seen.get(&c.state).is_none_or(|v| *v >= c.value)
+                && c.value.saturating_add(c.estimate) < incumbent.value);
+
+            let mut layer = std::mem::take(&mut open[depth]);
+            if layer.len() > width {
+                self.select(&beam, &mut layer, width, incumbent);
+                open[depth] = layer.split_off(width);
+            }
+            if layer.is_empty() {
+                continue;
+            }
+
+            let Some(var) = self.var_ordering.next(&mut layer.iter().map(|c| c.state.as_ref())) else {
+                if self.terminal(&beam, &layer, incumbent, f) {
+                    return false;
+                }
+                continue;
+            };
+            let next = self.expand(&mut beam, &layer, var, incumbent, &mut seen);
+            if open.len() <= depth + 1 {
+                open.push(vec![]);
+            }
+            open[depth + 1].extend(next);
+        }
+        !self.killed()
+    }
+
+    /// Creates the root node (unless it cannot improve the incumbent)
+    fn root(&self, beam: &mut Beam<P::State, P::Cost>, incumbent: &Incumbent<P::Cost>) -> Option<Candidate<P::State, P::Cost>> {
+        let state    = self.problem.initial_state();
+        let value    = self.problem.initial_value();
+        let estimate = self.problem.estimate(&state);
+        if value.saturating_add(estimate) >= incumbent.value {
+            return None;
+        }
+        Some(Candidate { id: beam.push(None), state: Rc::new(state), value, estimate })
+    }
+
+    /// Expands all the nodes of the layer. The children which cannot improve
+    /// the incumbent are pruned, and so are those whose state has already been
+    /// reached with a better value.
+    fn expand(
+        &self,
+        beam: &mut Beam<P::State, P::Cost>,
+        layer: &[Candidate<P::State, P::Cost>],
+        var: Var,
+        incumbent: &Incumbent<P::Cost>,
+        seen: &mut FxHashMap<Rc<P::State>, P::Cost>,
+    ) -> Vec<Candidate<P::State, P::Cost>> {
+        let mut next = vec![];
+        for node in layer.iter() {
+            if seen.get(&node.state).is_some_and(|v| *v < node.value) {
+                continue;
+            }
+            self.for_each_in_domain(node.state.as_ref(), var, |decision| {
+                let state = self.problem.transition(node.state.as_ref(), decision);
+                if self.is_dead_end(&state) {
+                    return;
+                }
+                let cost  = self.problem.transition_cost(node.state.as_ref(), decision);
+                let value = node.value.saturating_add(cost);
+                if seen.get(&state).is_some_and(|v| *v <= value) {
+                    return;
+                }
+                let estimate = self.problem.estimate(&state);
+                if value.saturating_add(estimate) >= incumbent.value {
+                    return;
+                }
+                let state = Rc::new(state);
+                seen.insert(Rc::clone(&state), value);
+                let id = beam.push(Some((node.id, decision)));
+                next.push(Candidate { id, state, value, estimate });
+            });
+        }
+        // a state might have been reached several times: only keep its best node
+        next.retain(|c| seen.get(&c.state).is_none_or(|v| *v >= c.value));
+        next
+    }
+
+    /// Moves the `width` most promising nodes at the beginning of the layer:
+    /// first the mandatory ones, then the best ones as per the heuristic.
+    fn select(&self, beam: &Beam<P::State, P::Cost>, layer: &mut [Candidate<P::State, P::Cost>], width: usize, incumbent: &Incumbent<P::Cost>) {
+        let mut frontier = 0;
+        for i in 0..layer.len() {
+            // the root is never mandatory since it has no last variable
+            let mandatory = beam.parents[layer[i].id].is_some_and(|(_, decision)|
+                self.node_selection.is_mandatory(beam, &layer[i], decision.var, &incumbent.solution));
+            if mandatory {
+                layer.swap(i, frontier);
+                frontier += 1;
+            }
+        }
+        let rest = &mut layer[frontier..];
+        let nth  = width.saturating_sub(frontier);
+        if nth > 0 && nth < rest.len() {
+            rest.select_nth_unstable_by(nth - 1, |a, b| self.node_selection.compare(beam, a, b));
+        }
+    }
+
+    /// Updates the incumbent with the best node of a terminal layer. Returns
+    /// true iff the search must stop.
+    fn terminal<F>(&self, beam: &Beam<P::State, P::Cost>, layer: &[Candidate<P::State, P::Cost>], incumbent: &mut Incumbent<P::Cost>, f: &mut F) -> bool
+    where
+        F: FnMut(P::Cost, &Solution) -> bool,
+    {
+        let Some(best) = layer.iter().min_by_key(|c| c.value) else {
+            return false;
+        };
+        if best.value >= incumbent.value {
+            return false;
+        }
+        let mut decisions = beam.path(best).collect::<Vec<_>>();
+        decisions.reverse();
+        let solution = Solution::from(decisions.into_iter());
+
+        incumbent.value    = best.value;
+        incumbent.time     = Some(self.start_time.elapsed());
+        let stop           = f(best.value, &solution);
+        incumbent.solution = Some(solution);
+        stop
+    }
+
+    /// This function evaluates the kill switch
+    fn killed(&self) -> bool {
+        self.kill_switch.load(std::sync::atomic::Ordering::Relaxed)
+    }
+
+    /// Iterates over the domain of `var` (filtered by the propagator if any)
+    fn for_each_in_domain(&self, state: &P::State, var: Var, f: impl FnMut(Decision)) {
+        if let Some(propagator) = self.propagator.as_ref() {
+            propagator.for_each_in_domain(&self.problem, state, var, f)
+        } else {
+            self.problem.for_each_in_domain(state, var, f)
+        }
+    }
+
+    /// Tells whether the propagator (if any) detects the state is a dead end
+    fn is_dead_end(&self, state: &P::State) -> bool {
+        self.propagator.as_ref().is_some_and(|p| p.is_dead_end(state))
+    }
+}
+
+impl<S, C> Default for Beam<S, C> {
+    fn default() -> Self {
+        Self { parents: vec![], _phantom: PhantomData }
+    }
+}
+impl<S, C> Beam<S, C> {
+    fn clear(&mut self) {
+        self.parents.clear();
+    }
+    fn push(&mut self, parent: Option<(usize, Decision)>) -> usize {
+        self.parents.push(parent);
+        self.parents.len() - 1
+    }
+}
+
+impl<S, C: Cost> SelectableNode for Candidate<S, C> {
+    type State = S;
+    type Cost = C;
+
+    fn state(&self) -> &Self::State {
+        self.state.as_ref()
+    }
+
+    fn value(&self) -> C {
+        self.value
+    }
+
+    fn estimate(&self) -> C {
+        self.estimate
+    }
+}
+
+impl<S, C: Cost> NodeSource for Beam<S, C> {
+    type State = S;
+    type Node = Candidate<S, C>;
+    type Path<'a> = BeamPath<'a, S, C> where Self: 'a;
+
+    fn path(&self, node: &Self::Node) -> Self::Path<'_> {
+        BeamPath { beam: self, current: self.parents[node.id] }
+    }
+}
+
+/// This iterator walks the path from a node back to the root of the search.
+struct BeamPath<'a, S, C> {
+    beam: &'a Beam<S, C>,
+    current: Option<(usize, Decision)>,
+}
+impl<S, C> Iterator for BeamPath<'_, S, C> {
+    type Item = Decision;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (from, decision) = self.current?;
+        self.current = self.beam.parents[from];
+        Some(decision)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::{atomic::AtomicBool, Arc},
+        time::Instant,
+    };
+
+    use crate::testing::{InOrder, Knapsack, StepByStep, Walk};
+    use crate::{MinLP, Problem, ResolutionStatus, VariableOrdering};
+
+    use super::{BeamSearch, BeamSearchBuilder, BeamStrategy};
+
+    fn solver<P, V>(problem: P, var_ordering: V, strategy: BeamStrategy, width: usize) -> BeamSearch<P, V, MinLP<P::State>>
+    where
+        P: Problem + Clone,
+        V: VariableOrdering<State = P::State> + Clone,
+    {
+        BeamSearchBuilder::default()
+            .problem(problem)
+            .var_ordering(var_ordering)
+            .node_selection(MinLP::new())
+            .start_time(Instant::now())
+            .kill_switch(Arc::new(AtomicBool::new(false)))
+            .strategy(strategy)
+            .width(width)
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn both_strategies_prove_the_optimum() {
+        let knapsack = Knapsack::small();
+        let walk     = Walk::new(10, 4);
+        for strategy in [BeamStrategy::Doubling, BeamStrategy::Column] {
+            for width in [1, 3] {
+                let outcome = solver(&knapsack, InOrder(knapsack.nb_vars()), strategy, width).minimize();
+                assert!(matches!(outcome.status, ResolutionStatus::Closed { improved: true }), "{:?} {}", strategy, width);
+                assert_eq!(Some(knapsack.brute_force()), outcome.best_value, "{:?} {}", strategy, width);
+                let sol = outcome.best_sol.expect("a solution");
+                assert_eq!(knapsack.brute_force(), knapsack.evaluate(&InOrder(knapsack.nb_vars()), &sol));
+
+                let outcome = solver(&walk, StepByStep(10), strategy, width).minimize();
+                assert!(matches!(outcome.status, ResolutionStatus::Closed { improved: true }), "{:?} {}", strategy, width);
+                assert_eq!(Some(walk.optimum()), outcome.best_value, "{:?} {}", strategy, width);
+            }
+        }
+    }
+
+    #[test]
+    fn the_published_solutions_keep_improving_until_the_caller_stops() {
+        let problem = Knapsack::small();
+        for strategy in [BeamStrategy::Doubling, BeamStrategy::Column] {
+            let mut published = vec![];
+            let outcome = solver(&problem, InOrder(problem.nb_vars()), strategy, 1)
+                .minimize_with_cond(|value, sol| {
+                    assert_eq!(value, problem.evaluate(&InOrder(problem.nb_vars()), sol));
+                    published.push(value);
+                    false
+                });
+            assert!(published.windows(2).all(|w| w[1] < w[0]), "{:?} {:?}", strategy, published);
+            assert_eq!(outcome.best_value, published.last().copied());
+
+            // the caller may stop the search as soon as a solution is found
+            let outcome = solver(&problem, InOrder(problem.nb_vars()), strategy, 1).minimize_with_cond(|_, _| true);
+            assert!(matches!(outcome.status, ResolutionStatus::Open { improved: true }));
+            assert_eq!(Some(published[0]), outcome.best_value);
+        }
+    }
+}
@@ -0,0 +1,268 @@
+//! This module defines the type of the costs manipulated by the solvers. Each
+//! problem declares the cost type that suits it best: a plain integer type
+//! (`isize`, `i64`, `i128`) or a fixed point decimal (`Fixed<D>`) when the
+//! instance data is expressed with a given number of decimals. The latter
+//! lets the solvers reason with exact integers while still reporting the
+//! costs with the precision of the original data.
+
+use std::{
+    fmt::{Debug, Display},
+    hash::Hash,
+    ops::{Add, AddAssign, Sub},
+    str::FromStr,
+};
+
+use serde::{de::DeserializeOwned, Deserialize, Deserializer, Serialize, Serializer};
+
+// ----------------------------------------------------------------------------
+/// Cost
+// ----------------------------------------------------------------------------
+pub trait Cost:
+    Copy
+    + Debug
+    + Display
+    + Default
+    + Eq
+    + Ord
+    + Hash
+    + Add<Output = Self>
+    + AddAssign
+    + Sub<Output = Self>
+    + Serialize
+    + DeserializeOwned
+{
+    /// The neutral element of the addition
+    const ZERO: Self;
+    /// The smallest cost. It is used as the trivial (absent) estimate
+    const MIN: Self;
+    /// The largest cost. It stands for an infinite (infeasible) cost
+    const MAX: Self;
+
+    /// Adds two costs, saturating at the bounds instead of overflowing
+    fn saturating_add(self, rhs: Self) -> Self;
+    /// Subtracts two costs, saturating at the bounds instead of overflowing
+    fn saturating_sub(self, rhs: Self) -> Self;
+    /// An approximation of the cost as a float (e.g. to weight an estimate)
+    fn to_f64(self) -> f64;
+    /// The cost closest to the given float (saturating at the bounds)
+    fn from_f64(value: f64) -> Self;
+}
+
+macro_rules! integer_cost {
+    ($t: ty) => {
+        impl Cost for $t {
+            const ZERO: Self = 0;
+            const MIN: Self = <$t>::MIN;
+            const MAX: Self = <$t>::MAX;
+
+            fn saturating_add(self, rhs: Self) -> Self {
+                <$t>::saturating_add(self, rhs)
+            }
+            fn saturating_sub(self, rhs: Self) -> Self {
+                <$t>::saturating_sub(self, rhs)
+            }
+            fn to_f64(self) -> f64 {
+                self as f64
+            }
+            fn from_f64(value: f64) -> Self {
+                // float to int casts saturate
+                value as $t
+            }
+        }
+    };
+}
+integer_cost!(isize);
+integer_cost!(i64);
+integer_cost!(i128);
+
+// ----------------------------------------------------------------------------
+/// Fixed point decimal having `D` digits after the decimal point. It is stored
+/// as an integer number of 10^-D units, hence all operations are exact.
+// ----------------------------------------------------------------------------
+#[derive(Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Fixed<const D: u32>(i64);
+
+impl<const D: u32> Fixed<D> {
+    /// The number of units in one
+    pub const SCALE: i64 = 10_i64.pow(D);
+
+    /// Creates a decimal from its number of 10^-D units
+    pub const fn from_units(units: i64) -> Self {
+        Self(units)
+    }
+    /// Returns the number of 10^-D units of this decimal
+    pub const fn units(self) -> i64 {
+        self.0
+    }
+}
+
+impl<const D: u32> Add for Fixed<D> {
+    type Output = Self;
+    fn add(self, rhs: Self) -> Self {
+        Self(self.0 + rhs.0)
+    }
+}
+impl<const D: u32> AddAssign for Fixed<D> {
+    fn add_assign(&mut self, rhs: Self) {
+        self.0 += rhs.0
+    }
+}
+impl<const D: u32> Sub for Fixed<D> {
+    type Output = Self;
+    fn sub(self, rhs: Self) -> Self {
+        Self(self.0 - rhs.0)
+    }
+}
+
+impl<const D: u32> Cost for Fixed<D> {
+    const ZERO: Self = Self(0);
+    const MIN: Self = Self(i64::MIN);
+    const MAX: Self = Self(i64::MAX);
+
+    fn saturating_add(self, rhs: Self) -> Self {
+        Self(self.0.saturating_add(rhs.0))
+    }
+    fn saturating_sub(self, rhs: Self) -> Self {
+        Self(self.0.saturating_sub(rhs.0))
+    }
+    fn to_f64(self) -> f64 {
+        self.0 as f64 / Self::SCALE as f64
+    }
+    fn from_f64(value: f64) -> Self {
+        Self((value * Self::SCALE as f64).round() as i64)
+    }
+}
+
+impl<const D: u32> Display for Fixed<D> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let sign  = if self.0 < 0 { "-" } else { "" };
+        let units = self.0.unsigned_abs();
+        let scale = Self::SCALE as u64;
+        let text  = if D == 0 {
+            format!("{}{}", sign, units)
+        } else {
+            format!("{}{}.{:0width$}", sign, units / scale, units % scale, width = D as usize)
+        };
+        f.pad(&text)
+    }
+}
+
+// the raw number of units is meaningless in the debug output
+impl<const D: u32> Debug for Fixed<D> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        Display::fmt(self, f)
+    }
+}
+
+/// A decimal cannot be parsed when it is not a number, when it has more than
+/// `D` digits after the decimal point or when it does not fit in the units
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum ParseFixedError {
+    #[error("'{0}' is not a decimal number")]
+    Malformed(String),
+    #[error("{0} has more than {1} decimals")]
+    TooPrecise(String, u32),
+    #[error("{0} is too large")]
+    Overflow(String),
+}
+impl<const D: u32> FromStr for Fixed<D> {
+    type Err = ParseFixedError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let (negative, digits) = match s.strip_prefix('-') {
+            Some(rest) => (true, rest),
+            None       => (false, s.strip_prefix('+').unwrap_or(s)),
+        };
+        let (int, frac) = match digits.split_once('.') {
+            Some((int, frac)) => (int, Some(frac)),
+            None              => (digits, None),
+        };
+        // both "" and "." would otherwise read as zero, and so would "1.". The
+        // digits are checked by hand since parsing an integer would accept a
+        // second sign (as in "-+5" or "1.+5").
+        let is_number = |digits: &str| !digits.is_empty() && digits.bytes().all(|b| b.is_ascii_digit());
+        if !is_number(int) || !frac.is_none_or(is_number) {
+            return Err(ParseFixedError::Malformed(s.to_string()));
+        }
+        // the trailing zeroes carry no precision
+        let frac = frac.unwrap_or("").trim_end_matches('0');
+        if frac.len() > D as usize {
+            return Err(ParseFixedError::TooPrecise(s.to_string(), D));
+        }
+        // the units are computed on 128 bits so that i64::MIN can be parsed
+        let overflow = || ParseFixedError::Overflow(s.to_string());
+        let int      = int.parse::<i128>().map_err(|_| overflow())?;
+        let frac     = format!("{:0<width$}", frac, width = D as usize);
+        let frac     = if frac.is_empty() { 0 } else { frac.parse::<i128>().map_err(|_| overflow())? };
+        let units    = int.checked_mul(Self::SCALE as i128)
+            .and_then(|int| int.checked_add(frac))
+            .map(|units| if negative { -units } else { units })
+            .and_then(|units| i64::try_from(units).ok())
+            .ok_or_else(overflow)?;
+        Ok(Self(units))
+    }
+}
+
+// decimals are exchanged as strings so as to preserve their precision
+impl<const D: u32> Serialize for Fixed<D> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+impl<'de, const D: u32> Deserialize<'de> for Fixed<D> {
+    fn deserialize<De: Deserializer<'de>>(deserializer: De) -> Result<Self, De::Error> {
+        let text = String::deserialize(deserializer)?;
+        text.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Fixed, ParseFixedError};
+
+    type Fixed5 = Fixed<5>;
+
+    fn units(text: &str) -> Result<i64, ParseFixedError> {
+        text.parse::<Fixed5>().map(Fixed5::units)
+    }
+
+    #[test]
+    fn it_parses_decimals_with_a_single_optional_sign() {
+        assert_eq!(Ok(123_450_000), units("1234.5"));
+        assert_eq!(Ok(500_000), units("+5"));
+        assert_eq!(Ok(-500_000), units("-5"));
+        assert_eq!(Ok(-1), units("-0.00001"));
+        assert_eq!(Ok(100_000), units(" 1.0000000 "));
+    }
+
+    #[test]
+    fn it_rejects_malformed_decimals() {
+        for text in ["", ".", "-", "1.", ".5", "-+5", "+-5", "++5", "1.+5", "1.-5", "1e5", "1,5", "0x10"] {
+            assert_eq!(Err(ParseFixedError::Malformed(text.to_string())), units(text), "'{}'", text);
+        }
+    }
+
+    #[test]
+    fn it_rejects_too_many_decimals() {
+        assert_eq!(Err(ParseFixedError::TooPrecise("1.000001".to_string(), 5)), units("1.000001"));
+        // the trailing zeroes carry no precision
+        assert_eq!(Ok(100_001), units("1.0000100"));
+    }
+
+    #[test]
+    fn it_parses_values_up_to_the_edge_of_overflow() {
+        assert_eq!(Ok(i64::MAX), units("92233720368547.75807"));
+        assert_eq!(Ok(i64::MIN), units("-92233720368547.75808"));
+        for text in ["92233720368547.75808", "-92233720368547.75809", "99999999999999999999999999999999999999999"] {
+            assert_eq!(Err(ParseFixedError::Overflow(text.to_string())), units(text), "'{}'", text);
+        }
+    }
+
+    #[test]
+    fn it_displays_what_it_parses() {
+        for text in ["0.00000", "-0.00001", "1234.50000", "92233720368547.75807", "-92233720368547.75808"] {
+            assert_eq!(text, text.parse::<Fixed5>().unwrap().to_string());
+        }
+    }
+}
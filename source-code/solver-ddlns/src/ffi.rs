@@ -0,0 +1,443 @@
+//! This module exposes a C compatible API which lets a foreign program embed
+//! the solver (the declarations are in `include/papier_lns.h`). The model is
+//! given as a set of callbacks, and the states are opaque byte buffers: the
+//! library only hashes and compares them, it never looks inside. The buffers
+//! and domains are filled by the callbacks through `pl_buffer_write` and
+//! `pl_domain_push`, hence the memory is never shared across the boundary.
+//!
+//! The variables are decided in order (the variable decided at depth `d` is
+//! `d`), and the problem is solved with `MddLns` on top of a `SimpleMdd`.
+//! Because the lns only stops when it proves optimality, a time limit should
+//! always be set.
+//!
+//! No panic ever crosses the boundary: each entry point catches them and
+//! reports `PL_ERR_PANIC` instead of aborting the host process.
+
+use std::{
+    ffi::{c_int, c_void},
+    panic::{self, AssertUnwindSafe},
+    ptr, slice,
+    sync::{atomic::AtomicBool, mpsc, Arc},
+    thread,
+    time::{Duration, Instant},
+};
+
+use rand::SeedableRng;
+use rand_xoshiro::Xoshiro256Plus;
+
+use crate::{
+    Decision, MddLnsBuilder, MinLP, Problem, ResolutionStatus, SimpleMddBuilder, Var,
+    VariableOrdering,
+};
+
+/// The version of the C API. It is bumped whenever a change breaks the
+/// binary compatibility (e.g. a field is added to a struct).
+pub const PL_API_VERSION: u32 = 1;
+
+/// The call succeeded
+pub const PL_OK: c_int = 0;
+/// A required pointer was null
+pub const PL_ERR_NULL: c_int = 1;
+/// An argument was out of its range
+pub const PL_ERR_INVALID: c_int = 2;
+/// The library panicked (the solver should not be used any more, except to
+/// free it)
+pub const PL_ERR_PANIC: c_int = 3;
+
+/// The optimality of the best solution has not been proved
+pub const PL_OPEN: c_int = 0;
+/// The best solution is optimal (or the problem is infeasible)
+pub const PL_CLOSED: c_int = 1;
+
+/// Runs the body of an entry point, turning a panic into `PL_ERR_PANIC`
+fn catch_panic(body: impl FnOnce() -> c_int) -> c_int {
+    panic::catch_unwind(AssertUnwindSafe(body)).unwrap_or(PL_ERR_PANIC)
+}
+
+// ----------------------------------------------------------------------------
+// Callbacks
+// ----------------------------------------------------------------------------
+/// The model, as a set of callbacks. Each callback receives the `user_data`
+/// pointer as its first argument. The `initial_value` and `estimate`
+/// callbacks are optional (NULL): the initial value then is zero, and the
+/// states have no estimate.
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct FfiCallbacks {
+    pub user_data: *mut c_void,
+    pub nb_vars: Option<unsafe extern "C" fn(*mut c_void) -> usize>,
+    pub initial_state: Option<unsafe extern "C" fn(*mut c_void, *mut FfiBuffer)>,
+    pub initial_value: Option<unsafe extern "C" fn(*mut c_void) -> i64>,
+    /// state, state length, variable, domain
+    pub domain: Option<unsafe extern "C" fn(*mut c_void, *const u8, usize, usize, *mut FfiDomain)>,
+    /// state, state length, variable, value, next state
+    pub transition: Option<unsafe extern "C" fn(*mut c_void, *const u8, usize, usize, i64, *mut FfiBuffer)>,
+    /// state, state length, variable, value
+    pub cost: Option<unsafe extern "C" fn(*mut c_void, *const u8, usize, usize, i64) -> i64>,
+    /// state, state length
+    pub estimate: Option<unsafe extern "C" fn(*mut c_void, *const u8, usize) -> i64>,
+}
+
+/// The buffer a callback writes a state into
+pub struct FfiBuffer(Vec<u8>);
+/// The values a domain callback pushes
+pub struct FfiDomain(Vec<i64>);
+
+/// Appends `len` bytes to the buffer
+///
+/// # Safety
+/// `buffer` must be the buffer given to the callback, and `data` must point
+/// to (at least) `len` readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn pl_buffer_write(buffer: *mut FfiBuffer, data: *const u8, len: usize) -> c_int {
+    catch_panic(|| {
+        let Some(buffer) = buffer.as_mut() else { return PL_ERR_NULL };
+        if len > 0 {
+            if data.is_null() {
+                return PL_ERR_NULL;
+            }
+            buffer.0.extend_from_slice(slice::from_raw_parts(data, len));
+        }
+        PL_OK
+    })
+}
+
+/// Adds a value to the domain
+///
+/// # Safety
+/// `domain` must be the domain given to the callback.
+#[no_mangle]
+pub unsafe extern "C" fn pl_domain_push(domain: *mut FfiDomain, value: i64) -> c_int {
+    catch_panic(|| {
+        let Some(domain) = domain.as_mut() else { return PL_ERR_NULL };
+        domain.0.push(value);
+        PL_OK
+    })
+}
+
+// ----------------------------------------------------------------------------
+// Problem
+// ----------------------------------------------------------------------------
+/// A state of a foreign model: its depth and its opaque content
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct FfiState {
+    depth: usize,
+    bytes: Vec<u8>,
+}
+
+/// A foreign model. The mandatory callbacks are known to be set.
+struct FfiProblem {
+    callbacks: FfiCallbacks,
+    nb_vars: usize,
+}
+impl FfiProblem {
+    fn new(callbacks: FfiCallbacks) -> Option<Self> {
+        callbacks.initial_state?;
+        callbacks.domain?;
+        callbacks.transition?;
+        callbacks.cost?;
+        let nb_vars = unsafe { callbacks.nb_vars?(callbacks.user_data) };
+        Some(Self { callbacks, nb_vars })
+    }
+    fn user_data(&self) -> *mut c_void {
+        self.callbacks.user_data
+    }
+}
+
+impl Problem for FfiProblem {
+    type State = FfiState;
+    type Cost = i64;
+
+    fn nb_vars(&self) -> usize {
+        self.nb_vars
+    }
+
+    fn initial_state(&self) -> Self::State {
+        let mut buffer = FfiBuffer(vec![]);
+        if let Some(f) = self.callbacks.initial_state {
+            unsafe { f(self.user_data(), &mut buffer) };
+        }
+        FfiState { depth: 0, bytes: buffer.0 }
+    }
+
+    fn initial_value(&self) -> Self::Cost {
+        self.callbacks.initial_value.map_or(0, |f| unsafe { f(self.user_data()) })
+    }
+
+    fn for_each_in_domain(&self, state: &Self::State, var: Var, mut f: impl FnMut(Decision)) {
+        let mut domain = FfiDomain(vec![]);
+        if let Some(g) = self.callbacks.domain {
+            unsafe { g(self.user_data(), state.bytes.as_ptr(), state.bytes.len(), var.id(), &mut domain) };
+        }
+        for val in domain.0 {
+            f(Decision::new(var, val as isize));
+        }
+    }
+
+    fn transition(&self, state: &Self::State, decision: Decision) -> Self::State {
+        let mut buffer = FfiBuffer(vec![]);
+        if let Some(f) = self.callbacks.transition {
+            unsafe {
+                f(self.user_data(), state.bytes.as_ptr(), state.bytes.len(), decision.var.id(), decision.val as i64, &mut buffer)
+            };
+        }
+        FfiState { depth: state.depth + 1, bytes: buffer.0 }
+    }
+
+    fn transition_cost(&self, state: &Self::State, decision: Decision) -> Self::Cost {
+        self.callbacks.cost.map_or(0, |f| unsafe {
+            f(self.user_data(), state.bytes.as_ptr(), state.bytes.len(), decision.var.id(), decision.val as i64)
+        })
+    }
+
+    fn estimate(&self, state: &Self::State) -> Self::Cost {
+        self.callbacks.estimate.map_or(i64::MIN, |f| unsafe {
+            f(self.user_data(), state.bytes.as_ptr(), state.bytes.len())
+        })
+    }
+}
+
+/// Decides the variables in order
+#[derive(Debug, Clone, Copy)]
+struct DepthOrdering(usize);
+impl VariableOrdering for DepthOrdering {
+    type State = FfiState;
+
+    fn next(&self, states: &mut dyn Iterator<Item = &Self::State>) -> Option<Var> {
+        let depth = states.next()?.depth;
+        if depth < self.0 {
+            Some(Var::new(depth))
+        } else {
+            None
+        }
+    }
+}
+
+// ----------------------------------------------------------------------------
+// Solver
+// ----------------------------------------------------------------------------
+/// The outcome of a resolution. The solution (when there is one) holds the
+/// value of each variable; it is owned by the solver and it remains valid
+/// until the next call to `pl_solver_run` or `pl_solver_free`.
+#[repr(C)]
+pub struct FfiOutcome {
+    /// PL_OPEN or PL_CLOSED
+    pub status: c_int,
+    /// Whether a solution was found
+    pub improved: bool,
+    pub has_value: bool,
+    pub best_value: i64,
+    /// In seconds (negative when unknown)
+    pub time_to_best: f64,
+    /// In seconds (negative when unknown)
+    pub time_to_prove: f64,
+    pub solution_len: usize,
+    pub solution: *const i64,
+}
+
+/// A solver along with its parameters
+pub struct FfiSolver {
+    problem: FfiProblem,
+    width: usize,
+    seed: u64,
+    proba: f64,
+    time_limit: Option<Duration>,
+    solution: Vec<i64>,
+}
+
+/// Returns the version of the C API implemented by the library
+#[no_mangle]
+pub extern "C" fn pl_api_version() -> u32 {
+    PL_API_VERSION
+}
+
+/// Creates a solver for the model described by the callbacks. Returns NULL
+/// when a mandatory callback is missing (or when the creation panicked). The
+/// callbacks are copied.
+///
+/// # Safety
+/// `callbacks` must point to a valid `pl_callbacks`, and the callbacks must
+/// remain callable (with `user_data`) until the solver is freed.
+#[no_mangle]
+pub unsafe extern "C" fn pl_solver_new(callbacks: *const FfiCallbacks) -> *mut FfiSolver {
+    let created = panic::catch_unwind(|| {
+        let Some(callbacks) = callbacks.as_ref() else { return ptr::null_mut() };
+        let Some(problem) = FfiProblem::new(*callbacks) else { return ptr::null_mut() };
+        let solver = FfiSolver {
+            problem,
+            width: 100,
+            seed: 0,
+            proba: 0.1,
+            time_limit: None,
+            solution: vec![],
+        };
+        Box::into_raw(Box::new(solver))
+    });
+    created.unwrap_or(ptr::null_mut())
+}
+
+/// Releases a solver (and the solution it holds)
+///
+/// # Safety
+/// `solver` must have been created by `pl_solver_new` (or be NULL), and it
+/// must not be used afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn pl_solver_free(solver: *mut FfiSolver) {
+    if !solver.is_null() {
+        // there is nothing left to report if the drop panics
+        let _ = panic::catch_unwind(AssertUnwindSafe(|| drop(Box::from_raw(solver))));
+    }
+}
+
+/// Sets the maximum width of the restricted diagrams (default 100)
+///
+/// # Safety
+/// `solver` must have been created by `pl_solver_new`.
+#[no_mangle]
+pub unsafe extern "C" fn pl_solver_set_width(solver: *mut FfiSolver, width: usize) -> c_int {
+    catch_panic(|| {
+        let Some(solver) = solver.as_mut() else { return PL_ERR_NULL };
+        if width == 0 {
+            return PL_ERR_INVALID;
+        }
+        solver.width = width;
+        PL_OK
+    })
+}
+
+/// Sets the seed of the random number generator (default 0)
+///
+/// # Safety
+/// `solver` must have been created by `pl_solver_new`.
+#[no_mangle]
+pub unsafe extern "C" fn pl_solver_set_seed(solver: *mut FfiSolver, seed: u64) -> c_int {
+    catch_panic(|| {
+        let Some(solver) = solver.as_mut() else { return PL_ERR_NULL };
+        solver.seed = seed;
+        PL_OK
+    })
+}
+
+/// Sets the probability to drop the best solution from the neighbourhood
+/// (default 0.1)
+///
+/// # Safety
+/// `solver` must have been created by `pl_solver_new`.
+#[no_mangle]
+pub unsafe extern "C" fn pl_solver_set_proba(solver: *mut FfiSolver, proba: f64) -> c_int {
+    catch_panic(|| {
+        let Some(solver) = solver.as_mut() else { return PL_ERR_NULL };
+        if !(0.0..=1.0).contains(&proba) {
+            return PL_ERR_INVALID;
+        }
+        solver.proba = proba;
+        PL_OK
+    })
+}
+
+/// Sets the time limit in seconds (a non positive limit removes it)
+///
+/// # Safety
+/// `solver` must have been created by `pl_solver_new`.
+#[no_mangle]
+pub unsafe extern "C" fn pl_solver_set_time_limit(solver: *mut FfiSolver, seconds: f64) -> c_int {
+    catch_panic(|| {
+        let Some(solver) = solver.as_mut() else { return PL_ERR_NULL };
+        if seconds.is_nan() {
+            return PL_ERR_INVALID;
+        }
+        solver.time_limit = if seconds > 0.0 {
+            match Duration::try_from_secs_f64(seconds) {
+                Ok(limit) => Some(limit),
+                Err(_)    => return PL_ERR_INVALID,
+            }
+        } else {
+            None
+        };
+        PL_OK
+    })
+}
+
+/// Solves the model with `MddLns` and writes the outcome. The lns needs at
+/// least two variables: PL_ERR_INVALID is returned otherwise. When the
+/// resolution panics (e.g. in a callback), PL_ERR_PANIC is returned and the
+/// outcome is left untouched.
+///
+/// # Safety
+/// `solver` must have been created by `pl_solver_new` and `outcome` must
+/// point to a writable `pl_outcome`.
+#[no_mangle]
+pub unsafe extern "C" fn pl_solver_run(solver: *mut FfiSolver, outcome: *mut FfiOutcome) -> c_int {
+    let (Some(solver), Some(outcome)) = (solver.as_mut(), outcome.as_mut()) else {
+        return PL_ERR_NULL;
+    };
+    if solver.problem.nb_vars < 2 {
+        return PL_ERR_INVALID;
+    }
+    catch_panic(|| run(solver, outcome))
+}
+
+/// The body of `pl_solver_run`, once its arguments have been checked
+fn run(solver: &mut FfiSolver, outcome: &mut FfiOutcome) -> c_int {
+    let start_tm    = Instant::now();
+    let kill_switch = Arc::new(AtomicBool::new(false));
+
+    // the timer stops as soon as the resolution is over
+    let (done, timeout) = mpsc::channel::<()>();
+    let timer = solver.time_limit.map(|limit| {
+        let kill_switch = Arc::clone(&kill_switch);
+        thread::spawn(move || {
+            if timeout.recv_timeout(limit).is_err() {
+                kill_switch.store(true, std::sync::atomic::Ordering::Relaxed);
+            }
+        })
+    });
+
+    let problem = &solver.problem;
+    let mdd = SimpleMddBuilder::default()
+        .problem(problem)
+        .var_ordering(DepthOrdering(problem.nb_vars))
+        .node_selection(MinLP::new())
+        .rng(Xoshiro256Plus::seed_from_u64(solver.seed))
+        .proba(solver.proba)
+        .kill_switch(Arc::clone(&kill_switch))
+        .build()
+        .expect("all the fields of the mdd are set");
+    let result = MddLnsBuilder::default()
+        .mdd(mdd)
+        .nb_var(problem.nb_vars)
+        .width(solver.width)
+        .initial_sol(None)
+        .start(start_tm)
+        .kill_switch(Arc::clone(&kill_switch))
+        .build()
+        .expect("all the fields of the lns are set")
+        .minimize();
+
+    // a closed channel wakes the timer up before its deadline
+    drop(done);
+    if let Some(timer) = timer {
+        let _ = timer.join();
+    }
+
+    solver.solution = result.best_sol.as_ref()
+        .map(|sol| sol.iter().map(|d| d.val as i64).collect())
+        .unwrap_or_default();
+    let has_value = result.best_sol.is_some();
+    let (status, improved) = match result.status {
+        ResolutionStatus::Open { improved }   => (PL_OPEN, improved),
+        ResolutionStatus::Closed { improved } => (PL_CLOSED, improved),
+    };
+    *outcome = FfiOutcome {
+        status,
+        improved,
+        has_value,
+        // the lns reports the trivial upper bound when nothing is found
+        best_value: result.best_value.filter(|_| has_value).unwrap_or(i64::MAX),
+        time_to_best: result.time_to_best.map_or(-1.0, |d| d.as_secs_f64()),
+        time_to_prove: result.time_to_prove.map_or(-1.0, |d| d.as_secs_f64()),
+        solution_len: solver.solution.len(),
+        solution: if has_value { solver.solution.as_ptr() } else { ptr::null() },
+    };
+    PL_OK
+}
@@ -0,0 +1,562 @@
+//! This module interprets the models written in the language of the `model`
+//! module. A loaded model is a `DpModel`: it implements `Problem`, hence it
+//! can be solved by any solver of the library. It comes along with a variable
+//! ordering (the variables are decided in order) and a node selection
+//! heuristic which honors the dominance statements of the model.
+//!
+//! The names and the number of arguments of the functions are checked when
+//! the model is loaded. The types are checked dynamically: the model is
+//! evaluated on the initial state (and on all its children) at load time to
+//! report the most obvious mistakes early. A type error which only shows up
+//! later during the search is a bug of the model: it aborts the resolution.
+
+use std::{
+    cell::OnceCell,
+    cmp::Ordering,
+    collections::BTreeSet,
+    fmt::Display,
+    fs::File,
+    hash::{Hash, Hasher},
+    io::Read,
+    rc::Rc,
+    str::FromStr,
+};
+
+use crate::{
+    BinOp, Builtin, Cost, Decision, Expr, Fold, ModelDef, ModelError, NodeSelectionHeuristic,
+    NodeSource, Preference, Problem, SelectableNode, ValueKind, Var, VariableOrdering,
+};
+
+// ----------------------------------------------------------------------------
+// Values
+// ----------------------------------------------------------------------------
+/// A value manipulated by a model
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Value {
+    Int(i64),
+    Set(Rc<BTreeSet<i64>>),
+    Vec(Rc<Vec<Value>>),
+}
+impl Value {
+    pub fn kind(&self) -> ValueKind {
+        match self {
+            Value::Int(_) => ValueKind::Int,
+            Value::Set(_) => ValueKind::Set,
+            Value::Vec(_) => ValueKind::Vec,
+        }
+    }
+    fn int(&self) -> Result<i64, String> {
+        match self {
+            Value::Int(v) => Ok(*v),
+            _             => Err(format!("expected an int but found {}", self)),
+        }
+    }
+    fn truth(&self) -> Result<bool, String> {
+        self.int().map(|v| v != 0)
+    }
+    /// The elements of a set or of a vector
+    fn elements(&self) -> Result<Vec<Value>, String> {
+        match self {
+            Value::Set(s) => Ok(s.iter().copied().map(Value::Int).collect()),
+            Value::Vec(v) => Ok(v.as_ref().clone()),
+            _             => Err(format!("expected a set or a vec but found {}", self)),
+        }
+    }
+    /// The elements of a set or of a vector of ints
+    fn ints(&self) -> Result<Vec<i64>, String> {
+        self.elements()?.iter().map(Value::int).collect()
+    }
+    fn set(items: impl IntoIterator<Item = i64>) -> Self {
+        Value::Set(Rc::new(items.into_iter().collect()))
+    }
+}
+impl Display for Value {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Value::Int(v) => write!(f, "{}", v),
+            Value::Set(s) => {
+                let items = s.iter().map(|x| x.to_string()).collect::<Vec<_>>();
+                write!(f, "{{{}}}", items.join(", "))
+            }
+            Value::Vec(v) => {
+                let items = v.iter().map(|x| x.to_string()).collect::<Vec<_>>();
+                write!(f, "[{}]", items.join(", "))
+            }
+        }
+    }
+}
+
+// ----------------------------------------------------------------------------
+// Evaluation
+// ----------------------------------------------------------------------------
+/// Everything an expression may refer to
+struct Env<'a> {
+    params: &'a [Value],
+    state: Option<&'a ModelState>,
+    var: i64,
+    val: i64,
+    locals: Vec<Value>,
+}
+impl<'a> Env<'a> {
+    fn new(params: &'a [Value], state: Option<&'a ModelState>) -> Self {
+        Self { params, state, var: 0, val: 0, locals: vec![] }
+    }
+    fn with_decision(mut self, decision: Decision) -> Self {
+        self.var = decision.var.id() as i64;
+        self.val = decision.val as i64;
+        self
+    }
+
+    fn eval(&mut self, expr: &Expr) -> Result<Value, String> {
+        match expr {
+            Expr::Int(v)    => Ok(Value::Int(*v)),
+            Expr::Param(i)  => Ok(self.params[*i].clone()),
+            Expr::State(i)  => Ok(self.state().values[*i].clone()),
+            Expr::Local(i)  => Ok(self.locals[*i].clone()),
+            Expr::Depth     => Ok(Value::Int(self.state().depth as i64)),
+            Expr::Var       => Ok(Value::Int(self.var)),
+            Expr::Val       => Ok(Value::Int(self.val)),
+            Expr::List(xs)  => {
+                let items = xs.iter().map(|x| self.eval(x)).collect::<Result<Vec<_>, _>>()?;
+                Ok(Value::Vec(Rc::new(items)))
+            }
+            Expr::Set(xs)   => {
+                let items = xs.iter().map(|x| self.eval(x)?.int()).collect::<Result<Vec<_>, _>>()?;
+                Ok(Value::set(items))
+            }
+            Expr::Neg(x)    => Ok(Value::Int(self.eval(x)?.int()?.saturating_neg())),
+            Expr::Not(x)    => Ok(Value::Int(!self.eval(x)?.truth()? as i64)),
+            Expr::Binary(op, a, b) => self.binary(*op, a, b),
+            Expr::Index(v, i) => {
+                let v = self.eval(v)?;
+                let i = self.eval(i)?.int()?;
+                match &v {
+                    Value::Vec(items) => usize::try_from(i).ok()
+                        .and_then(|i| items.get(i).cloned())
+                        .ok_or_else(|| format!("index {} is out of bounds (len {})", i, items.len())),
+                    _ => Err(format!("expected a vec but found {}", v)),
+                }
+            }
+            Expr::If(c, a, b) => {
+                if self.eval(c)?.truth()? {
+                    self.eval(a)
+                } else {
+                    self.eval(b)
+                }
+            }
+            Expr::Call(f, args) => {
+                let args = args.iter().map(|x| self.eval(x)).collect::<Result<Vec<_>, _>>()?;
+                call(*f, &args)
+            }
+            Expr::Fold(fold, items, body) => {
+                let items = self.eval(items)?.elements()?;
+                let mut result: Option<i64> = None;
+                for item in items {
+                    self.locals.push(item);
+                    let value = self.eval(body).and_then(|v| v.int());
+                    self.locals.pop();
+                    let value = value?;
+                    result = Some(match (fold, result) {
+                        (_, None)               => value,
+                        (Fold::Sum, Some(acc))  => acc.saturating_add(value),
+                        (Fold::Min, Some(acc))  => acc.min(value),
+                        (Fold::Max, Some(acc))  => acc.max(value),
+                    });
+                }
+                Ok(Value::Int(result.unwrap_or(0)))
+            }
+        }
+    }
+
+    fn binary(&mut self, op: BinOp, a: &Expr, b: &Expr) -> Result<Value, String> {
+        // the logical operators are short circuiting
+        match op {
+            BinOp::And => return Ok(Value::Int((self.eval(a)?.truth()? && self.eval(b)?.truth()?) as i64)),
+            BinOp::Or  => return Ok(Value::Int((self.eval(a)?.truth()? || self.eval(b)?.truth()?) as i64)),
+            _ => {}
+        }
+        let a = self.eval(a)?;
+        let b = self.eval(b)?;
+        let result = match (op, &a, &b) {
+            (BinOp::Eq, _, _) => Value::Int((a == b) as i64),
+            (BinOp::Ne, _, _) => Value::Int((a != b) as i64),
+            (BinOp::In, Value::Int(x), Value::Set(s)) => Value::Int(s.contains(x) as i64),
+            (BinOp::In, _, Value::Vec(v))             => Value::Int(v.contains(&a) as i64),
+            //
+            (BinOp::Add, Value::Set(s), Value::Int(x)) => Value::set(s.iter().copied().chain(Some(*x))),
+            (BinOp::Add, Value::Set(s), Value::Set(t)) => Value::set(s.union(t).copied()),
+            (BinOp::Sub, Value::Set(s), Value::Int(x)) => Value::set(s.iter().copied().filter(|y| y != x)),
+            (BinOp::Sub, Value::Set(s), Value::Set(t)) => Value::set(s.difference(t).copied()),
+            (BinOp::Mul, Value::Set(s), Value::Set(t)) => Value::set(s.intersection(t).copied()),
+            //
+            (_, Value::Int(x), Value::Int(y)) => {
+                let (x, y) = (*x, *y);
+                Value::Int(match op {
+                    BinOp::Add => x.saturating_add(y),
+                    BinOp::Sub => x.saturating_sub(y),
+                    BinOp::Mul => x.saturating_mul(y),
+                    BinOp::Div | BinOp::Rem if y == 0 => return Err("division by zero".to_string()),
+                    BinOp::Div => x.checked_div(y).ok_or_else(|| format!("{} / {} overflows", x, y))?,
+                    BinOp::Rem => x.checked_rem(y).ok_or_else(|| format!("{} % {} overflows", x, y))?,
+                    BinOp::Lt  => (x <  y) as i64,
+                    BinOp::Le  => (x <= y) as i64,
+                    BinOp::Gt  => (x >  y) as i64,
+                    BinOp::Ge  => (x >= y) as i64,
+                    _ => return Err(format!("invalid operands {} and {}", a, b)),
+                })
+            }
+            _ => return Err(format!("invalid operands {} and {}", a, b)),
+        };
+        Ok(result)
+    }
+
+    fn state(&self) -> &ModelState {
+        // the parser only lets the state be read where a state is given
+        self.state.expect("no state in a static expression")
+    }
+}
+
+fn call(f: Builtin, args: &[Value]) -> Result<Value, String> {
+    let result = match f {
+        Builtin::Len => match &args[0] {
+            Value::Set(s) => Value::Int(s.len() as i64),
+            Value::Vec(v) => Value::Int(v.len() as i64),
+            x             => return Err(format!("expected a set or a vec but found {}", x)),
+        },
+        Builtin::Abs => Value::Int(args[0].int()?.saturating_abs()),
+        Builtin::Min | Builtin::Max => {
+            let items = if let [single] = args {
+                if let Value::Int(x) = single { vec![*x] } else { single.ints()? }
+            } else {
+                args.iter().map(Value::int).collect::<Result<Vec<_>, _>>()?
+            };
+            let best = if f == Builtin::Min { items.into_iter().min() } else { items.into_iter().max() };
+            Value::Int(best.unwrap_or(0))
+        }
+        Builtin::Sum => Value::Int(args[0].ints()?.into_iter().fold(0, i64::saturating_add)),
+        Builtin::Range => Value::set(args[0].int()?..args[1].int()?),
+        Builtin::Fill => {
+            let n = usize::try_from(args[0].int()?).map_err(|_| "fill expects a positive length".to_string())?;
+            Value::Vec(Rc::new(vec![args[1].clone(); n]))
+        }
+        Builtin::Update => {
+            let Value::Vec(items) = &args[0] else {
+                return Err(format!("expected a vec but found {}", args[0]));
+            };
+            let i = args[1].int()?;
+            let mut items = items.as_ref().clone();
+            let Some(slot) = usize::try_from(i).ok().and_then(|i| items.get_mut(i)) else {
+                return Err(format!("index {} is out of bounds (len {})", i, items.len()));
+            };
+            *slot = args[2].clone();
+            Value::Vec(Rc::new(items))
+        }
+    };
+    Ok(result)
+}
+
+// ----------------------------------------------------------------------------
+// Problem
+// ----------------------------------------------------------------------------
+/// The state of a model: the number of decisions taken along with the value
+/// of each state variable (in the order of their declaration)
+#[derive(Debug, Clone)]
+pub struct ModelState {
+    depth: usize,
+    values: Vec<Value>,
+    /// The value of the dominance expressions. It is only computed the first
+    /// time the node selection heuristic needs it, and it is not part of the
+    /// identity of the state.
+    dominance: OnceCell<Vec<i64>>,
+}
+impl PartialEq for ModelState {
+    fn eq(&self, other: &Self) -> bool {
+        self.depth == other.depth && self.values == other.values
+    }
+}
+impl Eq for ModelState {}
+impl Hash for ModelState {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.depth.hash(state);
+        self.values.hash(state);
+    }
+}
+impl ModelState {
+    fn new(depth: usize, values: Vec<Value>) -> Self {
+        Self { depth, values, dominance: OnceCell::new() }
+    }
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+    pub fn values(&self) -> &[Value] {
+        &self.values
+    }
+}
+
+/// A model which has been loaded and checked
+#[derive(Debug, Clone)]
+pub struct DpModel {
+    def: ModelDef,
+    params: Vec<Value>,
+    nb_vars: usize,
+    initial: Vec<Value>,
+}
+
+impl FromStr for DpModel {
+    type Err = ModelError;
+
+    fn from_str(source: &str) -> Result<Self, Self::Err> {
+        let def = ModelDef::from_str(source)?;
+
+        let mut params = vec![];
+        for (_, expr) in def.params.iter() {
+            let value = Env::new(&params, None).eval(&expr.expr).map_err(|msg| eval_error(expr.line, msg))?;
+            params.push(value);
+        }
+        let nb_vars = Env::new(&params, None).eval(&def.nb_vars.expr)
+            .and_then(|v| v.int())
+            .and_then(|n| usize::try_from(n).map_err(|_| "the number of variables must be positive".to_string()))
+            .map_err(|msg| eval_error(def.nb_vars.line, msg))?;
+
+        let mut initial = vec![];
+        for decl in def.states.iter() {
+            let value = Env::new(&params, None).eval(&decl.init.expr).map_err(|msg| eval_error(decl.init.line, msg))?;
+            if value.kind() != decl.kind {
+                return Err(eval_error(decl.init.line, format!("{} is declared as {} but it is initialized with {}", decl.name, decl.kind, value)));
+            }
+            initial.push(value);
+        }
+
+        let model = Self { def, params, nb_vars, initial };
+        model.smoke_test()?;
+        Ok(model)
+    }
+}
+impl TryFrom<File> for DpModel {
+    type Error = ModelError;
+
+    fn try_from(mut file: File) -> Result<Self, Self::Error> {
+        let mut source = String::new();
+        file.read_to_string(&mut source)?;
+        Self::from_str(&source)
+    }
+}
+
+impl DpModel {
+    /// The ordering imposed by the model: the variables are decided in order
+    pub fn ordering(&self) -> ModelOrdering {
+        ModelOrdering { nb_vars: self.nb_vars }
+    }
+    /// The value of the given param (if it exists)
+    pub fn param(&self, name: &str) -> Option<&Value> {
+        self.def.params.iter().position(|(p, _)| p == name).map(|i| &self.params[i])
+    }
+    /// The value of the dominance expressions of the given state. These are
+    /// negated when the greater values are preferred: smaller is better. They
+    /// are only evaluated once per state.
+    pub fn dominance<'s>(&self, state: &'s ModelState) -> &'s [i64] {
+        state.dominance.get_or_init(|| self.eval_dominance(state))
+    }
+    fn eval_dominance(&self, state: &ModelState) -> Vec<i64> {
+        self.def.dominance.iter()
+            .map(|(pref, expr)| {
+                let v = self.eval(Env::new(&self.params, Some(state)), expr.line, &expr.expr).int();
+                let v = or_abort(expr.line, v);
+                match pref {
+                    Preference::Less    => v,
+                    Preference::Greater => v.saturating_neg(),
+                }
+            })
+            .collect()
+    }
+
+    /// Evaluates an expression, turning the errors into a model error
+    fn eval(&self, mut env: Env, line: usize, expr: &Expr) -> Value {
+        or_abort(line, env.eval(expr))
+    }
+    /// Returns the first requirement which rejects the given decision
+    fn failed_requirement(&self, state: &ModelState, decision: Decision) -> Result<Option<usize>, ModelError> {
+        for (i, req) in self.def.requires.iter().enumerate() {
+            let ok = Env::new(&self.params, Some(state)).with_decision(decision)
+                .eval(&req.check.expr)
+                .and_then(|v| v.truth())
+                .map_err(|msg| eval_error(req.check.line, msg))?;
+            if !ok {
+                return Ok(Some(i));
+            }
+        }
+        Ok(None)
+    }
+    /// The values of the domain of the variable (prior to the requirements)
+    fn domain(&self, state: &ModelState, var: Var) -> Result<Vec<i64>, ModelError> {
+        let decision = Decision::new(var, 0);
+        Env::new(&self.params, Some(state)).with_decision(decision)
+            .eval(&self.def.domain.expr)
+            .and_then(|v| v.ints())
+            .map_err(|msg| eval_error(self.def.domain.line, msg))
+    }
+    fn try_transition(&self, state: &ModelState, decision: Decision) -> Result<ModelState, ModelError> {
+        let mut values = Vec::with_capacity(state.values.len());
+        for (i, transition) in self.def.transitions.iter().enumerate() {
+            let Some(expr) = transition else {
+                values.push(state.values[i].clone());
+                continue;
+            };
+            let value = Env::new(&self.params, Some(state)).with_decision(decision)
+                .eval(&expr.expr)
+                .map_err(|msg| eval_error(expr.line, msg))?;
+            let decl = &self.def.states[i];
+            if value.kind() != decl.kind {
+                return Err(eval_error(expr.line, format!("{} is declared as {} but it is assigned {}", decl.name, decl.kind, value)));
+            }
+            values.push(value);
+        }
+        Ok(ModelState::new(state.depth + 1, values))
+    }
+    fn try_cost(&self, state: &ModelState, decision: Decision) -> Result<i64, ModelError> {
+        Env::new(&self.params, Some(state)).with_decision(decision)
+            .eval(&self.def.cost.expr)
+            .and_then(|v| v.int())
+            .map_err(|msg| eval_error(self.def.cost.line, msg))
+    }
+    fn try_estimate(&self, state: &ModelState) -> Result<i64, ModelError> {
+        let Some(expr) = self.def.estimate.as_ref() else {
+            return Ok(i64::MIN);
+        };
+        Env::new(&self.params, Some(state))
+            .eval(&expr.expr)
+            .and_then(|v| v.int())
+            .map_err(|msg| eval_error(expr.line, msg))
+    }
+
+    /// Evaluates all the statements of the model on the initial state and on
+    /// its children so as to report the type errors when the model is loaded
+    fn smoke_test(&self) -> Result<(), ModelError> {
+        let initial = self.initial_state();
+        self.try_estimate(&initial)?;
+        for (_, expr) in self.def.dominance.iter() {
+            Env::new(&self.params, Some(&initial)).eval(&expr.expr)
+                .and_then(|v| v.int())
+                .map_err(|msg| eval_error(expr.line, msg))?;
+        }
+        let Some(var) = self.ordering().next(&mut std::iter::once(&initial)) else {
+            return Ok(());
+        };
+        for val in self.domain(&initial, var)? {
+            let decision = Decision::new(var, val as isize);
+            if self.failed_requirement(&initial, decision)?.is_none() {
+                let next = self.try_transition(&initial, decision)?;
+                self.try_cost(&initial, decision)?;
+                self.try_estimate(&next)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn eval_error(line: usize, msg: String) -> ModelError {
+    ModelError::Eval { line, msg }
+}
+/// A model error showing up during the search cannot be recovered from
+fn or_abort<T>(line: usize, result: Result<T, String>) -> T {
+    result.unwrap_or_else(|msg| panic!("{}", eval_error(line, msg)))
+}
+fn abort_on_error<T>(result: Result<T, ModelError>) -> T {
+    result.unwrap_or_else(|e| panic!("{}", e))
+}
+
+impl Problem for DpModel {
+    type State = ModelState;
+    type Cost = i64;
+
+    fn nb_vars(&self) -> usize {
+        self.nb_vars
+    }
+
+    fn initial_state(&self) -> Self::State {
+        ModelState::new(0, self.initial.clone())
+    }
+
+    fn initial_value(&self) -> Self::Cost {
+        0
+    }
+
+    fn for_each_in_domain(&self, state: &Self::State, var: Var, mut f: impl FnMut(Decision)) {
+        for val in abort_on_error(self.domain(state, var)) {
+            let decision = Decision::new(var, val as isize);
+            if abort_on_error(self.failed_requirement(state, decision)).is_none() {
+                f(decision);
+            }
+        }
+    }
+
+    fn transition(&self, state: &Self::State, decision: Decision) -> Self::State {
+        abort_on_error(self.try_transition(state, decision))
+    }
+
+    fn transition_cost(&self, state: &Self::State, decision: Decision) -> Self::Cost {
+        abort_on_error(self.try_cost(state, decision))
+    }
+
+    fn estimate(&self, state: &Self::State) -> Self::Cost {
+        abort_on_error(self.try_estimate(state))
+    }
+
+    fn state_summary(&self, state: &Self::State) -> String {
+        let values = self.def.states.iter().zip(state.values.iter())
+            .map(|(decl, value)| format!("{} = {}", decl.name, value))
+            .collect::<Vec<_>>();
+        format!("depth {} ; {}", state.depth, values.join(" ; "))
+    }
+
+    fn violation_reason(&self, state: &Self::State, decision: Decision) -> String {
+        match abort_on_error(self.failed_requirement(state, decision)) {
+            Some(i) => format!("requirement {} is violated", self.def.requires[i].text),
+            None    => format!("{} is not in the domain of x{}", decision.val, decision.var.id()),
+        }
+    }
+}
+
+// ----------------------------------------------------------------------------
+// Heuristics
+// ----------------------------------------------------------------------------
+/// Decides the variables of a model in order
+#[derive(Debug, Clone, Copy)]
+pub struct ModelOrdering {
+    nb_vars: usize,
+}
+impl VariableOrdering for ModelOrdering {
+    type State = ModelState;
+
+    fn next(&self, states: &mut dyn Iterator<Item = &Self::State>) -> Option<Var> {
+        let depth = states.next()?.depth;
+        if depth < self.nb_vars {
+            Some(Var::new(depth))
+        } else {
+            None
+        }
+    }
+}
+
+/// Keeps the nodes having the best bound (value plus estimate). The ties are
+/// broken by the dominance statements of the model, then by the value.
+#[derive(Debug, Clone, Copy)]
+pub struct ModelSelection<'a> {
+    model: &'a DpModel,
+}
+impl<'a> ModelSelection<'a> {
+    pub fn new(model: &'a DpModel) -> Self {
+        Self { model }
+    }
+}
+impl NodeSelectionHeuristic for ModelSelection<'_> {
+    type State = ModelState;
+
+    fn compare<S: NodeSource<State = ModelState>>(&self, _dd: &S, na: &S::Node, nb: &S::Node) -> Ordering {
+        let ta = na.value().saturating_add(na.estimate());
+        let tb = nb.value().saturating_add(nb.estimate());
+        ta.cmp(&tb)
+            .then_with(|| self.model.dominance(na.state()).cmp(self.model.dominance(nb.state())))
+            .then_with(|| na.value().cmp(&nb.value()))
+    }
+}
@@ -5,14 +5,44 @@
 //! but a matter of taste. I prefer to have a clear separation but this is not
 //! mandatory.
 
+mod arena;
+mod astar;
 mod basics;
+mod beam;
+mod cost;
+mod ffi;
+mod interpreter;
 mod lns;
+mod model;
+mod ordering;
+mod proof;
+mod propagation;
 mod simple_mdd;
 mod puredp;
+mod reduced;
+mod replay;
+mod report;
+mod sanity;
 mod utils;
+#[cfg(test)]
+mod testing;
 
+pub use arena::*;
+pub use astar::*;
 pub use basics::*;
+pub use beam::*;
+pub use cost::*;
+pub use ffi::*;
+pub use interpreter::*;
 pub use lns::*;
+pub use model::*;
+pub use ordering::*;
+pub use proof::*;
+pub use propagation::*;
 pub use simple_mdd::*;
 pub use puredp::*;
+pub use reduced::*;
+pub use replay::*;
+pub use report::*;
+pub use sanity::*;
 pub use utils::*;
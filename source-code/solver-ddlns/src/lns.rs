@@ -7,7 +7,8 @@ use std::{
 };
 
 use crate::{
-    Mdd, ResolutionOutcome, ResolutionStatus, Solution,
+    Cost, Mdd, MemoryMonitor, ReplayEntry, ReplayWriter, ResolutionOutcome, ResolutionStatus,
+    Solution,
 };
 use derive_builder::Builder;
 
@@ -18,30 +19,49 @@ pub struct MddLns<D: Mdd>
     pub start: Instant,
     pub mdd: D,
     pub width: usize,
-    #[builder(default = "Some(isize::MAX)")]
-    pub initial_val: Option<isize>,
+    #[builder(default = "Some(<D::Cost as Cost>::MAX)")]
+    pub initial_val: Option<D::Cost>,
     pub initial_sol: Option<Solution>,
     pub kill_switch: Arc<AtomicBool>,
-    pub nb_var     : usize
+    pub nb_var     : usize,
+    /// An optional memory monitor. Whenever the memory exceeds its soft limit,
+    /// the width of the subsequent restricted diagrams is halved. It is then
+    /// doubled back (up to the configured width) at each iteration where the
+    /// memory is no longer scarce.
+    #[builder(default, setter(strip_option))]
+    pub memory: Option<Arc<dyn MemoryMonitor>>,
+    /// An optional replay log. Each iteration is logged so that it can be
+    /// replayed in isolation (see `replay`).
+    #[builder(default, setter(strip_option))]
+    pub replay: Option<Arc<ReplayWriter>>,
+    /// The configured width while the width is narrowed
+    #[builder(setter(skip))]
+    configured_width: Option<usize>,
 }
 
 impl<D: Mdd> MddLns<D>
 {
-    pub fn minimize(&mut self) -> ResolutionOutcome {
+    pub fn minimize(&mut self) -> ResolutionOutcome<D::Cost> {
         let mut opt = self.initial_val;
         let mut sol = self.initial_sol.clone();
         let mut ttb = None;
         let mut ttp = None;
         let mut status = ResolutionStatus::Open{improved: false};
         
+        self.log_header();
         let mut d  = self.nb_var - 2;
+        let mut iteration = 0;
         while !self.killed() {
+            self.adapt_width();
             let depth = if sol.is_some() { d } else { 0 };
+            let rng   = self.replay.as_ref().and_then(|_| self.mdd.rng_state());
             let curr  = self
                 .mdd
-                .restricted(self.width, opt.unwrap_or(isize::MAX), &sol, depth);
+                .restricted(self.width, opt.unwrap_or(D::Cost::MAX), &sol, depth);
+            let before   = opt;
+            let improved = curr.unwrap_or(D::Cost::MAX) < opt.unwrap_or(D::Cost::MAX);
 
-            if curr.unwrap_or(isize::MAX) < opt.unwrap_or(isize::MAX) {
+            if improved {
                 opt = curr;
                 sol = self.mdd.get_best_solution();
                 ttb = Some(self.start.elapsed());
@@ -52,6 +72,11 @@ impl<D: Mdd> MddLns<D>
             } else {
                 d = self.nb_var - 2; // on boucle
             }
+            self.log_iteration(ReplayEntry {
+                iteration, start_depth: depth, width: self.width, rng, incumbent: before,
+                value: curr, exact: self.mdd.is_exact(), improvement: if improved { sol.clone() } else { None },
+            });
+            iteration += 1;
             if self.mdd.is_exact() {
                 status = ResolutionStatus::Closed{improved: opt != self.initial_val};
                 ttp = Some(self.start.elapsed());
@@ -75,9 +100,9 @@ impl<D: Mdd> MddLns<D>
         }
     }
 
-    pub fn minimize_with_cond<F>(&mut self, f: F) -> ResolutionOutcome
+    pub fn minimize_with_cond<F>(&mut self, f: F) -> ResolutionOutcome<D::Cost>
     where
-        F: Fn(isize) -> bool,
+        F: Fn(D::Cost) -> bool,
     {
         //let mut rng = Xoshiro256Plus::seed_from_u64(self.seed);
         let mut opt = self.initial_val;
@@ -86,34 +111,42 @@ impl<D: Mdd> MddLns<D>
         let mut ttp = None;
         let mut status = ResolutionStatus::Open {improved: false};
 
+        self.log_header();
         let mut d  = self.nb_var - 2;
+        let mut iteration = 0;
         while !self.killed() {
+            self.adapt_width();
             let depth = if sol.is_some() {
                 d
             } else { 
                 0
             };
+            let rng   = self.replay.as_ref().and_then(|_| self.mdd.rng_state());
             let curr  = self
                 .mdd
-                .restricted(self.width, opt.unwrap_or(isize::MAX), &sol, depth);
+                .restricted(self.width, opt.unwrap_or(D::Cost::MAX), &sol, depth);
+            let before   = opt;
+            let improved = curr.unwrap_or(D::Cost::MAX) <= opt.unwrap_or(D::Cost::MAX);
             
-            if curr.unwrap_or(isize::MAX) <= opt.unwrap_or(isize::MAX) {
+            if improved {
                 opt = curr;
                 sol = self.mdd.get_best_solution();
                 ttb = Some(self.start.elapsed());
                 
                 d   = self.nb_var - 2;
-
-                if let Some(opt) = opt {
-                    if f(opt) {
-                        break;
-                    }
-                }
             } else if d > 0 {
                 d -= 1;
             } else {
                 d = self.nb_var - 2; // on boucle
             }
+            self.log_iteration(ReplayEntry {
+                iteration, start_depth: depth, width: self.width, rng, incumbent: before,
+                value: curr, exact: self.mdd.is_exact(), improvement: if improved { sol.clone() } else { None },
+            });
+            iteration += 1;
+            if improved && opt.is_some_and(&f) {
+                break;
+            }
             if self.mdd.is_exact() {
                 status = ResolutionStatus::Closed {improved: opt != self.initial_val};
                 ttp = Some(self.start.elapsed());
@@ -137,7 +170,89 @@ impl<D: Mdd> MddLns<D>
         }
     }
 
+    fn log_header(&self) {
+        if let Some(replay) = self.replay.as_ref() {
+            replay.header(self.initial_val, &self.initial_sol);
+        }
+    }
+    fn log_iteration(&self, entry: ReplayEntry<D::Cost>) {
+        if let Some(replay) = self.replay.as_ref() {
+            replay.entry(&entry);
+        }
+    }
+
+    /// Halves the width when the memory is getting scarce, and doubles it
+    /// back (up to the configured width) once the memory is no longer scarce
+    fn adapt_width(&mut self) {
+        if self.memory.as_ref().is_some_and(|m| m.is_over_soft_limit()) {
+            self.configured_width.get_or_insert(self.width);
+            self.width = (self.width / 2).max(1);
+        } else if let Some(configured) = self.configured_width {
+            self.width = self.width.saturating_mul(2).min(configured);
+            if self.width == configured {
+                self.configured_width = None;
+            }
+        }
+    }
+
     fn killed(&self) -> bool {
         self.kill_switch.load(Ordering::Relaxed)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::{
+            atomic::{AtomicBool, Ordering},
+            Arc,
+        },
+        time::Instant,
+    };
+
+    use crate::testing::{InOrder, Knapsack};
+    use crate::{MemoryMonitor, MinLP, Problem, SimpleMddBuilder};
+
+    use super::MddLnsBuilder;
+
+    /// A memory monitor whose scarcity is set by the test
+    struct Scarcity(AtomicBool);
+    impl MemoryMonitor for Scarcity {
+        fn is_over_soft_limit(&self) -> bool {
+            self.0.load(Ordering::Relaxed)
+        }
+    }
+
+    #[test]
+    fn the_width_is_restored_once_the_memory_is_no_longer_scarce() {
+        let problem  = Knapsack::small();
+        let scarcity = Arc::new(Scarcity(AtomicBool::new(true)));
+        let mdd = SimpleMddBuilder::default()
+            .problem(&problem)
+            .var_ordering(InOrder(problem.nb_vars()))
+            .node_selection(MinLP::new())
+            .kill_switch(Arc::new(AtomicBool::new(false)))
+            .proba(0.0)
+            .build()
+            .unwrap();
+        let mut lns = MddLnsBuilder::default()
+            .mdd(mdd)
+            .nb_var(problem.nb_vars())
+            .width(10)
+            .initial_sol(None)
+            .start(Instant::now())
+            .kill_switch(Arc::new(AtomicBool::new(false)))
+            .memory(Arc::clone(&scarcity) as Arc<dyn MemoryMonitor>)
+            .build()
+            .unwrap();
+
+        let mut widths = vec![];
+        for scarce in [true, true, true, true, true, false, false, false, false, true, false] {
+            scarcity.0.store(scarce, Ordering::Relaxed);
+            lns.adapt_width();
+            widths.push(lns.width);
+        }
+        // the width never drops below 1 and never exceeds the configured one
+        assert_eq!(vec![5, 2, 1, 1, 1, 2, 4, 8, 10, 5, 10], widths);
+    }
+}
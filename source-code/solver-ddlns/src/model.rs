@@ -0,0 +1,694 @@
+//! This module defines a small text language to describe dynamic programming
+//! models, along with its parser. It lets one prototype a model without
+//! writing (nor compiling) a `Problem` implementation: the model file is
+//! loaded at runtime and turned into a `DpModel` by the interpreter.
+//!
+//! A model is a sequence of statements, one per line (a statement may span
+//! several lines as long as some bracket is left open). `#` starts a comment.
+//!
+//! ```text
+//! # 0-1 knapsack
+//! param capacity = 10
+//! param weight   = [2, 3, 4, 5]
+//! param profit   = [3, 4, 5, 6]
+//!
+//! variables len(weight)
+//! state int room = capacity
+//!
+//! domain     {0, 1}
+//! require    val * weight[var] <= room
+//! transition room = room - val * weight[var]
+//! cost       -val * profit[var]
+//! estimate   -sum(i in range(depth, len(profit)): profit[i])
+//! dominance  greater room
+//! ```
+//!
+//! * `param NAME = EXPR` declares a constant (it may use the previous params).
+//! * `variables EXPR` tells the number of decision variables. These are
+//!   decided in order: the variable decided at depth `d` is `d`.
+//! * `state (int|set|vec) NAME = EXPR` declares a state variable and its
+//!   initial value.
+//! * `domain EXPR` is the set (or vector) of values a variable may take.
+//! * `require EXPR` filters the values of the domain (many are allowed).
+//! * `transition NAME = EXPR` tells the value of a state variable after a
+//!   decision. All transitions read the state before the decision, and the
+//!   variables having no transition keep their value.
+//! * `cost EXPR` is the cost of a decision (the objective is minimized).
+//! * `estimate EXPR` is an optional lower bound on the cost-to-go of a state.
+//! * `dominance (less|greater) EXPR` optionally tells that, all else being
+//!   equal, the states having a smaller (resp. greater) value for EXPR should
+//!   be preferred. These are used to rank the nodes of a layer.
+//!
+//! The expressions manipulate integers, sets of integers and vectors (which
+//! may be nested, e.g. a distance matrix). `var` is the variable being
+//! decided, `val` its value, `depth` the number of decisions already taken
+//! and `inf` stands for an infinite cost. The operators are those of C
+//! (`&&`, `||`, `!`, comparisons yield 0 or 1) plus `x in s` (membership),
+//! `s + x`, `s - x` (insert or remove an element or a set), `s * t`
+//! (intersection) and `if c then a else b`. The available functions are
+//! `len`, `abs`, `min`, `max`, `sum`, `range(a, b)`, `fill(n, x)` and
+//! `update(v, i, x)`. Finally, `sum`, `min` and `max` accept a comprehension
+//! as in `min(c in left: dist[here][c])` (`min` and `max` are zero when the
+//! set is empty). A name must be declared before it is used.
+
+use std::{fmt::Display, str::FromStr};
+
+// ----------------------------------------------------------------------------
+// Errors
+// ----------------------------------------------------------------------------
+#[derive(Debug, thiserror::Error)]
+pub enum ModelError {
+    #[error("io error {0}")]
+    Io(#[from] std::io::Error),
+    #[error("syntax error (line {line}): {msg}")]
+    Syntax { line: usize, msg: String },
+    #[error("unknown name {name} (line {line})")]
+    Unknown { line: usize, name: String },
+    #[error("{name} is declared twice (line {line})")]
+    Duplicate { line: usize, name: String },
+    #[error("the model has no {0} statement")]
+    Missing(&'static str),
+    #[error("evaluation error (line {line}): {msg}")]
+    Eval { line: usize, msg: String },
+}
+
+fn syntax<T>(line: usize, msg: impl Into<String>) -> Result<T, ModelError> {
+    Err(ModelError::Syntax { line, msg: msg.into() })
+}
+
+// ----------------------------------------------------------------------------
+// Abstract syntax
+// ----------------------------------------------------------------------------
+/// The type of a state variable
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ValueKind {
+    Int,
+    Set,
+    Vec,
+}
+impl Display for ValueKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ValueKind::Int => write!(f, "int"),
+            ValueKind::Set => write!(f, "set"),
+            ValueKind::Vec => write!(f, "vec"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum BinOp {
+    Add, Sub, Mul, Div, Rem,
+    Eq, Ne, Lt, Le, Gt, Ge,
+    And, Or, In,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Builtin {
+    Len, Abs, Min, Max, Sum, Range, Fill, Update,
+}
+impl Builtin {
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "len"    => Some(Self::Len),
+            "abs"    => Some(Self::Abs),
+            "min"    => Some(Self::Min),
+            "max"    => Some(Self::Max),
+            "sum"    => Some(Self::Sum),
+            "range"  => Some(Self::Range),
+            "fill"   => Some(Self::Fill),
+            "update" => Some(Self::Update),
+            _        => None,
+        }
+    }
+    /// Tells whether the given number of arguments suits this function
+    fn accepts(self, arity: usize) -> bool {
+        match self {
+            Self::Len | Self::Abs | Self::Sum => arity == 1,
+            Self::Min | Self::Max             => arity >= 1,
+            Self::Range | Self::Fill          => arity == 2,
+            Self::Update                      => arity == 3,
+        }
+    }
+}
+
+/// The aggregates which accept a comprehension
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Fold {
+    Sum, Min, Max,
+}
+
+/// An expression whose names have been resolved
+#[derive(Debug, Clone)]
+pub(crate) enum Expr {
+    Int(i64),
+    Param(usize),
+    State(usize),
+    /// A variable bound by a comprehension (position in the stack of locals)
+    Local(usize),
+    Depth,
+    Var,
+    Val,
+    List(Vec<Expr>),
+    Set(Vec<Expr>),
+    Neg(Box<Expr>),
+    Not(Box<Expr>),
+    Binary(BinOp, Box<Expr>, Box<Expr>),
+    Index(Box<Expr>, Box<Expr>),
+    If(Box<Expr>, Box<Expr>, Box<Expr>),
+    Call(Builtin, Vec<Expr>),
+    /// fold, collection, body (the element is the next local)
+    Fold(Fold, Box<Expr>, Box<Expr>),
+}
+
+/// An expression along with the line it was written on
+#[derive(Debug, Clone)]
+pub(crate) struct Located {
+    pub line: usize,
+    pub expr: Expr,
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct StateDecl {
+    pub name: String,
+    pub kind: ValueKind,
+    pub init: Located,
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct Requirement {
+    /// The source of the requirement (used to explain the violations)
+    pub text: String,
+    pub check: Located,
+}
+
+/// Whether the smaller or the greater values of a dominance are preferred
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Preference {
+    Less,
+    Greater,
+}
+
+/// A parsed model
+#[derive(Debug, Clone)]
+pub(crate) struct ModelDef {
+    pub params: Vec<(String, Located)>,
+    pub nb_vars: Located,
+    pub states: Vec<StateDecl>,
+    pub domain: Located,
+    pub requires: Vec<Requirement>,
+    /// The transition of each state variable (None when it never changes)
+    pub transitions: Vec<Option<Located>>,
+    pub cost: Located,
+    pub estimate: Option<Located>,
+    pub dominance: Vec<(Preference, Located)>,
+}
+
+// ----------------------------------------------------------------------------
+// Lexer
+// ----------------------------------------------------------------------------
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Tok {
+    Int(i64),
+    Ident(String),
+    Sym(&'static str),
+    Newline,
+    Eof,
+}
+impl Display for Tok {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Tok::Int(i)   => write!(f, "{}", i),
+            Tok::Ident(s) => write!(f, "{}", s),
+            Tok::Sym(s)   => write!(f, "{}", s),
+            Tok::Newline  => write!(f, "end of line"),
+            Tok::Eof      => write!(f, "end of file"),
+        }
+    }
+}
+
+/// The symbols of the language (the two chars ones come first)
+const SYMBOLS: [&str; 23] = [
+    "<=", ">=", "==", "!=", "&&", "||",
+    "+", "-", "*", "/", "%", "<", ">", "=", "!", "(", ")", "[", "]", "{", "}", ",", ":",
+];
+
+/// Splits the source in tokens. The line breaks are only significant when
+/// all brackets are closed: these end the statements.
+fn tokenize(source: &str) -> Result<Vec<(Tok, usize)>, ModelError> {
+    let mut tokens = vec![];
+    let mut depth  = 0_usize;
+    for (n, text) in source.lines().enumerate() {
+        let line  = n + 1;
+        let text  = text.split('#').next().unwrap_or_default();
+        let chars = text.char_indices().collect::<Vec<_>>();
+        let mut i = 0;
+        while i < chars.len() {
+            let (pos, c) = chars[i];
+            if c.is_whitespace() {
+                i += 1;
+            } else if c.is_ascii_digit() {
+                let end = chars[i..].iter().position(|(_, c)| !c.is_ascii_digit()).map_or(chars.len(), |p| i + p);
+                let lit = &text[pos..chars.get(end).map_or(text.len(), |(p, _)| *p)];
+                match lit.parse::<i64>() {
+                    Ok(v)  => tokens.push((Tok::Int(v), line)),
+                    Err(_) => return syntax(line, format!("{} is too large", lit)),
+                }
+                i = end;
+            } else if c.is_alphabetic() || c == '_' {
+                let end  = chars[i..].iter().position(|(_, c)| !(c.is_alphanumeric() || *c == '_')).map_or(chars.len(), |p| i + p);
+                let name = &text[pos..chars.get(end).map_or(text.len(), |(p, _)| *p)];
+                tokens.push((Tok::Ident(name.to_string()), line));
+                i = end;
+            } else {
+                let Some(sym) = SYMBOLS.iter().find(|s| text[pos..].starts_with(**s)) else {
+                    return syntax(line, format!("unexpected character {}", c));
+                };
+                match *sym {
+                    "(" | "[" | "{" => depth += 1,
+                    ")" | "]" | "}" => depth = depth.saturating_sub(1),
+                    _ => {}
+                }
+                tokens.push((Tok::Sym(sym), line));
+                i += sym.len();
+            }
+        }
+        if depth == 0 && tokens.last().is_some_and(|(t, _)| *t != Tok::Newline) {
+            tokens.push((Tok::Newline, line));
+        }
+    }
+    let last = source.lines().count().max(1);
+    tokens.push((Tok::Newline, last));
+    tokens.push((Tok::Eof, last));
+    Ok(tokens)
+}
+
+// ----------------------------------------------------------------------------
+// Parser
+// ----------------------------------------------------------------------------
+/// The implicit names which are visible in an expression
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Scope {
+    /// Params only (param, variables and state declarations)
+    Static,
+    /// Params, states and depth (estimate and dominance)
+    State,
+    /// Same as above plus the variable being decided (domain)
+    Var,
+    /// Same as above plus its value (require, transition, cost)
+    Decision,
+}
+
+struct Parser<'a> {
+    source: &'a str,
+    tokens: Vec<(Tok, usize)>,
+    pos: usize,
+    params: Vec<String>,
+    states: Vec<String>,
+    locals: Vec<String>,
+    scope: Scope,
+}
+
+impl FromStr for ModelDef {
+    type Err = ModelError;
+
+    fn from_str(source: &str) -> Result<Self, Self::Err> {
+        let mut parser = Parser {
+            source,
+            tokens: tokenize(source)?,
+            pos: 0,
+            params: vec![],
+            states: vec![],
+            locals: vec![],
+            scope: Scope::Static,
+        };
+        parser.model()
+    }
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> &Tok {
+        &self.tokens[self.pos].0
+    }
+    fn line(&self) -> usize {
+        self.tokens[self.pos].1
+    }
+    fn next(&mut self) -> Tok {
+        let tok = self.tokens[self.pos].0.clone();
+        if tok != Tok::Eof {
+            self.pos += 1;
+        }
+        tok
+    }
+    fn eat(&mut self, sym: &str) -> bool {
+        if matches!(self.peek(), Tok::Sym(s) if *s == sym) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+    fn eat_keyword(&mut self, kw: &str) -> bool {
+        if matches!(self.peek(), Tok::Ident(s) if s == kw) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+    fn expect(&mut self, sym: &str) -> Result<(), ModelError> {
+        if self.eat(sym) {
+            Ok(())
+        } else {
+            syntax(self.line(), format!("expected {} but found {}", sym, self.peek()))
+        }
+    }
+    fn expect_keyword(&mut self, kw: &str) -> Result<(), ModelError> {
+        if self.eat_keyword(kw) {
+            Ok(())
+        } else {
+            syntax(self.line(), format!("expected {} but found {}", kw, self.peek()))
+        }
+    }
+    fn ident(&mut self) -> Result<String, ModelError> {
+        let line = self.line();
+        match self.next() {
+            Tok::Ident(name) => Ok(name),
+            tok              => syntax(line, format!("expected a name but found {}", tok)),
+        }
+    }
+    /// A name which is about to be declared
+    fn fresh_ident(&mut self) -> Result<String, ModelError> {
+        let line = self.line();
+        let name = self.ident()?;
+        if is_reserved(&name) {
+            syntax(line, format!("{} is a reserved name", name))
+        } else if self.params.contains(&name) || self.states.contains(&name) {
+            Err(ModelError::Duplicate { line, name })
+        } else {
+            Ok(name)
+        }
+    }
+    fn end_of_statement(&mut self) -> Result<(), ModelError> {
+        match self.next() {
+            Tok::Newline | Tok::Eof => Ok(()),
+            tok => syntax(self.tokens[self.pos - 1].1, format!("expected end of line but found {}", tok)),
+        }
+    }
+
+    fn model(&mut self) -> Result<ModelDef, ModelError> {
+        let mut params      = vec![];
+        let mut nb_vars     = None;
+        let mut states      = vec![];
+        let mut domain      = None;
+        let mut requires    = vec![];
+        let mut transitions = vec![];
+        let mut cost        = None;
+        let mut estimate    = None;
+        let mut dominance   = vec![];
+
+        loop {
+            let line = self.line();
+            let keyword = match self.next() {
+                Tok::Eof        => break,
+                Tok::Newline    => continue,
+                Tok::Ident(kw)  => kw,
+                tok             => return syntax(line, format!("expected a statement but found {}", tok)),
+            };
+            match keyword.as_str() {
+                "param" => {
+                    let name = self.fresh_ident()?;
+                    self.expect("=")?;
+                    let expr = self.located(Scope::Static)?;
+                    self.params.push(name.clone());
+                    params.push((name, expr));
+                }
+                "variables" => {
+                    set_once(&mut nb_vars, self.located(Scope::Static)?, line, "variables")?;
+                }
+                "state" => {
+                    let kind = match self.ident()?.as_str() {
+                        "int" => ValueKind::Int,
+                        "set" => ValueKind::Set,
+                        "vec" => ValueKind::Vec,
+                        kind  => return syntax(line, format!("unknown type {} (expected int, set or vec)", kind)),
+                    };
+                    let name = self.fresh_ident()?;
+                    self.expect("=")?;
+                    let init = self.located(Scope::Static)?;
+                    self.states.push(name.clone());
+                    transitions.push(None);
+                    states.push(StateDecl { name, kind, init });
+                }
+                "domain" => {
+                    set_once(&mut domain, self.located(Scope::Var)?, line, "domain")?;
+                }
+                "require" => {
+                    let check = self.located(Scope::Decision)?;
+                    let text  = self.source_line(line).trim_start_matches("require").trim().to_string();
+                    requires.push(Requirement { text, check });
+                }
+                "transition" => {
+                    let name = self.ident()?;
+                    let Some(id) = self.states.iter().position(|s| *s == name) else {
+                        return Err(ModelError::Unknown { line, name });
+                    };
+                    self.expect("=")?;
+                    let expr = self.located(Scope::Decision)?;
+                    set_once(&mut transitions[id], expr, line, "transition")?;
+                }
+                "cost" => {
+                    set_once(&mut cost, self.located(Scope::Decision)?, line, "cost")?;
+                }
+                "estimate" => {
+                    set_once(&mut estimate, self.located(Scope::State)?, line, "estimate")?;
+                }
+                "dominance" => {
+                    let preference = match self.ident()?.as_str() {
+                        "less"    => Preference::Less,
+                        "greater" => Preference::Greater,
+                        pref      => return syntax(line, format!("unknown preference {} (expected less or greater)", pref)),
+                    };
+                    dominance.push((preference, self.located(Scope::State)?));
+                }
+                kw => return syntax(line, format!("unknown statement {}", kw)),
+            }
+            self.end_of_statement()?;
+        }
+
+        Ok(ModelDef {
+            params,
+            nb_vars: nb_vars.ok_or(ModelError::Missing("variables"))?,
+            states,
+            domain: domain.ok_or(ModelError::Missing("domain"))?,
+            requires,
+            transitions,
+            cost: cost.ok_or(ModelError::Missing("cost"))?,
+            estimate,
+            dominance,
+        })
+    }
+
+    /// The source text of the given line (without its comment)
+    fn source_line(&self, line: usize) -> &str {
+        let text = self.source.lines().nth(line - 1).unwrap_or_default();
+        text.split('#').next().unwrap_or_default().trim()
+    }
+
+    fn located(&mut self, scope: Scope) -> Result<Located, ModelError> {
+        self.scope = scope;
+        let line = self.line();
+        let expr = self.expr()?;
+        Ok(Located { line, expr })
+    }
+
+    // ---- expressions (by increasing precedence) -----------------------------
+    fn expr(&mut self) -> Result<Expr, ModelError> {
+        if self.eat_keyword("if") {
+            let cond = self.expr()?;
+            self.expect_keyword("then")?;
+            let then = self.expr()?;
+            self.expect_keyword("else")?;
+            let other = self.expr()?;
+            return Ok(Expr::If(Box::new(cond), Box::new(then), Box::new(other)));
+        }
+        self.or()
+    }
+    fn or(&mut self) -> Result<Expr, ModelError> {
+        let mut lhs = self.and()?;
+        while self.eat("||") {
+            lhs = Expr::Binary(BinOp::Or, Box::new(lhs), Box::new(self.and()?));
+        }
+        Ok(lhs)
+    }
+    fn and(&mut self) -> Result<Expr, ModelError> {
+        let mut lhs = self.comparison()?;
+        while self.eat("&&") {
+            lhs = Expr::Binary(BinOp::And, Box::new(lhs), Box::new(self.comparison()?));
+        }
+        Ok(lhs)
+    }
+    fn comparison(&mut self) -> Result<Expr, ModelError> {
+        let lhs = self.additive()?;
+        let op  = match self.peek() {
+            Tok::Sym("==") => BinOp::Eq,
+            Tok::Sym("!=") => BinOp::Ne,
+            Tok::Sym("<")  => BinOp::Lt,
+            Tok::Sym("<=") => BinOp::Le,
+            Tok::Sym(">")  => BinOp::Gt,
+            Tok::Sym(">=") => BinOp::Ge,
+            Tok::Ident(kw) if kw == "in" => BinOp::In,
+            _ => return Ok(lhs),
+        };
+        self.pos += 1;
+        let rhs = self.additive()?;
+        Ok(Expr::Binary(op, Box::new(lhs), Box::new(rhs)))
+    }
+    fn additive(&mut self) -> Result<Expr, ModelError> {
+        let mut lhs = self.multiplicative()?;
+        loop {
+            let op = if self.eat("+") {
+                BinOp::Add
+            } else if self.eat("-") {
+                BinOp::Sub
+            } else {
+                return Ok(lhs);
+            };
+            lhs = Expr::Binary(op, Box::new(lhs), Box::new(self.multiplicative()?));
+        }
+    }
+    fn multiplicative(&mut self) -> Result<Expr, ModelError> {
+        let mut lhs = self.unary()?;
+        loop {
+            let op = if self.eat("*") {
+                BinOp::Mul
+            } else if self.eat("/") {
+                BinOp::Div
+            } else if self.eat("%") {
+                BinOp::Rem
+            } else {
+                return Ok(lhs);
+            };
+            lhs = Expr::Binary(op, Box::new(lhs), Box::new(self.unary()?));
+        }
+    }
+    fn unary(&mut self) -> Result<Expr, ModelError> {
+        if self.eat("-") {
+            Ok(Expr::Neg(Box::new(self.unary()?)))
+        } else if self.eat("!") {
+            Ok(Expr::Not(Box::new(self.unary()?)))
+        } else {
+            self.postfix()
+        }
+    }
+    fn postfix(&mut self) -> Result<Expr, ModelError> {
+        let mut expr = self.primary()?;
+        while self.eat("[") {
+            let index = self.expr()?;
+            self.expect("]")?;
+            expr = Expr::Index(Box::new(expr), Box::new(index));
+        }
+        Ok(expr)
+    }
+    fn primary(&mut self) -> Result<Expr, ModelError> {
+        let line = self.line();
+        match self.next() {
+            Tok::Int(v)   => Ok(Expr::Int(v)),
+            Tok::Sym("(") => {
+                let expr = self.expr()?;
+                self.expect(")")?;
+                Ok(expr)
+            }
+            Tok::Sym("[") => Ok(Expr::List(self.items("]")?)),
+            Tok::Sym("{") => Ok(Expr::Set(self.items("}")?)),
+            Tok::Ident(name) => {
+                if self.eat("(") {
+                    self.call(line, &name)
+                } else {
+                    self.name(line, name)
+                }
+            }
+            tok => syntax(line, format!("expected an expression but found {}", tok)),
+        }
+    }
+    /// The comma separated expressions up to the given closing bracket
+    fn items(&mut self, close: &str) -> Result<Vec<Expr>, ModelError> {
+        let mut items = vec![];
+        while !self.eat(close) {
+            if !items.is_empty() {
+                self.expect(",")?;
+            }
+            items.push(self.expr()?);
+        }
+        Ok(items)
+    }
+    fn call(&mut self, line: usize, name: &str) -> Result<Expr, ModelError> {
+        let Some(builtin) = Builtin::from_name(name) else {
+            return Err(ModelError::Unknown { line, name: name.to_string() });
+        };
+        // a comprehension: fold(x in collection: body)
+        let fold = match builtin {
+            Builtin::Sum => Some(Fold::Sum),
+            Builtin::Min => Some(Fold::Min),
+            Builtin::Max => Some(Fold::Max),
+            _            => None,
+        };
+        if let (Some(fold), Tok::Ident(x), Tok::Ident(kw)) = (fold, self.peek().clone(), &self.tokens[self.pos + 1].0) {
+            if kw == "in" && !is_reserved(&x) {
+                let start = self.pos;
+                self.pos += 2;
+                let collection = self.additive()?;
+                if self.eat(":") {
+                    self.locals.push(x);
+                    let body = self.expr();
+                    self.locals.pop();
+                    let body = body?;
+                    self.expect(")")?;
+                    return Ok(Expr::Fold(fold, Box::new(collection), Box::new(body)));
+                }
+                // this was a plain membership test after all
+                self.pos = start;
+            }
+        }
+        let args = self.items(")")?;
+        if !builtin.accepts(args.len()) {
+            return syntax(line, format!("wrong number of arguments for {}", name));
+        }
+        Ok(Expr::Call(builtin, args))
+    }
+    fn name(&mut self, line: usize, name: String) -> Result<Expr, ModelError> {
+        if let Some(pos) = self.locals.iter().rposition(|l| *l == name) {
+            return Ok(Expr::Local(pos));
+        }
+        let scope = self.scope;
+        match name.as_str() {
+            "inf" => return Ok(Expr::Int(i64::MAX)),
+            "depth" if scope != Scope::Static    => return Ok(Expr::Depth),
+            "var" if matches!(scope, Scope::Var | Scope::Decision) => return Ok(Expr::Var),
+            "val" if scope == Scope::Decision    => return Ok(Expr::Val),
+            _ => {}
+        }
+        if let Some(id) = self.params.iter().position(|p| *p == name) {
+            return Ok(Expr::Param(id));
+        }
+        if scope != Scope::Static {
+            if let Some(id) = self.states.iter().position(|s| *s == name) {
+                return Ok(Expr::State(id));
+            }
+        }
+        Err(ModelError::Unknown { line, name })
+    }
+}
+
+fn is_reserved(name: &str) -> bool {
+    matches!(name, "inf" | "depth" | "var" | "val" | "in" | "if" | "then" | "else")
+        || Builtin::from_name(name).is_some()
+}
+
+fn set_once<T>(slot: &mut Option<T>, value: T, line: usize, what: &str) -> Result<(), ModelError> {
+    if slot.is_some() {
+        return Err(ModelError::Duplicate { line, name: what.to_string() });
+    }
+    *slot = Some(value);
+    Ok(())
+}
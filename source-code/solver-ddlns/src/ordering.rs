@@ -0,0 +1,237 @@
+//! This module provides some generic dynamic variable orderings. These are
+//! meant for the problems whose variables are not positional: the variable a
+//! layer branches on is chosen by looking at all the states of that layer.
+//! Because each layer branches on one single variable, all the states of a
+//! layer share the same set of free variables.
+
+use std::ops::Deref;
+
+use rustc_hash::FxHashSet;
+
+use crate::{Problem, Var, VariableOrdering};
+
+// ----------------------------------------------------------------------------
+/// Free Variables: tells which variables are still to be assigned in a state
+// ----------------------------------------------------------------------------
+pub trait FreeVariables {
+    type State;
+
+    /// Calls `f` for each variable that has not been assigned yet in the state
+    fn for_each_free_var(&self, state: &Self::State, f: impl FnMut(Var));
+}
+
+/// Branches on the free variable having the smallest domain once the domains
+/// of all the states in the layer have been merged (ties are broken in favor
+/// of the first free variable).
+#[derive(Debug, Clone, Copy)]
+pub struct SmallestUnionDomain<P> {
+    problem: P,
+}
+impl<P> SmallestUnionDomain<P> {
+    pub fn new(problem: P) -> Self {
+        Self { problem }
+    }
+}
+impl<P> VariableOrdering for SmallestUnionDomain<P>
+where
+    P: Problem + FreeVariables<State = <P as Problem>::State>,
+{
+    type State = <P as Problem>::State;
+
+    fn next(&self, states: &mut dyn Iterator<Item = &Self::State>) -> Option<Var> {
+        let states = states.collect::<Vec<_>>();
+        let first  = *states.first()?;
+
+        let mut best   = None;
+        let mut values = FxHashSet::default();
+        self.problem.for_each_free_var(first, |var| {
+            values.clear();
+            for state in states.iter() {
+                self.problem.for_each_in_domain(state, var, |d| { values.insert(d.val); });
+            }
+            let score = values.len();
+            if best.is_none_or(|(s, _)| score < s) {
+                best = Some((score, var));
+            }
+        });
+        best.map(|(_, var)| var)
+    }
+}
+
+/// Branches on the most constrained free variable: the one having the smallest
+/// domain in some state of the layer (ties are broken in favor of the variable
+/// having the smallest domains overall).
+#[derive(Debug, Clone, Copy)]
+pub struct MostConstrained<P> {
+    problem: P,
+}
+impl<P> MostConstrained<P> {
+    pub fn new(problem: P) -> Self {
+        Self { problem }
+    }
+}
+impl<P> VariableOrdering for MostConstrained<P>
+where
+    P: Problem + FreeVariables<State = <P as Problem>::State>,
+{
+    type State = <P as Problem>::State;
+
+    fn next(&self, states: &mut dyn Iterator<Item = &Self::State>) -> Option<Var> {
+        let states = states.collect::<Vec<_>>();
+        let first  = *states.first()?;
+
+        let mut best = None;
+        self.problem.for_each_free_var(first, |var| {
+            let mut min = usize::MAX;
+            let mut sum = 0_usize;
+            for state in states.iter() {
+                let mut size = 0;
+                self.problem.for_each_in_domain(state, var, |_| size += 1);
+                min = min.min(size);
+                sum = sum.saturating_add(size);
+            }
+            let score = (min, sum);
+            if best.is_none_or(|(s, _)| score < s) {
+                best = Some((score, var));
+            }
+        });
+        best.map(|(_, var)| var)
+    }
+}
+
+/// Branches on the free variable with the lowest score as computed by some
+/// problem specific function of the layer states (ties are broken in favor
+/// of the first free variable).
+#[derive(Debug, Clone, Copy)]
+pub struct ScoredOrdering<P, F> {
+    problem: P,
+    score: F,
+}
+impl<P, F> ScoredOrdering<P, F> {
+    pub fn new(problem: P, score: F) -> Self {
+        Self { problem, score }
+    }
+}
+impl<P, F> VariableOrdering for ScoredOrdering<P, F>
+where
+    P: FreeVariables,
+    F: Fn(&[&P::State], Var) -> isize,
+{
+    type State = P::State;
+
+    fn next(&self, states: &mut dyn Iterator<Item = &Self::State>) -> Option<Var> {
+        let states = states.collect::<Vec<_>>();
+        let first  = *states.first()?;
+
+        let mut best = None;
+        self.problem.for_each_free_var(first, |var| {
+            let score = (self.score)(&states, var);
+            if best.is_none_or(|(s, _)| score < s) {
+                best = Some((score, var));
+            }
+        });
+        best.map(|(_, var)| var)
+    }
+}
+
+// ----------------------------------------------------------------------------
+// Boilerplate to make any reference to a problem expose its free variables
+// ----------------------------------------------------------------------------
+impl<P, D> FreeVariables for D
+where
+    P: FreeVariables,
+    D: Deref<Target = P>,
+{
+    type State = P::State;
+
+    fn for_each_free_var(&self, state: &Self::State, f: impl FnMut(Var)) {
+        self.deref().for_each_free_var(state, f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{atomic::AtomicBool, Arc};
+
+    use crate::testing::{InOrder, Knapsack, KnapsackState};
+    use crate::{Mdd, MinLP, Problem, SimpleMdd, SimpleMddBuilder, Var, VariableOrdering};
+
+    use super::{MostConstrained, ScoredOrdering, SmallestUnionDomain};
+
+    fn mdd<V>(problem: &Knapsack, var_ordering: V) -> SimpleMdd<&Knapsack, V, MinLP<KnapsackState>>
+    where
+        V: VariableOrdering<State = KnapsackState> + Clone,
+    {
+        SimpleMddBuilder::default()
+            .problem(problem)
+            .var_ordering(var_ordering)
+            .node_selection(MinLP::new())
+            .kill_switch(Arc::new(AtomicBool::new(false)))
+            .proba(0.0)
+            .build()
+            .unwrap()
+    }
+
+    /// Compiles the knapsack with a dynamic ordering and checks that the exact
+    /// mdd, the evaluation of its solution and a restricted dive agree with
+    /// the static ordering
+    fn check_against_the_static_ordering<V>(var_ordering: V)
+    where
+        V: VariableOrdering<State = KnapsackState> + Copy,
+    {
+        let problem  = Knapsack::small();
+        let in_order = InOrder(problem.nb_vars());
+        let optimum  = problem.brute_force();
+
+        let mut reference = mdd(&problem, in_order);
+        assert_eq!(Some(optimum), reference.exact());
+
+        let mut dynamic = mdd(&problem, var_ordering);
+        assert_eq!(Some(optimum), dynamic.exact());
+        assert!(dynamic.is_exact());
+        let sol = dynamic.get_best_solution().expect("a solution");
+        assert_eq!(optimum, problem.evaluate(&var_ordering, &sol));
+        // the recorded order is followed, even by another ordering
+        assert_eq!(optimum, problem.evaluate(&in_order, &sol));
+
+        // a dive below the first decisions of the incumbent never worsens it
+        let best = Some(sol);
+        let dive = dynamic.restricted(2, optimum, &best, 3);
+        assert!(dive.is_none_or(|value| value >= optimum));
+        if let Some(value) = dive {
+            let sol = dynamic.get_best_solution().expect("a solution");
+            assert_eq!(value, problem.evaluate(&var_ordering, &sol));
+        }
+    }
+
+    #[test]
+    fn smallest_union_domain_finds_the_same_optimum() {
+        check_against_the_static_ordering(SmallestUnionDomain::new(&Knapsack::small()));
+    }
+
+    #[test]
+    fn most_constrained_finds_the_same_optimum() {
+        check_against_the_static_ordering(MostConstrained::new(&Knapsack::small()));
+    }
+
+    #[test]
+    fn scored_ordering_branches_on_the_lowest_score() {
+        let problem  = Knapsack::small();
+        // the heaviest items first
+        let ordering = ScoredOrdering::new(&problem, |_: &[&KnapsackState], var: Var| -problem.weight[var.id()]);
+        let root     = problem.initial_state();
+        assert_eq!(Some(Var::new(7)), ordering.next(&mut std::iter::once(&root)));
+        check_against_the_static_ordering(ordering);
+    }
+
+    #[test]
+    fn the_orderings_stop_when_nothing_is_left_to_decide() {
+        let problem = Knapsack::small();
+        let done    = KnapsackState { decided: (1 << problem.nb_vars()) - 1, room: 0 };
+        let empty: [KnapsackState; 0] = [];
+        assert_eq!(None, SmallestUnionDomain::new(&problem).next(&mut empty.iter()));
+        assert_eq!(None, MostConstrained::new(&problem).next(&mut empty.iter()));
+        assert_eq!(None, SmallestUnionDomain::new(&problem).next(&mut std::iter::once(&done)));
+        assert_eq!(None, MostConstrained::new(&problem).next(&mut std::iter::once(&done)));
+    }
+}
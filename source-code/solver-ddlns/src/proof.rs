@@ -0,0 +1,544 @@
+//! This module provides the optimality certificates of the exact
+//! compilations. When a `ProofRecorder` is given to a `SimpleMdd`, the mdd
+//! logs every step of its compilations: the layers it explores, the nodes it
+//! creates, the edges it merges into an existing node (because they reach the
+//! same state) and the edges or nodes it prunes because of the bound. Once a
+//! resolution is closed, the log of its last (exact) compilation becomes the
+//! certificate of the optimum.
+//!
+//! The certificate is checked by `check_proof` which replays it against the
+//! problem: it recomputes every state and cost, it makes sure that every
+//! decision of every developed node is accounted for, and it verifies each
+//! bound derivation against the claimed optimum. The checker trusts nothing
+//! but the problem itself. In particular, the pruning steps are only sound
+//! when the estimate of the problem never overestimates the remaining cost.
+//!
+//! The certificates are written as json lines: a header carrying the claimed
+//! optimum and a solution achieving it, followed by one line per step. The
+//! header also tells the type of the costs and whether the compilation used a
+//! propagator, so that a certificate is never checked against a different
+//! problem than the one it was recorded for.
+
+use std::{
+    any::type_name,
+    io::{BufRead, Write},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex, MutexGuard, PoisonError,
+    },
+};
+
+use rustc_hash::{FxHashMap, FxHashSet};
+use serde::{Deserialize, Serialize};
+
+use crate::{Cost, Decision, Problem, Propagator, Solution, Var, VariableOrdering};
+
+/// The version of the certificate format
+pub const PROOF_VERSION: u32 = 2;
+
+// ----------------------------------------------------------------------------
+// Errors
+// ----------------------------------------------------------------------------
+#[derive(Debug, thiserror::Error)]
+pub enum ProofError {
+    #[error("io error {0}")]
+    Io(#[from] std::io::Error),
+    #[error("malformed certificate (line {line}): {msg}")]
+    Malformed { line: usize, msg: String },
+    #[error("unsupported certificate version {0} (expected {PROOF_VERSION})")]
+    UnsupportedVersion(u32),
+    #[error("the certificate does not match the problem: {0}")]
+    Mismatch(String),
+    #[error("invalid step {step}: {reason}")]
+    Invalid { step: usize, reason: String },
+    #[error("invalid claim: {0}")]
+    Claim(String),
+}
+
+// ----------------------------------------------------------------------------
+// Steps
+// ----------------------------------------------------------------------------
+/// One step of a compilation. The nodes are identified by the ids they have in
+/// the diagram (the root is node 0), and each edge is identified by its source
+/// node and the value assigned to the variable of the current layer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "step", rename_all = "lowercase")]
+pub enum ProofStep {
+    /// The nodes of the current layer are developed by branching on `var`
+    Layer { var: Var },
+    /// The edge creates node `id` in the next layer
+    Node { id: usize, from: usize, val: isize },
+    /// The edge reaches the state of node `id` which already exists
+    Merge { id: usize, from: usize, val: isize },
+    /// The edge is discarded: its cost plus the estimate of its destination
+    /// cannot improve the bound
+    Prune { from: usize, val: isize },
+    /// The node is not developed: its value plus its estimate cannot improve
+    /// the bound
+    Skip { id: usize },
+    /// The edge is discarded by the propagator
+    Discard { from: usize, val: isize },
+}
+
+/// Collects the steps of the compilations of an mdd. Only the steps of the
+/// latest compilation are kept. The recorder is `Sync` so that it can be
+/// shared (through an `Arc`) with the mdd.
+#[derive(Debug, Default)]
+pub struct ProofRecorder {
+    steps: Mutex<Vec<ProofStep>>,
+    /// Did the latest compilation use a propagator ?
+    propagator: AtomicBool,
+}
+impl ProofRecorder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Forgets the previous compilation before a new one starts
+    pub(crate) fn start(&self, propagator: bool) {
+        self.steps().clear();
+        self.propagator.store(propagator, Ordering::Relaxed);
+    }
+    pub(crate) fn record(&self, step: ProofStep) {
+        self.steps().push(step);
+    }
+    /// The number of steps of the latest compilation
+    pub fn len(&self) -> usize {
+        self.steps().len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.steps().is_empty()
+    }
+    /// The steps recorded so far (a compilation that panicked leaves them in
+    /// a consistent state: at worst, the last step is missing)
+    fn steps(&self) -> MutexGuard<'_, Vec<ProofStep>> {
+        self.steps.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+    /// Turns the latest compilation into the certificate of the given claim.
+    /// (The recorded steps are moved into the certificate)
+    pub fn certificate<C: Cost>(&self, optimum: C, solution: Solution) -> ProofLog<C> {
+        ProofLog {
+            version: PROOF_VERSION,
+            cost: type_name::<C>().to_string(),
+            propagator: self.propagator.load(Ordering::Relaxed),
+            optimum,
+            solution,
+            steps: std::mem::take(&mut *self.steps()),
+        }
+    }
+}
+
+// ----------------------------------------------------------------------------
+// Certificate
+// ----------------------------------------------------------------------------
+#[derive(Serialize, Deserialize)]
+#[serde(bound = "C: Cost")]
+struct Header<C> {
+    version: u32,
+    cost: String,
+    propagator: bool,
+    optimum: C,
+    solution: Solution,
+}
+/// The part of the header that is readable whatever its version and the type
+/// of its costs
+#[derive(Deserialize)]
+struct Preamble {
+    version: u32,
+    #[serde(default)]
+    cost: String,
+}
+
+/// The certificate of an optimum: the claim and the compilation proving it
+#[derive(Debug, Clone)]
+pub struct ProofLog<C> {
+    pub version: u32,
+    /// The name of the cost type of the problem (`std::any::type_name`)
+    pub cost: String,
+    /// Whether the compilation filtered its edges with a propagator
+    pub propagator: bool,
+    /// The claimed optimal value
+    pub optimum: C,
+    /// A solution achieving the claimed optimum
+    pub solution: Solution,
+    /// The steps of the exact compilation
+    pub steps: Vec<ProofStep>,
+}
+impl<C: Cost> ProofLog<C> {
+    /// Writes the certificate as json lines
+    pub fn write_to<W: Write>(&self, mut out: W) -> Result<(), ProofError> {
+        let header = Header {
+            version: self.version,
+            cost: self.cost.clone(),
+            propagator: self.propagator,
+            optimum: self.optimum,
+            solution: self.solution.clone(),
+        };
+        writeln!(out, "{}", serde_json::to_string(&header).expect("a header is always serializable"))?;
+        for step in self.steps.iter() {
+            writeln!(out, "{}", serde_json::to_string(step).expect("a step is always serializable"))?;
+        }
+        out.flush()?;
+        Ok(())
+    }
+    /// Reads a certificate and makes sure its format is supported and that
+    /// its costs are of type `C`
+    pub fn read_from<R: BufRead>(input: R) -> Result<Self, ProofError> {
+        let malformed = |line: usize, e: serde_json::Error| ProofError::Malformed { line, msg: e.to_string() };
+
+        let mut lines = input.lines();
+        let header    = lines.next().ok_or(ProofError::Malformed { line: 1, msg: "missing header".to_string() })??;
+        let preamble  = serde_json::from_str::<Preamble>(&header).map_err(|e| malformed(1, e))?;
+        if preamble.version != PROOF_VERSION {
+            return Err(ProofError::UnsupportedVersion(preamble.version));
+        }
+        if preamble.cost != type_name::<C>() {
+            return Err(ProofError::Mismatch(format!("the costs are of type {} and not {}", preamble.cost, type_name::<C>())));
+        }
+        let header    = serde_json::from_str::<Header<C>>(&header).map_err(|e| malformed(1, e))?;
+        let mut steps = vec![];
+        for (i, line) in lines.enumerate() {
+            let line = line?;
+            if !line.trim().is_empty() {
+                steps.push(serde_json::from_str(&line).map_err(|e| malformed(i + 2, e))?);
+            }
+        }
+        Ok(Self {
+            version: header.version,
+            cost: header.cost,
+            propagator: header.propagator,
+            optimum: header.optimum,
+            solution: header.solution,
+            steps,
+        })
+    }
+}
+
+// ----------------------------------------------------------------------------
+// Checker
+// ----------------------------------------------------------------------------
+/// Some statistics about a certificate which has been checked
+#[derive(Debug, Clone)]
+pub struct ProofSummary<C> {
+    pub optimum: C,
+    pub nb_layers: usize,
+    pub nb_nodes: usize,
+    pub nb_merges: usize,
+    pub nb_prunes: usize,
+    pub nb_skips: usize,
+    pub nb_discards: usize,
+}
+
+/// A node replayed by the checker
+struct Replayed<S, C> {
+    state: S,
+    value: C,
+}
+
+/// The ids of the nodes of a layer, in the order they were created. The set
+/// lets the checker test the membership of a node in constant time.
+#[derive(Default)]
+struct Layer {
+    ids: Vec<usize>,
+    members: FxHashSet<usize>,
+}
+impl Layer {
+    fn root() -> Self {
+        let mut layer = Self::default();
+        layer.push(0);
+        layer
+    }
+    fn push(&mut self, id: usize) {
+        self.ids.push(id);
+        self.members.insert(id);
+    }
+    fn contains(&self, id: usize) -> bool {
+        self.members.contains(&id)
+    }
+    fn is_empty(&self) -> bool {
+        self.ids.is_empty()
+    }
+}
+
+/// Replays the certificate against the problem. The propagator must be the
+/// one the mdd was configured with (if any): it is needed to confirm the
+/// discarded edges. A certificate recorded with a propagator is rejected when
+/// none is given, and conversely.
+pub fn check_proof<P: Problem>(
+    problem: &P,
+    var_ord: &dyn VariableOrdering<State = P::State>,
+    propagator: Option<&Propagator<P::State>>,
+    log: &ProofLog<P::Cost>,
+) -> Result<ProofSummary<P::Cost>, ProofError> {
+    if log.propagator != propagator.is_some() {
+        let (recorded, given) = if log.propagator { ("with", "without") } else { ("without", "with") };
+        return Err(ProofError::Mismatch(format!("it was recorded {} a propagator but it is checked {} one", recorded, given)));
+    }
+    // the claimed optimum must be achieved by a feasible solution
+    let report = problem.check(var_ord, &log.solution);
+    if let Some(violation) = report.violations.first() {
+        return Err(ProofError::Claim(format!("the solution is infeasible ({})", violation)));
+    }
+    if report.cost != log.optimum {
+        return Err(ProofError::Claim(format!("the solution costs {} and not {}", report.cost, log.optimum)));
+    }
+
+    let optimum = log.optimum;
+    let mut summary = ProofSummary {
+        optimum,
+        nb_layers: 0,
+        nb_nodes: 1,
+        nb_merges: 0,
+        nb_prunes: 0,
+        nb_skips: 0,
+        nb_discards: 0,
+    };
+    let mut nodes = FxHashMap::default();
+    nodes.insert(0, Replayed { state: problem.initial_state(), value: problem.initial_value() });
+
+    let mut current = Layer::root();
+    let mut next    = Layer::default();
+    let mut var     = None;
+    // the values covered by the steps of each developed node
+    let mut covered = FxHashMap::<usize, FxHashSet<isize>>::default();
+    let mut skipped = FxHashSet::default();
+
+    for (step_id, step) in log.steps.iter().copied().enumerate() {
+        let invalid = |reason: String| ProofError::Invalid { step: step_id, reason };
+        // the source of an edge must be a developed node of the current layer
+        let source = |from: usize, val: isize, covered: &mut FxHashMap<usize, FxHashSet<isize>>, skipped: &FxHashSet<usize>| {
+            let var = var.ok_or_else(|| invalid("an edge precedes the first layer".to_string()))?;
+            if !current.contains(from) || skipped.contains(&from) {
+                return Err(invalid(format!("node {} is not developed in this layer", from)));
+            }
+            if !covered.entry(from).or_default().insert(val) {
+                return Err(invalid(format!("the edge {} --{}--> is accounted for twice", from, val)));
+            }
+            let decision = Decision::new(var, val);
+            let node     = &nodes[&from];
+            if !problem.is_in_domain(&node.state, decision) {
+                return Err(invalid(format!("{} is not in the domain of x{} at node {}", val, var.id(), from)));
+            }
+            Ok(decision)
+        };
+
+        match step {
+            ProofStep::Layer { var: layer_var } => {
+                if var.is_some() {
+                    check_coverage(problem, var, &current, &covered, &skipped, &nodes)
+                        .map_err(invalid)?;
+                    current = std::mem::take(&mut next);
+                    covered.clear();
+                    skipped.clear();
+                }
+                if current.is_empty() {
+                    return Err(invalid("there is no node left to develop".to_string()));
+                }
+                let expected = var_ord.next(&mut current.ids.iter().map(|id| &nodes[id].state));
+                if expected != Some(layer_var) {
+                    return Err(invalid(format!("the variable ordering does not branch on x{} here", layer_var.id())));
+                }
+                var = Some(layer_var);
+                summary.nb_layers += 1;
+            }
+            ProofStep::Node { id, from, val } => {
+                let decision = source(from, val, &mut covered, &skipped)?;
+                if nodes.contains_key(&id) {
+                    return Err(invalid(format!("node {} already exists", id)));
+                }
+                let parent = &nodes[&from];
+                let value  = parent.value.saturating_add(problem.transition_cost(&parent.state, decision));
+                let state  = problem.transition(&parent.state, decision);
+                nodes.insert(id, Replayed { state, value });
+                next.push(id);
+                summary.nb_nodes += 1;
+            }
+            ProofStep::Merge { id, from, val } => {
+                let decision = source(from, val, &mut covered, &skipped)?;
+                if !next.contains(id) {
+                    return Err(invalid(format!("node {} does not belong to the next layer", id)));
+                }
+                let parent = &nodes[&from];
+                let value  = parent.value.saturating_add(problem.transition_cost(&parent.state, decision));
+                let state  = problem.transition(&parent.state, decision);
+                let node   = nodes.get_mut(&id).expect("the nodes of the next layer exist");
+                if node.state != state {
+                    return Err(invalid(format!("the edge {} --{}--> does not reach the state of node {}", from, val, id)));
+                }
+                if value < node.value {
+                    node.value = value;
+                }
+                summary.nb_merges += 1;
+            }
+            ProofStep::Prune { from, val } => {
+                let decision = source(from, val, &mut covered, &skipped)?;
+                let parent = &nodes[&from];
+                let state  = problem.transition(&parent.state, decision);
+                let bound  = parent.value
+                    .saturating_add(problem.transition_cost(&parent.state, decision))
+                    .saturating_add(problem.estimate(&state));
+                if bound < optimum {
+                    return Err(invalid(format!("the edge {} --{}--> has a bound of {} < {}", from, val, bound, optimum)));
+                }
+                summary.nb_prunes += 1;
+            }
+            ProofStep::Discard { from, val } => {
+                let decision = source(from, val, &mut covered, &skipped)?;
+                let parent = &nodes[&from];
+                let discarded = propagator.is_some_and(|propagator| {
+                    !propagator.allows(&parent.state, decision)
+                        || propagator.is_dead_end(&problem.transition(&parent.state, decision))
+                });
+                if !discarded {
+                    return Err(invalid(format!("the edge {} --{}--> is not discarded by the propagator", from, val)));
+                }
+                summary.nb_discards += 1;
+            }
+            ProofStep::Skip { id } => {
+                if !current.contains(id) || covered.contains_key(&id) || !skipped.insert(id) {
+                    return Err(invalid(format!("node {} cannot be skipped", id)));
+                }
+                let node  = &nodes[&id];
+                let bound = node.value.saturating_add(problem.estimate(&node.state));
+                if bound < optimum {
+                    return Err(invalid(format!("node {} has a bound of {} < {}", id, bound, optimum)));
+                }
+                summary.nb_skips += 1;
+            }
+        }
+    }
+    let invalid = |reason: String| ProofError::Invalid { step: log.steps.len(), reason };
+    if var.is_some() {
+        check_coverage(problem, var, &current, &covered, &skipped, &nodes).map_err(invalid)?;
+        current = next;
+    }
+    // the compilation must be complete, and none of its terminal nodes may
+    // beat the claimed optimum
+    if !current.is_empty() && var_ord.next(&mut current.ids.iter().map(|id| &nodes[id].state)).is_some() {
+        return Err(invalid("the last layer is not terminal".to_string()));
+    }
+    if let Some(best) = current.ids.iter().map(|id| nodes[id].value).min() {
+        if best < optimum {
+            return Err(invalid(format!("a terminal node has value {} < {}", best, optimum)));
+        }
+    }
+    Ok(summary)
+}
+
+/// Makes sure that all the decisions of all the developed nodes of a layer are
+/// accounted for
+fn check_coverage<P: Problem>(
+    problem: &P,
+    var: Option<Var>,
+    layer: &Layer,
+    covered: &FxHashMap<usize, FxHashSet<isize>>,
+    skipped: &FxHashSet<usize>,
+    nodes: &FxHashMap<usize, Replayed<P::State, P::Cost>>,
+) -> Result<(), String> {
+    let var = var.expect("coverage is only checked after a layer");
+    for id in layer.ids.iter().filter(|id| !skipped.contains(id)) {
+        let node = &nodes[id];
+        let mut missing = None;
+        problem.for_each_in_domain(&node.state, var, |decision| {
+            let done = covered.get(id).is_some_and(|vals| vals.contains(&decision.val));
+            if !done && missing.is_none() {
+                missing = Some(decision.val);
+            }
+        });
+        if let Some(val) = missing {
+            return Err(format!("the edge {} --{}--> is not accounted for", id, val));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{atomic::AtomicBool, Arc};
+
+    use crate::testing::{InOrder, Knapsack, KnapsackState};
+    use crate::{DeadEnd, Mdd, MinLP, Problem, Propagator, SimpleMddBuilder};
+
+    use super::{check_proof, ProofError, ProofLog, ProofRecorder, ProofStep};
+
+    /// Compiles the knapsack exactly and returns the certificate of its optimum
+    fn certify(problem: &Knapsack, propagator: Option<Arc<Propagator<KnapsackState>>>) -> ProofLog<isize> {
+        let recorder = Arc::new(ProofRecorder::new());
+        let mut mdd = SimpleMddBuilder::default();
+        mdd.problem(problem)
+            .var_ordering(InOrder(problem.nb_vars()))
+            .node_selection(MinLP::new())
+            .kill_switch(Arc::new(AtomicBool::new(false)))
+            .proba(0.0)
+            .proof(Arc::clone(&recorder));
+        if let Some(propagator) = propagator {
+            mdd.propagator(propagator);
+        }
+        let mut mdd = mdd.build().unwrap();
+        let optimum = mdd.exact().expect("an optimum");
+        assert!(mdd.is_exact());
+        recorder.certificate(optimum, mdd.get_best_solution().expect("a solution"))
+    }
+
+    /// Writes the certificate and reads it back
+    fn round_trip(log: &ProofLog<isize>) -> ProofLog<isize> {
+        let mut bytes = vec![];
+        log.write_to(&mut bytes).unwrap();
+        ProofLog::read_from(&bytes[..]).unwrap()
+    }
+
+    #[test]
+    fn a_certificate_survives_a_round_trip_and_is_accepted() {
+        let problem = Knapsack::small();
+        let log     = round_trip(&certify(&problem, None));
+        let summary = check_proof(&problem, &InOrder(problem.nb_vars()), None, &log).unwrap();
+        assert_eq!(problem.brute_force(), summary.optimum);
+        assert_eq!(problem.nb_vars(), summary.nb_layers);
+        assert!(summary.nb_merges > 0);
+
+        // with a propagator, the certificate must be checked with that propagator
+        let propagator = Arc::new(Propagator::new().with(DeadEnd::new(|s: &KnapsackState| s.room < 3)));
+        let log = round_trip(&certify(&problem, Some(Arc::clone(&propagator))));
+        assert!(log.propagator);
+        let summary = check_proof(&problem, &InOrder(problem.nb_vars()), Some(&propagator), &log).unwrap();
+        assert!(summary.nb_discards > 0);
+        assert!(matches!(check_proof(&problem, &InOrder(problem.nb_vars()), None, &log), Err(ProofError::Mismatch(_))));
+    }
+
+    #[test]
+    fn a_tampered_certificate_is_rejected() {
+        let problem = Knapsack::small();
+        let var_ord = InOrder(problem.nb_vars());
+        let log     = certify(&problem, None);
+
+        // a better optimum than the solution achieves
+        let mut claim = log.clone();
+        claim.optimum -= 1;
+        assert!(matches!(check_proof(&problem, &var_ord, None, &claim), Err(ProofError::Claim(_))));
+
+        // an edge that is never accounted for
+        let mut missing = log.clone();
+        let merge = missing.steps.iter().position(|s| matches!(s, ProofStep::Merge { .. })).unwrap();
+        missing.steps.remove(merge);
+        assert!(matches!(check_proof(&problem, &var_ord, None, &missing), Err(ProofError::Invalid { .. })));
+
+        // an edge that is accounted for twice
+        let mut twice = log.clone();
+        twice.steps.insert(merge, twice.steps[merge]);
+        assert!(matches!(check_proof(&problem, &var_ord, None, &twice), Err(ProofError::Invalid { step, .. }) if step == merge + 1));
+    }
+
+    #[test]
+    fn an_unreadable_certificate_is_rejected() {
+        let log = certify(&Knapsack::small(), None);
+        let mut bytes = vec![];
+        log.write_to(&mut bytes).unwrap();
+        let text = String::from_utf8(bytes).unwrap();
+
+        assert!(matches!(ProofLog::<i64>::read_from(text.as_bytes()), Err(ProofError::Mismatch(_))));
+        let old = text.replacen("\"version\":2", "\"version\":1", 1);
+        assert!(matches!(ProofLog::<isize>::read_from(old.as_bytes()), Err(ProofError::UnsupportedVersion(1))));
+        let garbled = text.replacen("\n{", "\n{oops", 1);
+        assert!(matches!(ProofLog::<isize>::read_from(garbled.as_bytes()), Err(ProofError::Malformed { line: 2, .. })));
+        assert!(matches!(ProofLog::<isize>::read_from(&b""[..]), Err(ProofError::Malformed { line: 1, .. })));
+    }
+}
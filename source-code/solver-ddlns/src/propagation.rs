@@ -0,0 +1,317 @@
+//! This module provides an optional propagation layer. It lets a model register
+//! a set of constraints which are used by the solvers to filter the domains
+//! and to detect the dead-end states before they are inserted in the diagram
+//! (or the cache). The rollouts of `SimpleMdd` are filtered the same way. The
+//! constraints below are generic: they only need a few accessors telling them
+//! how to read the relevant information in the states of the model.
+
+use std::{fmt::Debug, marker::PhantomData};
+
+use crate::{Decision, Problem, Var};
+
+// ----------------------------------------------------------------------------
+/// Constraint
+// ----------------------------------------------------------------------------
+pub trait Constraint {
+    type State;
+
+    /// Tells whether the given decision may be taken from the given state
+    fn allows(&self, _state: &Self::State, _decision: Decision) -> bool {
+        true
+    }
+    /// Tells whether the given state is a dead end (it cannot be completed
+    /// into a feasible solution)
+    fn is_dead_end(&self, _state: &Self::State) -> bool {
+        false
+    }
+}
+
+// ----------------------------------------------------------------------------
+/// Propagator: the set of constraints registered for some model
+// ----------------------------------------------------------------------------
+/// The constraints must be `Send + Sync` so that a propagator can be shared
+/// (through an `Arc`) by the solvers.
+pub struct Propagator<S> {
+    constraints: Vec<Box<dyn Constraint<State = S> + Send + Sync>>,
+}
+impl<S> Default for Propagator<S> {
+    fn default() -> Self {
+        Self { constraints: vec![] }
+    }
+}
+impl<S> Debug for Propagator<S> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Propagator({} constraints)", self.constraints.len())
+    }
+}
+impl<S> Propagator<S> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Registers an additional constraint
+    pub fn with<C>(mut self, constraint: C) -> Self
+    where
+        C: Constraint<State = S> + Send + Sync + 'static,
+    {
+        self.constraints.push(Box::new(constraint));
+        self
+    }
+    /// Returns the number of registered constraints
+    pub fn len(&self) -> usize {
+        self.constraints.len()
+    }
+    /// Returns true iff no constraint has been registered
+    pub fn is_empty(&self) -> bool {
+        self.constraints.is_empty()
+    }
+    /// Tells whether all constraints allow the given decision
+    pub fn allows(&self, state: &S, decision: Decision) -> bool {
+        self.constraints.iter().all(|c| c.allows(state, decision))
+    }
+    /// Tells whether any constraint detects that the state is a dead end
+    pub fn is_dead_end(&self, state: &S) -> bool {
+        self.constraints.iter().any(|c| c.is_dead_end(state))
+    }
+    /// Iterates over the domain of `var` in the given state, once it has
+    /// been filtered by all the registered constraints
+    pub fn for_each_in_domain<P>(&self, problem: &P, state: &S, var: Var, mut f: impl FnMut(Decision))
+    where
+        P: Problem<State = S>,
+    {
+        problem.for_each_in_domain(state, var, |decision| {
+            if self.allows(state, decision) {
+                f(decision)
+            }
+        })
+    }
+}
+
+/// A propagator is itself a constraint (the conjunction of its constraints),
+/// which lets it be wrapped in an adapter such as `Constrained`
+impl<S> Constraint for Propagator<S> {
+    type State = S;
+
+    fn allows(&self, state: &S, decision: Decision) -> bool {
+        Propagator::allows(self, state, decision)
+    }
+    fn is_dead_end(&self, state: &S) -> bool {
+        Propagator::is_dead_end(self, state)
+    }
+}
+
+// ----------------------------------------------------------------------------
+/// Precedence: value `after` can only be assigned once `before` has been
+// ----------------------------------------------------------------------------
+pub struct Precedence<S, F> {
+    before: isize,
+    after: isize,
+    /// Tells whether the given value has already been assigned in the state
+    assigned: F,
+    _phantom: PhantomData<fn(&S)>,
+}
+impl<S, F> Precedence<S, F>
+where
+    F: Fn(&S, isize) -> bool,
+{
+    pub fn new(before: isize, after: isize, assigned: F) -> Self {
+        Self { before, after, assigned, _phantom: PhantomData }
+    }
+}
+impl<S, F> Constraint for Precedence<S, F>
+where
+    F: Fn(&S, isize) -> bool,
+{
+    type State = S;
+
+    fn allows(&self, state: &S, decision: Decision) -> bool {
+        decision.val != self.after || (self.assigned)(state, self.before)
+    }
+}
+
+// ----------------------------------------------------------------------------
+/// All different: no value may be assigned twice
+// ----------------------------------------------------------------------------
+pub struct AllDifferent<S, F> {
+    /// Tells whether the given value has already been assigned in the state
+    assigned: F,
+    _phantom: PhantomData<fn(&S)>,
+}
+impl<S, F> AllDifferent<S, F>
+where
+    F: Fn(&S, isize) -> bool,
+{
+    pub fn new(assigned: F) -> Self {
+        Self { assigned, _phantom: PhantomData }
+    }
+}
+impl<S, F> Constraint for AllDifferent<S, F>
+where
+    F: Fn(&S, isize) -> bool,
+{
+    type State = S;
+
+    fn allows(&self, state: &S, decision: Decision) -> bool {
+        !(self.assigned)(state, decision.val)
+    }
+}
+
+// ----------------------------------------------------------------------------
+/// Resource limit: the usage of some resource may never exceed its capacity
+// ----------------------------------------------------------------------------
+pub struct ResourceLimit<S, U, D> {
+    capacity: isize,
+    /// Tells how much of the resource is used in the given state
+    usage: U,
+    /// Tells how much of the resource the given decision consumes
+    demand: D,
+    _phantom: PhantomData<fn(&S)>,
+}
+impl<S, U, D> ResourceLimit<S, U, D>
+where
+    U: Fn(&S) -> isize,
+    D: Fn(&S, Decision) -> isize,
+{
+    pub fn new(capacity: isize, usage: U, demand: D) -> Self {
+        Self { capacity, usage, demand, _phantom: PhantomData }
+    }
+}
+impl<S, U, D> Constraint for ResourceLimit<S, U, D>
+where
+    U: Fn(&S) -> isize,
+    D: Fn(&S, Decision) -> isize,
+{
+    type State = S;
+
+    fn allows(&self, state: &S, decision: Decision) -> bool {
+        (self.usage)(state).saturating_add((self.demand)(state, decision)) <= self.capacity
+    }
+    fn is_dead_end(&self, state: &S) -> bool {
+        (self.usage)(state) > self.capacity
+    }
+}
+
+// ----------------------------------------------------------------------------
+/// Dead end: a model specific test telling whether a state is a dead end
+// ----------------------------------------------------------------------------
+pub struct DeadEnd<S, F> {
+    test: F,
+    _phantom: PhantomData<fn(&S)>,
+}
+impl<S, F> DeadEnd<S, F>
+where
+    F: Fn(&S) -> bool,
+{
+    pub fn new(test: F) -> Self {
+        Self { test, _phantom: PhantomData }
+    }
+}
+impl<S, F> Constraint for DeadEnd<S, F>
+where
+    F: Fn(&S) -> bool,
+{
+    type State = S;
+
+    fn is_dead_end(&self, state: &S) -> bool {
+        (self.test)(state)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{atomic::AtomicBool, Arc};
+
+    use crate::testing::{InOrder, Knapsack, KnapsackState};
+    use crate::{Constrained, ConstrainedProblem, Decision, Mdd, MinLP, Problem, SimpleMddBuilder, Var};
+
+    use super::{AllDifferent, Constraint, DeadEnd, Precedence, Propagator, ResourceLimit};
+
+    /// The values assigned so far (one bit per value)
+    type Assigned = u32;
+    fn assigned(state: &Assigned, val: isize) -> bool {
+        state & (1 << val) != 0
+    }
+    fn decide(val: isize) -> Decision {
+        Decision::new(Var::new(0), val)
+    }
+
+    #[test]
+    fn each_constraint_filters_what_it_should() {
+        let precedence = Precedence::new(1, 2, assigned);
+        assert!(precedence.allows(&0b010, decide(2)));
+        assert!(!precedence.allows(&0b000, decide(2)));
+        assert!(precedence.allows(&0b000, decide(3)));
+
+        let all_different = AllDifferent::new(assigned);
+        assert!(all_different.allows(&0b010, decide(2)));
+        assert!(!all_different.allows(&0b010, decide(1)));
+
+        let resource = ResourceLimit::new(10, |s: &Assigned| s.count_ones() as isize * 4, |_: &Assigned, d: Decision| d.val);
+        assert!(resource.allows(&0b11, decide(2)));
+        assert!(!resource.allows(&0b11, decide(3)));
+        assert!(!resource.is_dead_end(&0b11));
+        assert!(resource.is_dead_end(&0b111));
+
+        let dead_end = DeadEnd::new(|s: &Assigned| *s == 0);
+        assert!(dead_end.allows(&0, decide(0)));
+        assert!(dead_end.is_dead_end(&0));
+        assert!(!dead_end.is_dead_end(&1));
+    }
+
+    #[test]
+    fn a_propagator_is_the_conjunction_of_its_constraints() {
+        let empty = Propagator::<Assigned>::new();
+        assert!(empty.is_empty());
+        assert!(empty.allows(&0, decide(5)) && !empty.is_dead_end(&0));
+
+        let propagator = Propagator::new()
+            .with(AllDifferent::new(assigned))
+            .with(Precedence::new(1, 2, assigned))
+            .with(DeadEnd::new(|s: &Assigned| s.count_ones() > 2));
+        assert_eq!(3, propagator.len());
+        assert!(propagator.allows(&0b010, decide(2)));
+        assert!(!propagator.allows(&0b010, decide(1)));
+        assert!(!propagator.allows(&0b001, decide(2)));
+        assert!(propagator.is_dead_end(&0b111));
+
+        // a whole propagator is a constraint of the constrained problem
+        let problem = ConstrainedProblem::new(Knapsack::new(10, &[3, 4], &[5, 6]));
+        let wrapped = Propagator::new().with(Constrained(Propagator::new().with(DeadEnd::new(|s: &KnapsackState| s.room < 7))));
+        let state   = problem.transition(&problem.initial_state(), Decision::new(Var::new(1), 1));
+        assert!(wrapped.is_dead_end(&state));
+        assert!(!wrapped.is_dead_end(&problem.initial_state()));
+    }
+
+    #[test]
+    fn the_domains_are_filtered_by_the_propagator() {
+        let problem    = Knapsack::small();
+        let capacity   = 30;
+        let (full, weight) = (problem.capacity, problem.weight.clone());
+        let propagator = Propagator::new().with(ResourceLimit::new(
+            capacity,
+            move |s: &KnapsackState| full - s.room,
+            move |_: &KnapsackState, d: Decision| d.val * weight[d.var.id()],
+        ));
+
+        let mut values = vec![];
+        let state = problem.transition(&problem.initial_state(), Decision::new(Var::new(7), 1));
+        let state = problem.transition(&state, Decision::new(Var::new(10), 1));
+        propagator.for_each_in_domain(&problem, &state, Var::new(1), |d| values.push(d.val));
+        assert_eq!(vec![0], values); // 15 + 4 + 12 fits in 50 but not in 30
+        values.clear();
+        propagator.for_each_in_domain(&problem, &state, Var::new(2), |d| values.push(d.val));
+        assert_eq!(vec![0, 1], values);
+
+        let mut mdd = SimpleMddBuilder::default()
+            .problem(&problem)
+            .var_ordering(InOrder(problem.nb_vars()))
+            .node_selection(MinLP::new())
+            .kill_switch(Arc::new(AtomicBool::new(false)))
+            .proba(0.0)
+            .propagator(Arc::new(propagator))
+            .build()
+            .unwrap();
+        let small = Knapsack::new(capacity, &problem.weight, &problem.profit);
+        assert_eq!(Some(small.brute_force()), mdd.exact());
+        assert!(mdd.is_exact());
+    }
+}
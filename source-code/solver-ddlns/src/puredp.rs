@@ -1,7 +1,9 @@
 use crate::basics::*;
+use crate::{Cost, Propagator};
 use derive_builder::Builder;
 use rustc_hash::FxHashMap;
 use std::{
+    cmp::Reverse,
     hash::Hash,
     rc::Rc,
     sync::{atomic::AtomicBool, Arc},
@@ -28,10 +30,80 @@ where
     /// An atomic boolean acting as a kill switch. Whenever this flag turns true,
     /// the progress must stop and return the best known solution asap.
     kill_switch: Arc<AtomicBool>,
+    /// An optional set of constraints used to filter the domains and to
+    /// discard the dead-end states
+    #[builder(default, setter(strip_option))]
+    propagator: Option<Arc<Propagator<P::State>>>,
+    /// An optional cap on the number of states memoized in the cache (this
+    /// bounds the memory used by the solver). Whenever the cache is full, half
+    /// of its entries are evicted: the deepest states first (they are the
+    /// cheapest to solve again) and the oldest among equally deep ones. The
+    /// solver remains exact but it may need to solve some subproblems more
+    /// than once.
+    #[builder(default, setter(strip_option))]
+    cache_limit: Option<usize>,
+    /// An optional initial solution and its value. The value is used as an
+    /// initial bound to prune the states whose estimate shows they cannot
+    /// improve it.
+    #[builder(default)]
+    initial_val: Option<P::Cost>,
+    #[builder(default)]
+    initial_sol: Option<Solution>,
 }
 /// Convenient type alias for when we are solving the problem and we care about
 /// the actual final solution (assignment)
-type FatCache<S> = FxHashMap<Rc<S>, (isize, Rc<S>, Option<Decision>, Option<Duration>)>;
+type FatCache<S, C> = FxHashMap<Rc<S>, CacheEntry<S, C>>;
+
+/// The memo of `minimize_from`. Along with the entries, it keeps a clock that
+/// stamps each entry when it is stored, so that a full cache knows which of
+/// its entries are the oldest.
+struct Cache<S, C> {
+    entries: FatCache<S, C>,
+    clock: usize,
+}
+
+/// What the cache knows about some state. Because of the pruning, the value
+/// of a state is not always known exactly. When the exploration of a state
+/// did not find any completion improving the incumbent, the cache only
+/// remembers a lower bound on the value of that state.
+struct CacheEntry<S, C> {
+    /// The optimal value of the state (or a lower bound when it is not exact)
+    value: C,
+    /// The next state and the decision which lead to that value
+    via: Rc<S>,
+    decision: Option<Decision>,
+    exact: bool,
+    /// The depth of the state (the number of decisions from the root)
+    depth: usize,
+    /// The time at which the entry was stored in the cache
+    stamp: usize,
+}
+
+impl<S, C> CacheEntry<S, C> {
+    /// The entries with the smallest keys are the last ones to be evicted
+    fn eviction_key(&self) -> (usize, Reverse<usize>) {
+        (self.depth, Reverse(self.stamp))
+    }
+}
+
+/// The explicit stack of `minimize_from` holds one such frame per layer
+struct Frame<S, C> {
+    state: Rc<S>,
+    /// The cost of the path from the root to this state
+    prefix: C,
+    /// The (filtered) domain of the variable branched on in this state
+    decisions: Vec<Decision>,
+    /// The position of the decision currently being explored
+    pos: usize,
+    best: CacheEntry<S, C>,
+}
+
+/// The best solution found so far
+struct Incumbent<C> {
+    value: C,
+    solution: Option<Solution>,
+    time: Option<Duration>,
+}
 
 impl<P, V> PureDp<P, V>
 where
@@ -41,57 +113,47 @@ where
 {
     /// Implements a generic dynamic programming resolution strategy which aims
     /// at returning the best possible value for the problem and the assignment
-    /// to variables which is required to actually reach that value.
-    pub fn minimize(&self) -> ResolutionOutcome {
-        let mut cache = FatCache::default();
+    /// to variables which is required to actually reach that value. The search
+    /// is a branch and bound: it keeps an incumbent solution which is updated
+    /// as soon as a better full path is found. When the search is interrupted,
+    /// that incumbent is returned.
+    pub fn minimize(&self) -> ResolutionOutcome<P::Cost> {
+        let mut incumbent = Incumbent {
+            value: self.initial_val.unwrap_or(P::Cost::MAX),
+            solution: None,
+            time: None,
+        };
         let initial = Rc::new(self.problem.initial_state());
-        self.minimize_rec(Rc::clone(&initial), &mut cache);
+        self.minimize_from(initial, &mut incumbent);
 
-        // build solution from cache
-        let killed = self.killed();
-        let status = if killed {
-            ResolutionStatus::Open{improved: true}
+        let killed   = self.killed();
+        let improved = incumbent.solution.is_some();
+        let status   = if killed {
+            ResolutionStatus::Open{improved}
         } else {
-            ResolutionStatus::Closed{improved: true}
+            ResolutionStatus::Closed{improved}
+        };
+        let time_to_prove = if killed {
+            None
+        } else {
+            Some(self.start_time.elapsed())
         };
 
-        let mut curr = &initial;
-        let cached = cache.get(curr);
-
-        if let Some((opt, _, _, t)) = cached {
-            let mut solution = vec![];
-            solution.reserve_exact(self.problem.nb_vars());
-
-            while let Some((_, via, dec, _)) = cache.get(curr) {
-                if let Some(dec) = dec {
-                    solution.push(*dec);
-                }
-                curr = via;
-            }
-
-            let best_value = Some(*opt);
-            let best_sol = Some(Solution::from(solution.iter().copied()));
-            let time_to_best = *t;
-            let time_to_prove = if killed {
-                None
-            } else {
-                Some(self.start_time.elapsed())
-            };
-
+        if improved {
             ResolutionOutcome {
                 status,
-                best_value,
-                best_sol,
-                time_to_best,
+                best_value: Some(incumbent.value),
+                best_sol: incumbent.solution,
+                time_to_best: incumbent.time,
                 time_to_prove,
             }
         } else {
             ResolutionOutcome {
                 status,
-                best_value: None,
-                best_sol: None,
+                best_value: self.initial_val,
+                best_sol: self.initial_sol.clone(),
                 time_to_best: None,
-                time_to_prove: None,
+                time_to_prove,
             }
         }
     }
@@ -101,46 +163,296 @@ where
         self.kill_switch.load(std::sync::atomic::Ordering::Relaxed)
     }
 
-    /// This is where the heavy lifting of minimize is achieved. This is the
-    /// method which encapsulates the resolution and recursion.
-    fn minimize_rec(
-        &self,
-        state: Rc<P::State>,
-        cache: &mut FatCache<P::State>,
-    ) -> (isize, Rc<P::State>, Option<Decision>, Option<Duration>) {
-        if self.killed() {
-            return (isize::MAX, state, None, None);
-        }
-        if let Some((value, endstate, decision, t)) = cache.get(&state) {
-            (*value, Rc::clone(endstate), *decision, *t)
+    /// Iterates over the domain of `var` (filtered by the propagator if any)
+    fn for_each_in_domain(&self, state: &P::State, var: Var, f: impl FnMut(Decision)) {
+        if let Some(propagator) = self.propagator.as_ref() {
+            propagator.for_each_in_domain(&self.problem, state, var, f)
         } else {
-            let next_var = self.var_ordering.next(&mut std::iter::once(state.as_ref()));
-            if let Some(var) = next_var {
-                let mut best = (isize::MAX, Rc::clone(&state), None, None);
-                self.problem
-                    .for_each_in_domain(state.as_ref(), var, |decision| {
-                        if self.killed() {
-                            return;
-                        }
-                        let next_state = Rc::new(self.problem.transition(state.as_ref(), decision));
-                        let tx_cost = self.problem.transition_cost(state.as_ref(), decision);
-
-                        let (opt, _, _, t) = self.minimize_rec(Rc::clone(&next_state), cache);
-                        let tot_cost = tx_cost.saturating_add(opt);
-                        if tot_cost < best.0 {
-                            best = (tot_cost, next_state, Some(decision), t)
-                        }
-                    });
-                cache.insert(state, best.clone());
-                best
+            self.problem.for_each_in_domain(state, var, f)
+        }
+    }
+
+    /// Tells whether the propagator (if any) detects the state is a dead end
+    fn is_dead_end(&self, state: &P::State) -> bool {
+        self.propagator.as_ref().is_some_and(|p| p.is_dead_end(state))
+    }
+
+    /// Tells whether the given state is terminal (all variables are assigned)
+    fn is_terminal(&self, state: &P::State) -> bool {
+        self.var_ordering.next(&mut std::iter::once(state)).is_none()
+    }
+
+    /// This is where the heavy lifting of minimize is achieved: it explores
+    /// the state space from the given root and updates the incumbent. Instead
+    /// of recursing once per variable, it keeps an explicit stack of frames on
+    /// the heap. This way, the depth of the problems it can solve is not
+    /// bounded by the size of the thread stack.
+    ///
+    /// The value of a state only needs to be known when it can improve the
+    /// incumbent. Hence, a state is pruned as soon as its estimate shows it
+    /// cannot, and once a state has been explored, the cache either remembers
+    /// its exact value (when it did not exceed the threshold it had to beat)
+    /// or the threshold itself as a lower bound.
+    fn minimize_from(&self, root: Rc<P::State>, incumbent: &mut Incumbent<P::Cost>) {
+        let mut cache = Cache { entries: FatCache::default(), clock: 0 };
+        let mut stack = vec![];
+        if self.is_terminal(root.as_ref()) {
+            let value = self.problem.initial_value();
+            if value < incumbent.value {
+                self.improve(incumbent, value, vec![]);
+            }
+        } else if let Some(frame) = self.frame(root, P::Cost::ZERO, incumbent) {
+            stack.push(frame);
+        }
+
+        while !stack.is_empty() {
+            if self.killed() {
+                return;
+            }
+            let top = stack.len() - 1;
+            let Some(decision) = stack[top].decisions.get(stack[top].pos).copied() else {
+                // all decisions have been explored: the frame is done and its
+                // value is reported to the parent frame
+                let Frame { state, prefix, mut best, .. } = stack.pop().expect("frame");
+                // the children that were pruned (or whose value is only a lower
+                // bound) cannot be better than the threshold: the best value is
+                // exact even when it is the one of the incumbent
+                let threshold = incumbent.value.saturating_sub(prefix);
+                best.exact = best.value <= threshold;
+                if !best.exact {
+                    best = CacheEntry { value: threshold, via: Rc::clone(&state), decision: None, exact: false, depth: 0, stamp: 0 };
+                }
+                let (value, exact) = (best.value, best.exact);
+                self.memoize(&mut cache, Rc::clone(&state), best, stack.len());
+                if let Some(parent) = stack.last_mut() {
+                    if exact {
+                        self.relax(parent, state, value);
+                    }
+                    parent.pos += 1;
+                }
+                continue;
+            };
+
+            let frame  = &stack[top];
+            let next   = Rc::new(self.problem.transition(frame.state.as_ref(), decision));
+            let prefix = frame.prefix.saturating_add(self.problem.transition_cost(frame.state.as_ref(), decision));
+            if self.is_dead_end(next.as_ref()) {
+                stack[top].pos += 1;
+                continue;
+            }
+
+            if let Some(entry) = cache.entries.get(&next) {
+                let (value, exact) = (entry.value, entry.exact);
+                let total = prefix.saturating_add(value);
+                if total >= incumbent.value {
+                    // the state cannot improve the incumbent
+                    if exact {
+                        self.relax(&mut stack[top], next, value);
+                    }
+                    stack[top].pos += 1;
+                    continue;
+                }
+                if exact {
+                    let completion = self.completion(&cache.entries, &next);
+                    if let Some(completion) = completion {
+                        let mut path = Self::path(&stack);
+                        path.extend(completion);
+                        self.improve(incumbent, total, path);
+                        self.relax(&mut stack[top], next, value);
+                        stack[top].pos += 1;
+                        continue;
+                    }
+                }
+                // the cached knowledge is not enough (or its completion has
+                // been flushed): the state must be explored again
+                cache.entries.remove(&next);
+            }
+
+            if self.is_terminal(next.as_ref()) {
+                let value = self.problem.initial_value();
+                let total = prefix.saturating_add(value);
+                if total < incumbent.value {
+                    self.improve(incumbent, total, Self::path(&stack));
+                }
+                self.relax(&mut stack[top], next, value);
+                stack[top].pos += 1;
+            } else if let Some(child) = self.frame(next, prefix, incumbent) {
+                stack.push(child);
             } else {
-                (
-                    self.problem.initial_value(),
-                    state,
-                    None,
-                    Some(self.start_time.elapsed()),
-                )
+                stack[top].pos += 1;
+            }
+        }
+    }
+
+    /// Creates the frame used to explore the given (non terminal) state unless
+    /// its estimate shows it cannot improve the incumbent
+    fn frame(&self, state: Rc<P::State>, prefix: P::Cost, incumbent: &Incumbent<P::Cost>) -> Option<Frame<P::State, P::Cost>> {
+        let bound = prefix
+            .saturating_add(self.problem.initial_value())
+            .saturating_add(self.problem.estimate(state.as_ref()));
+        if bound >= incumbent.value {
+            return None;
+        }
+        let var = self.var_ordering.next(&mut std::iter::once(state.as_ref()))?;
+        let mut decisions = vec![];
+        self.for_each_in_domain(state.as_ref(), var, |decision| decisions.push(decision));
+        Some(Frame {
+            best: CacheEntry { value: P::Cost::MAX, via: Rc::clone(&state), decision: None, exact: true, depth: 0, stamp: 0 },
+            state,
+            prefix,
+            decisions,
+            pos: 0,
+        })
+    }
+
+    /// Updates the best value of the frame given the exact value of the state
+    /// reached through the current decision
+    fn relax(&self, frame: &mut Frame<P::State, P::Cost>, next_state: Rc<P::State>, value: P::Cost) {
+        let decision = frame.decisions[frame.pos];
+        let tx_cost  = self.problem.transition_cost(frame.state.as_ref(), decision);
+        let tot_cost = tx_cost.saturating_add(value);
+        if tot_cost < frame.best.value {
+            frame.best.value    = tot_cost;
+            frame.best.via      = next_state;
+            frame.best.decision = Some(decision);
+        }
+    }
+
+    /// Returns the decisions on the path from the root to the state reached
+    /// by the decision currently explored in the topmost frame
+    fn path(stack: &[Frame<P::State, P::Cost>]) -> Vec<Decision> {
+        stack.iter().map(|f| f.decisions[f.pos]).collect()
+    }
+
+    /// Returns the decisions of the best completion of the given state (as
+    /// remembered by the cache) or None when that completion has been flushed
+    fn completion(&self, cache: &FatCache<P::State, P::Cost>, state: &Rc<P::State>) -> Option<Vec<Decision>> {
+        let mut decisions = vec![];
+        let mut curr = state;
+        loop {
+            match cache.get(curr) {
+                Some(CacheEntry { via, decision: Some(decision), exact: true, .. }) => {
+                    decisions.push(*decision);
+                    curr = via;
+                }
+                None if self.is_terminal(curr.as_ref()) => return Some(decisions),
+                _ => return None,
+            }
+        }
+    }
+
+    /// Records a new incumbent solution
+    fn improve(&self, incumbent: &mut Incumbent<P::Cost>, value: P::Cost, decisions: Vec<Decision>) {
+        incumbent.value    = value;
+        incumbent.solution = Some(Solution::from(decisions.into_iter()));
+        incumbent.time     = Some(self.start_time.elapsed());
+    }
+
+    /// Stores what is known about a state (found at the given depth) in the
+    /// cache. When the cache has a limited size and it is full, half of its
+    /// entries are evicted first.
+    fn memoize(&self, cache: &mut Cache<P::State, P::Cost>, state: Rc<P::State>, mut entry: CacheEntry<P::State, P::Cost>, depth: usize) {
+        if let Some(limit) = self.cache_limit {
+            if cache.entries.len() >= limit {
+                Self::evict(&mut cache.entries, limit / 2);
             }
         }
+        entry.depth  = depth;
+        entry.stamp  = cache.clock;
+        cache.clock += 1;
+        cache.entries.insert(state, entry);
     }
+
+    /// Evicts the deepest (and among those, the oldest) entries of the cache
+    /// until only `keep` of them remain. Because no two entries share the same
+    /// stamp, the eviction keys are all distinct and exactly `keep` entries
+    /// survive.
+    fn evict(cache: &mut FatCache<P::State, P::Cost>, keep: usize) {
+        let mut keys = cache.values().map(CacheEntry::eviction_key).collect::<Vec<_>>();
+        if keep >= keys.len() {
+            return;
+        }
+        let pivot = *keys.select_nth_unstable(keep).1;
+        cache.retain(|_, entry| entry.eviction_key() < pivot);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        rc::Rc,
+        sync::{atomic::AtomicBool, Arc},
+        time::Instant,
+    };
+
+    use crate::testing::{InOrder, Knapsack, StepByStep, Walk, WalkState};
+    use crate::{Problem, ResolutionStatus, VariableOrdering};
+
+    use super::{CacheEntry, FatCache, PureDp, PureDpBuilder};
+
+    fn solver<P, V>(problem: P, var_ordering: V) -> PureDpBuilder<P, V>
+    where
+        P: Problem + Clone,
+        V: VariableOrdering<State = P::State> + Clone,
+    {
+        let mut builder = PureDpBuilder::default();
+        builder
+            .problem(problem)
+            .var_ordering(var_ordering)
+            .start_time(Instant::now())
+            .kill_switch(Arc::new(AtomicBool::new(false)));
+        builder
+    }
+
+    #[test]
+    fn it_finds_the_optimum_of_the_knapsack() {
+        let problem = Knapsack::small();
+        let solver: PureDp<_, _> = solver(&problem, InOrder(problem.nb_vars())).build().unwrap();
+        let outcome = solver.minimize();
+        assert!(matches!(outcome.status, ResolutionStatus::Closed { improved: true }));
+        assert_eq!(Some(problem.brute_force()), outcome.best_value);
+        let sol = outcome.best_sol.expect("a solution");
+        assert_eq!(problem.brute_force(), problem.evaluate(&InOrder(problem.nb_vars()), &sol));
+    }
+
+    #[test]
+    fn the_states_that_match_the_incumbent_are_memoized_exactly() {
+        // every state of the walk is reached by many paths: the walk can only
+        // be solved with few transitions if the value of the states on the
+        // path of the incumbent is remembered as exact
+        let walk    = Walk::new(30, 5);
+        let outcome = solver(&walk, StepByStep(30)).build().unwrap().minimize();
+        assert_eq!(Some(walk.optimum()), outcome.best_value);
+        assert!(walk.transitions.get() < 2 * 3 * 30 * 5, "{} transitions", walk.transitions.get());
+    }
+
+    #[test]
+    fn a_small_cache_evicts_entries_but_remains_exact() {
+        // the walk has 60 states: half of them fit in the cache
+        let walk    = Walk::new(12, 5);
+        let outcome = solver(&walk, StepByStep(12)).cache_limit(30).build().unwrap().minimize();
+        assert!(matches!(outcome.status, ResolutionStatus::Closed { improved: true }));
+        assert_eq!(Some(walk.optimum()), outcome.best_value);
+        assert_eq!(walk.optimum(), walk.evaluate(&StepByStep(12), &outcome.best_sol.unwrap()));
+    }
+
+    #[test]
+    fn the_deepest_and_oldest_entries_are_evicted_first() {
+        let mut cache = FatCache::default();
+        // (depth, stamp) of each entry
+        for (pos, (depth, stamp)) in [(3, 0), (1, 1), (2, 2), (1, 3), (3, 4), (2, 5)].into_iter().enumerate() {
+            let state = Rc::new(WalkState { depth, pos });
+            let entry = CacheEntry { value: 0, via: Rc::clone(&state), decision: None, exact: true, depth, stamp };
+            cache.insert(state, entry);
+        }
+        PureDp::<&Walk, StepByStep>::evict(&mut cache, 3);
+        let mut kept = cache.values().map(|e| (e.depth, e.stamp)).collect::<Vec<_>>();
+        kept.sort_unstable();
+        assert_eq!(vec![(1, 1), (1, 3), (2, 5)], kept);
+
+        // there is nothing to evict when the cache holds few enough entries
+        PureDp::<&Walk, StepByStep>::evict(&mut cache, 3);
+        assert_eq!(3, cache.len());
+    }
+
 }
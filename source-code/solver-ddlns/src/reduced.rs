@@ -0,0 +1,358 @@
+//! This module implements reduced exact decision diagrams. The diagram is first
+//! compiled top-down (keeping all the edges, not only the best parent of each
+//! node as `SimpleMdd` does). Then, a bottom-up pass merges all the nodes of a
+//! layer having the exact same outgoing edges (same labels, same weights, same
+//! children) -- that is, nodes rooting identical sub-diagrams -- and it drops
+//! the nodes which cannot reach the terminal node. This is the canonical
+//! reduction of BDDs/MDDs. The result is kept as a standalone structure which
+//! can be queried after the compilation. Because it is exact, this is only
+//! meant to be used on small instances.
+//!
+//! Since all the edges are kept, the diagram also knows every optimal path
+//! (not just one of them): an edge is optimal when its weight plus the
+//! cost-to-go of its child equals the cost-to-go of its parent. This is used
+//! to count and enumerate all the optimal solutions of an instance.
+
+use std::hash::Hash;
+
+use rustc_hash::FxHashMap;
+
+use crate::{Cost, Decision, Problem, Propagator, Solution, Var, VariableOrdering};
+
+/// The identifier of a node in a reduced diagram
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ReducedNode(usize);
+impl ReducedNode {
+    pub fn id(self) -> usize {
+        self.0
+    }
+}
+
+/// An edge of a reduced diagram
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ReducedEdge<C> {
+    /// The value assigned to the variable of the layer
+    pub val: isize,
+    /// The cost of the transition
+    pub weight: C,
+    /// The node this edge leads to
+    pub to: ReducedNode,
+}
+
+/// A reduced exact decision diagram
+///
+/// # Example
+/// ```
+/// # use papier_lns::{DpModel, ReducedMdd, Var};
+/// // the best profit (7) is reached by picking one of the two items that
+/// // weigh 2 along with one of the two items that weigh 3
+/// let model = "
+///     param capacity = 5
+///     param weight   = [2, 3, 2, 3]
+///     param profit   = [3, 4, 3, 4]
+///     variables len(weight)
+///     state int room = capacity
+///     domain     {0, 1}
+///     require    val * weight[var] <= room
+///     transition room = room - val * weight[var]
+///     cost       -val * profit[var]
+/// ".parse::<DpModel>().unwrap();
+///
+/// let mdd = ReducedMdd::compile(&model, &model.ordering(), None);
+/// assert_eq!(Some(-7), mdd.optimum());
+/// assert_eq!(10, mdd.nb_solutions());
+/// assert_eq!(4, mdd.nb_optimal_solutions());
+///
+/// // the optimal solutions come in lexicographic order
+/// let picked = mdd.optimal_solutions()
+///     .map(|sol| (0..4).map(|i| sol[Var::new(i)]).collect::<Vec<_>>())
+///     .collect::<Vec<_>>();
+/// assert_eq!(vec![vec![0, 0, 1, 1], vec![0, 1, 1, 0], vec![1, 0, 0, 1], vec![1, 1, 0, 0]], picked);
+/// ```
+#[derive(Debug, Clone)]
+pub struct ReducedMdd<C> {
+    /// The value of the root node
+    initial_value: C,
+    /// The variable each layer branches on
+    vars: Vec<Var>,
+    /// The nodes of each layer (the last one only holds the terminal node)
+    layers: Vec<Vec<ReducedNode>>,
+    /// The outgoing edges of each node. Nodes are created bottom-up, hence the
+    /// children of a node always have a smaller identifier than the node.
+    edges: Vec<Vec<ReducedEdge<C>>>,
+    /// The length of the shortest path from each node to the terminal
+    cost_to_go: Vec<C>,
+    /// The root node (None when the problem is infeasible)
+    root: Option<ReducedNode>,
+}
+
+/// The terminal node is always the first node to be created
+const TERMINAL: ReducedNode = ReducedNode(0);
+
+impl<C: Cost> ReducedMdd<C> {
+    /// Compiles the exact diagram of the given problem and reduces it. When a
+    /// propagator is given, it filters the domains and removes dead ends.
+    pub fn compile<P, V>(problem: &P, var_ord: &V, propagator: Option<&Propagator<P::State>>) -> Self
+    where
+        P: Problem<Cost = C>,
+        V: VariableOrdering<State = P::State>,
+    {
+        // ---- top down compilation --------------------------------------------
+        // edges of each node of each layer: (value, weight, position of child)
+        let mut vars   = vec![];
+        let mut layers: Vec<Vec<Vec<(isize, C, usize)>>> = vec![];
+        let mut states = vec![problem.initial_state()];
+
+        while !states.is_empty() {
+            let Some(var) = var_ord.next(&mut states.iter()) else { break };
+            let mut next  = FxHashMap::<P::State, usize>::default();
+            let mut layer = Vec::with_capacity(states.len());
+            for state in states.iter() {
+                let mut out = vec![];
+                problem.for_each_in_domain(state, var, |decision| {
+                    if !propagator.is_none_or(|p| p.allows(state, decision)) {
+                        return;
+                    }
+                    let child  = problem.transition(state, decision);
+                    if propagator.is_some_and(|p| p.is_dead_end(&child)) {
+                        return;
+                    }
+                    let weight = problem.transition_cost(state, decision);
+                    let fresh  = next.len();
+                    let pos    = *next.entry(child).or_insert(fresh);
+                    out.push((decision.val, weight, pos));
+                });
+                layer.push(out);
+            }
+            vars.push(var);
+            layers.push(layer);
+
+            let mut children = next.into_iter().map(|(s, pos)| (pos, s)).collect::<Vec<_>>();
+            children.sort_unstable_by_key(|(pos, _)| *pos);
+            states = children.into_iter().map(|(_, s)| s).collect();
+        }
+
+        // ---- bottom up reduction ---------------------------------------------
+        let mut edges  = vec![vec![]];
+        let mut result = vec![vec![]; layers.len() + 1];
+        // canonical node of each node of the layer below (None = dead end)
+        let mut below  = vec![Some(TERMINAL); states.len()];
+        if !states.is_empty() {
+            result[layers.len()].push(TERMINAL);
+        }
+        for (depth, layer) in layers.iter().enumerate().rev() {
+            let mut unique  = FxHashMap::<Vec<ReducedEdge<C>>, ReducedNode>::default();
+            let mut current = Vec::with_capacity(layer.len());
+            for out in layer.iter() {
+                let mut signature = out.iter()
+                    .filter_map(|(val, weight, pos)| below[*pos]
+                        .map(|to| ReducedEdge { val: *val, weight: *weight, to }))
+                    .collect::<Vec<ReducedEdge<C>>>();
+
+                if signature.is_empty() {
+                    current.push(None);
+                } else {
+                    signature.sort_unstable();
+                    let node = *unique.entry(signature.clone()).or_insert_with(|| {
+                        let node = ReducedNode(edges.len());
+                        edges.push(signature);
+                        result[depth].push(node);
+                        node
+                    });
+                    current.push(Some(node));
+                }
+            }
+            below = current;
+        }
+        let root = below.first().copied().flatten();
+
+        // ---- shortest paths to the terminal ----------------------------------
+        let mut cost_to_go = vec![C::ZERO; edges.len()];
+        for (i, out) in edges.iter().enumerate().skip(1) {
+            cost_to_go[i] = out.iter()
+                .map(|e| e.weight.saturating_add(cost_to_go[e.to.0]))
+                .min()
+                .unwrap_or(C::MAX);
+        }
+
+        if root.is_none() {
+            result.iter_mut().for_each(|l| l.clear());
+        }
+
+        Self {
+            initial_value: problem.initial_value(),
+            vars,
+            layers: result,
+            edges,
+            cost_to_go,
+            root,
+        }
+    }
+
+    /// Returns true iff the diagram holds no solution (the problem is infeasible)
+    pub fn is_empty(&self) -> bool {
+        self.root.is_none()
+    }
+    /// Returns the root node of the diagram (if the problem is feasible)
+    pub fn root(&self) -> Option<ReducedNode> {
+        self.root
+    }
+    /// Returns the terminal node of the diagram (if the problem is feasible)
+    pub fn terminal(&self) -> Option<ReducedNode> {
+        self.root.map(|_| TERMINAL)
+    }
+    /// Returns the number of layers (including the terminal one)
+    pub fn nb_layers(&self) -> usize {
+        self.layers.len()
+    }
+    /// Returns the variable the given layer branches on (None for the terminal layer)
+    pub fn layer_var(&self, depth: usize) -> Option<Var> {
+        self.vars.get(depth).copied()
+    }
+    /// Returns the nodes of the given layer
+    pub fn layer(&self, depth: usize) -> &[ReducedNode] {
+        &self.layers[depth]
+    }
+    /// Returns the width (number of nodes) of the widest layer
+    pub fn width(&self) -> usize {
+        self.layers.iter().map(|l| l.len()).max().unwrap_or(0)
+    }
+    /// Returns the number of nodes in the diagram
+    pub fn nb_nodes(&self) -> usize {
+        self.layers.iter().map(|l| l.len()).sum()
+    }
+    /// Returns the number of edges in the diagram
+    pub fn nb_edges(&self) -> usize {
+        self.layers.iter().flatten().map(|n| self.edges[n.0].len()).sum()
+    }
+    /// Returns the outgoing edges of the given node (sorted by value)
+    pub fn edges(&self, node: ReducedNode) -> &[ReducedEdge<C>] {
+        &self.edges[node.0]
+    }
+    /// Returns the length of the shortest path from the given node to the terminal
+    pub fn cost_to_go(&self, node: ReducedNode) -> C {
+        self.cost_to_go[node.0]
+    }
+    /// Returns the optimal objective value of the problem
+    pub fn optimum(&self) -> Option<C> {
+        self.root.map(|r| self.initial_value.saturating_add(self.cost_to_go[r.0]))
+    }
+    /// Returns an optimal solution of the problem
+    pub fn best_solution(&self) -> Option<Solution> {
+        let mut node = self.root?;
+        let mut decisions = vec![];
+        for var in self.vars.iter().copied() {
+            let target = self.cost_to_go[node.0];
+            let edge   = self.edges[node.0].iter()
+                .find(|e| e.weight.saturating_add(self.cost_to_go[e.to.0]) == target)?;
+            decisions.push(Decision::new(var, edge.val));
+            node = edge.to;
+        }
+        Some(Solution::from(decisions.into_iter()))
+    }
+    /// Returns the number of feasible solutions of the problem (saturates at
+    /// `u128::MAX`)
+    pub fn nb_solutions(&self) -> u128 {
+        self.count_paths(|_, _| true)
+    }
+    /// Returns the number of distinct optimal solutions of the problem
+    /// (saturates at `u128::MAX`)
+    pub fn nb_optimal_solutions(&self) -> u128 {
+        self.count_paths(|node, edge| self.is_optimal(node, edge))
+    }
+    /// Lazily enumerates all the optimal solutions of the problem
+    pub fn optimal_solutions(&self) -> OptimalSolutions<'_, C> {
+        OptimalSolutions { mdd: self, stack: vec![], started: false }
+    }
+    /// Returns the optimal outgoing edges of the given node: these are the
+    /// edges leading to the best-value children (symmetrically, a node is a
+    /// best-value parent of all the children it reaches by an optimal edge)
+    pub fn optimal_edges(&self, node: ReducedNode) -> impl Iterator<Item = &ReducedEdge<C>> + '_ {
+        self.edges[node.0].iter().filter(move |e| self.is_optimal(node, e))
+    }
+    /// Returns the objective value of the given solution if it is part of the
+    /// diagram, and None otherwise (e.g. when the solution is infeasible)
+    pub fn evaluate(&self, sol: &Solution) -> Option<C> {
+        let mut node  = self.root?;
+        let mut value = self.initial_value;
+        for var in self.vars.iter().copied() {
+            let edge = self.edges[node.0].iter().find(|e| e.val == sol[var])?;
+            value = value.saturating_add(edge.weight);
+            node  = edge.to;
+        }
+        Some(value)
+    }
+
+    /// Tells whether the given edge lies on some shortest path to the terminal
+    fn is_optimal(&self, node: ReducedNode, edge: &ReducedEdge<C>) -> bool {
+        edge.weight.saturating_add(self.cost_to_go[edge.to.0]) == self.cost_to_go[node.0]
+    }
+    /// Counts the paths from the root to the terminal which only use the edges
+    /// accepted by the given filter. The children of a node always have a
+    /// smaller identifier, hence the counts are computed in a single pass.
+    fn count_paths(&self, filter: impl Fn(ReducedNode, &ReducedEdge<C>) -> bool) -> u128 {
+        let Some(root) = self.root else { return 0 };
+        let mut count = vec![0_u128; self.edges.len()];
+        count[TERMINAL.0] = 1;
+        for i in 1..=root.0 {
+            let node = ReducedNode(i);
+            count[i] = self.edges[i].iter()
+                .filter(|e| filter(node, e))
+                .fold(0_u128, |acc, e| acc.saturating_add(count[e.to.0]));
+        }
+        count[root.0]
+    }
+}
+
+/// A lazy iterator over the optimal solutions of a reduced diagram. The
+/// solutions are enumerated by a depth first traversal of the optimal edges,
+/// hence they come in lexicographic order of the values taken by the layers.
+#[derive(Debug, Clone)]
+pub struct OptimalSolutions<'a, C> {
+    mdd: &'a ReducedMdd<C>,
+    /// The node of each layer on the current path along with the position of
+    /// the (optimal) edge the path follows from it
+    stack: Vec<(ReducedNode, usize)>,
+    started: bool,
+}
+impl<C: Cost> OptimalSolutions<'_, C> {
+    /// Returns the position of the first optimal edge of `node` whose position
+    /// is at least `from`
+    fn next_edge(&self, node: ReducedNode, from: usize) -> Option<usize> {
+        let edges = &self.mdd.edges[node.0];
+        (from..edges.len()).find(|i| self.mdd.is_optimal(node, &edges[*i]))
+    }
+    /// Completes the current path down to the terminal by following the first
+    /// optimal edge of each node
+    fn descend(&mut self, mut node: ReducedNode) {
+        while self.stack.len() < self.mdd.vars.len() {
+            // every node but the terminal reaches the terminal, hence it has
+            // at least one optimal edge
+            let pos = self.next_edge(node, 0).expect("node without optimal edge");
+            self.stack.push((node, pos));
+            node = self.mdd.edges[node.0][pos].to;
+        }
+    }
+}
+impl<C: Cost> Iterator for OptimalSolutions<'_, C> {
+    type Item = Solution;
+
+    fn next(&mut self) -> Option<Solution> {
+        if !self.started {
+            self.started = true;
+            self.descend(self.mdd.root?);
+        } else {
+            loop {
+                let (node, pos) = self.stack.pop()?;
+                if let Some(pos) = self.next_edge(node, pos + 1) {
+                    self.stack.push((node, pos));
+                    self.descend(self.mdd.edges[node.0][pos].to);
+                    break;
+                }
+            }
+        }
+        let decisions = self.stack.iter().zip(self.mdd.vars.iter())
+            .map(|((node, pos), var)| Decision::new(*var, self.mdd.edges[node.0][*pos].val));
+        Some(Solution::from(decisions))
+    }
+}
@@ -0,0 +1,308 @@
+//! This module provides the replay log of `MddLns`. When a `ReplayWriter` is
+//! given to the lns, each iteration is logged along with everything that is
+//! needed to run it again: its start depth, its width, the state of the random
+//! number generator of the mdd and the incumbent it had to improve. The
+//! incumbent solutions are only logged when they change, which keeps the log
+//! compact.
+//!
+//! An iteration of the log is replayed in isolation with `replay`. Provided
+//! that the mdd is configured the same way as during the logged run (the
+//! parameters of the run are recorded in the header of the log for that
+//! purpose), the replay compiles the very same diagram.
+//!
+//! The log is written as json lines: a header followed by one line per
+//! iteration.
+
+use std::{
+    collections::BTreeMap,
+    io::{BufRead, Write},
+    sync::{Mutex, PoisonError},
+};
+
+use rand_xoshiro::Xoshiro256Plus;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::{Cost, Mdd, Solution};
+
+/// The version of the replay log format
+pub const REPLAY_VERSION: u32 = 1;
+
+// ----------------------------------------------------------------------------
+// Errors
+// ----------------------------------------------------------------------------
+#[derive(Debug, thiserror::Error)]
+pub enum ReplayError {
+    #[error("io error {0}")]
+    Io(#[from] std::io::Error),
+    #[error("malformed replay log (line {line}): {msg}")]
+    Malformed { line: usize, msg: String },
+    #[error("unsupported replay log version {0} (expected at most {REPLAY_VERSION})")]
+    UnsupportedVersion(u32),
+    #[error("the log has no iteration {0}")]
+    NoSuchIteration(usize),
+    #[error("parameter {0} is invalid: {1}")]
+    Parameter(String, serde_json::Error),
+}
+
+// ----------------------------------------------------------------------------
+// Log entries
+// ----------------------------------------------------------------------------
+/// The first line of a replay log
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(bound = "C: Cost")]
+pub struct ReplayHeader<C> {
+    pub version: u32,
+    /// The parameters of the run, by name
+    pub parameters: BTreeMap<String, Value>,
+    pub initial_val: Option<C>,
+    pub initial_sol: Option<Solution>,
+}
+
+/// One iteration of the lns
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(bound = "C: Cost")]
+pub struct ReplayEntry<C> {
+    pub iteration: usize,
+    pub start_depth: usize,
+    pub width: usize,
+    /// The state of the random number generator of the mdd when the
+    /// iteration started (if the mdd uses one)
+    pub rng: Option<Xoshiro256Plus>,
+    /// The value of the incumbent when the iteration started
+    pub incumbent: Option<C>,
+    /// The best value of the restricted diagram
+    pub value: Option<C>,
+    /// Was the restricted diagram exact ?
+    pub exact: bool,
+    /// The new incumbent solution (only when the iteration improved it)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub improvement: Option<Solution>,
+}
+
+// ----------------------------------------------------------------------------
+// Writer
+// ----------------------------------------------------------------------------
+/// Writes the replay log of an lns. The log is a debugging aid: failing to
+/// write it never stops the resolution. The writer is `Sync` so that it can be
+/// shared (through an `Arc`) with the lns.
+pub struct ReplayWriter {
+    out: Mutex<Box<dyn Write + Send>>,
+    parameters: BTreeMap<String, Value>,
+}
+impl ReplayWriter {
+    pub fn new<W: Write + Send + 'static>(out: W) -> Self {
+        Self {
+            out: Mutex::new(Box::new(out)),
+            parameters: BTreeMap::new(),
+        }
+    }
+    /// Records the value of some parameter of the run
+    pub fn param<T: Serialize>(mut self, name: &str, value: T) -> Self {
+        // serializing a plain value into a json value cannot fail
+        let value = serde_json::to_value(value).unwrap_or(Value::Null);
+        self.parameters.insert(name.to_string(), value);
+        self
+    }
+    pub(crate) fn header<C: Cost>(&self, initial_val: Option<C>, initial_sol: &Option<Solution>) {
+        let header = ReplayHeader {
+            version: REPLAY_VERSION,
+            parameters: self.parameters.clone(),
+            initial_val,
+            initial_sol: initial_sol.clone(),
+        };
+        self.write(&header);
+    }
+    pub(crate) fn entry<C: Cost>(&self, entry: &ReplayEntry<C>) {
+        self.write(entry);
+    }
+    fn write<T: Serialize>(&self, line: &T) {
+        let mut out = self.out.lock().unwrap_or_else(PoisonError::into_inner);
+        let json    = serde_json::to_string(line).expect("a replay entry is always serializable");
+        let _ = writeln!(out, "{}", json).and_then(|_| out.flush());
+    }
+}
+
+// ----------------------------------------------------------------------------
+// Log
+// ----------------------------------------------------------------------------
+/// A replay log which has been read back
+#[derive(Debug, Clone)]
+pub struct ReplayLog<C> {
+    pub header: ReplayHeader<C>,
+    pub entries: Vec<ReplayEntry<C>>,
+}
+impl<C: Cost> ReplayLog<C> {
+    pub fn read_from<R: BufRead>(input: R) -> Result<Self, ReplayError> {
+        let malformed = |line: usize, e: serde_json::Error| ReplayError::Malformed { line, msg: e.to_string() };
+
+        let mut lines = input.lines();
+        let header    = lines.next().ok_or(ReplayError::Malformed { line: 1, msg: "missing header".to_string() })??;
+        let header    = serde_json::from_str::<ReplayHeader<C>>(&header).map_err(|e| malformed(1, e))?;
+        if header.version > REPLAY_VERSION {
+            return Err(ReplayError::UnsupportedVersion(header.version));
+        }
+        let mut entries = vec![];
+        for (i, line) in lines.enumerate() {
+            let line = line?;
+            if !line.trim().is_empty() {
+                entries.push(serde_json::from_str(&line).map_err(|e| malformed(i + 2, e))?);
+            }
+        }
+        Ok(Self { header, entries })
+    }
+    /// Returns the value of the given parameter of the run (if it was recorded)
+    pub fn param<T: DeserializeOwned>(&self, name: &str) -> Result<Option<T>, ReplayError> {
+        self.header.parameters.get(name)
+            .map(|value| serde_json::from_value(value.clone())
+                .map_err(|e| ReplayError::Parameter(name.to_string(), e)))
+            .transpose()
+    }
+    pub fn entry(&self, iteration: usize) -> Result<&ReplayEntry<C>, ReplayError> {
+        self.entries.iter()
+            .find(|e| e.iteration == iteration)
+            .ok_or(ReplayError::NoSuchIteration(iteration))
+    }
+    /// Returns the incumbent solution when the given iteration started
+    pub fn incumbent_before(&self, iteration: usize) -> Option<Solution> {
+        self.entries.iter()
+            .filter(|e| e.iteration < iteration)
+            .filter_map(|e| e.improvement.as_ref())
+            .last()
+            .or(self.header.initial_sol.as_ref())
+            .cloned()
+    }
+}
+
+// ----------------------------------------------------------------------------
+// Replay
+// ----------------------------------------------------------------------------
+/// The outcome of an iteration which has been replayed
+#[derive(Debug, Clone)]
+pub struct ReplayOutcome<C> {
+    /// The logged iteration
+    pub entry: ReplayEntry<C>,
+    /// The best value of the replayed diagram
+    pub value: Option<C>,
+    pub exact: bool,
+}
+impl<C: Cost> ReplayOutcome<C> {
+    /// Tells whether the replay reproduced the logged iteration
+    pub fn is_faithful(&self) -> bool {
+        self.value == self.entry.value && self.exact == self.entry.exact
+    }
+}
+
+/// Runs the given iteration of the log again with the given mdd. The mdd must
+/// be configured as it was during the logged run.
+pub fn replay<D: Mdd>(mdd: &mut D, log: &ReplayLog<D::Cost>, iteration: usize) -> Result<ReplayOutcome<D::Cost>, ReplayError> {
+    let entry = log.entry(iteration)?.clone();
+    let sol   = log.incumbent_before(iteration);
+    if let Some(rng) = entry.rng.clone() {
+        mdd.set_rng_state(rng);
+    }
+    let best_val = entry.incumbent.unwrap_or(D::Cost::MAX);
+    let value    = mdd.restricted(entry.width, best_val, &sol, entry.start_depth);
+    Ok(ReplayOutcome { value, exact: mdd.is_exact(), entry })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::Write,
+        sync::{atomic::AtomicBool, Arc, Mutex},
+        thread,
+        time::{Duration, Instant},
+    };
+
+    use rand::SeedableRng;
+    use rand_xoshiro::Xoshiro256Plus;
+
+    use crate::testing::{InOrder, Knapsack, KnapsackState};
+    use crate::{MddLnsBuilder, MinLP, Problem, SimpleMdd, SimpleMddBuilder};
+
+    use super::{replay, ReplayError, ReplayLog, ReplayWriter};
+
+    /// A writer whose output remains readable once the lns is done with it
+    #[derive(Clone, Default)]
+    struct Shared(Arc<Mutex<Vec<u8>>>);
+    impl Write for Shared {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn mdd(problem: &Knapsack) -> SimpleMdd<&Knapsack, InOrder, MinLP<KnapsackState>> {
+        SimpleMddBuilder::default()
+            .problem(problem)
+            .var_ordering(InOrder(problem.nb_vars()))
+            .node_selection(MinLP::new())
+            .kill_switch(Arc::new(AtomicBool::new(false)))
+            .rng(Xoshiro256Plus::seed_from_u64(7))
+            .proba(0.2)
+            .build()
+            .unwrap()
+    }
+
+    /// Runs the lns on the knapsack (for a short while) and reads its log back
+    fn logged_run(problem: &Knapsack) -> ReplayLog<isize> {
+        let out  = Shared::default();
+        let kill = Arc::new(AtomicBool::new(false));
+        let timer = {
+            let kill = Arc::clone(&kill);
+            thread::spawn(move || {
+                thread::sleep(Duration::from_millis(100));
+                kill.store(true, std::sync::atomic::Ordering::Relaxed);
+            })
+        };
+        MddLnsBuilder::default()
+            .mdd(mdd(problem))
+            .nb_var(problem.nb_vars())
+            .width(4)
+            .initial_sol(None)
+            .start(Instant::now())
+            .kill_switch(kill)
+            .replay(Arc::new(ReplayWriter::new(out.clone()).param("width", 4).param("proba", 0.2)))
+            .build()
+            .unwrap()
+            .minimize();
+        timer.join().unwrap();
+        let bytes = out.0.lock().unwrap().clone();
+        ReplayLog::read_from(&bytes[..]).unwrap()
+    }
+
+    #[test]
+    fn the_logged_iterations_are_replayed_faithfully() {
+        let problem = Knapsack::small();
+        let log     = logged_run(&problem);
+        assert!(log.entries.len() > 1);
+        assert_eq!(Some(4), log.param::<usize>("width").unwrap());
+        assert_eq!(Some(0.2), log.param::<f64>("proba").unwrap());
+        assert!(log.entries.iter().any(|e| e.improvement.is_some()));
+
+        // each iteration is replayed in isolation, in any order
+        for entry in log.entries.iter().take(50).rev() {
+            let outcome = replay(&mut mdd(&problem), &log, entry.iteration).unwrap();
+            assert!(outcome.is_faithful(), "iteration {}", entry.iteration);
+        }
+    }
+
+    #[test]
+    fn the_log_rejects_what_it_does_not_hold() {
+        let problem = Knapsack::small();
+        let log     = logged_run(&problem);
+        let missing = log.entries.len() + 1000;
+        assert!(matches!(replay(&mut mdd(&problem), &log, missing), Err(ReplayError::NoSuchIteration(i)) if i == missing));
+        assert!(matches!(log.param::<usize>("seed"), Ok(None)));
+        assert!(matches!(log.param::<String>("width"), Err(ReplayError::Parameter(name, _)) if name == "width"));
+
+        let newer = r#"{"version":2,"parameters":{},"initial_val":null,"initial_sol":null}"#;
+        assert!(matches!(ReplayLog::<isize>::read_from(newer.as_bytes()), Err(ReplayError::UnsupportedVersion(2))));
+        let garbled = r#"{"version":1,"parameters":{},"initial_val":null,"initial_sol":null}
+{"iteration":"zero"}"#;
+        assert!(matches!(ReplayLog::<isize>::read_from(garbled.as_bytes()), Err(ReplayError::Malformed { line: 2, .. })));
+    }
+}
@@ -0,0 +1,134 @@
+//! This module defines the machine readable report of a resolution. It is a
+//! versioned JSON document which carries the outcome of the resolution along
+//! with everything that is needed to make sense of it (the instance, the
+//! solver and its parameters, the seed, the peak memory usage, ...). The
+//! example binaries emit it with `--output json` so that downstream tools do
+//! not have to parse the human readable table.
+
+use std::{collections::BTreeMap, str::FromStr};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::{Cost, ResolutionOutcome};
+
+/// The version of the report format. It must be bumped whenever a change
+/// breaks the existing consumers of the reports (e.g. a field is renamed).
+pub const REPORT_VERSION: u32 = 2;
+
+// ----------------------------------------------------------------------------
+// Errors
+// ----------------------------------------------------------------------------
+#[derive(Debug, thiserror::Error)]
+pub enum ReportError {
+    #[error("malformed report {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("unsupported report version {0} (expected at most {REPORT_VERSION})")]
+    UnsupportedVersion(u32),
+}
+
+// ----------------------------------------------------------------------------
+// Report
+// ----------------------------------------------------------------------------
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(bound = "C: Cost")]
+pub struct SolverReport<C> {
+    /// The version of the format this report complies with
+    pub version: u32,
+    /// The name of the solved instance
+    pub instance: String,
+    /// The method used to solve the instance (lns, astar, dp, ...)
+    pub method: String,
+    /// The status, best value, best solution and timings of the resolution
+    #[serde(flatten)]
+    pub outcome: ResolutionOutcome<C>,
+    /// The best known lower bound on the optimal value (if any)
+    pub best_bound: Option<C>,
+    /// The peak memory usage of the process (in gigabytes)
+    pub peak_ram_gb: f64,
+    /// The seed of the random number generator (when the method uses one)
+    pub seed: Option<u64>,
+    /// The parameters of the solver, by name
+    pub parameters: BTreeMap<String, Value>,
+}
+
+impl<C: Cost> SolverReport<C> {
+    pub fn new(instance: &str, method: &str, outcome: ResolutionOutcome<C>) -> Self {
+        Self {
+            version: REPORT_VERSION,
+            instance: instance.to_string(),
+            method: method.to_string(),
+            best_bound: outcome.best_bound(),
+            outcome,
+            peak_ram_gb: 0.0,
+            seed: None,
+            parameters: BTreeMap::new(),
+        }
+    }
+    /// Records the value of some parameter of the solver
+    pub fn param<T: Serialize>(&mut self, name: &str, value: T) -> &mut Self {
+        // serializing a plain value into a json value cannot fail
+        let value = serde_json::to_value(value).unwrap_or(Value::Null);
+        self.parameters.insert(name.to_string(), value);
+        self
+    }
+    /// Encodes the report as a single line of json
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("a report is always serializable")
+    }
+    /// Decodes a report and makes sure its format is supported
+    pub fn from_json(json: &str) -> Result<Self, ReportError> {
+        let report = serde_json::from_str::<Self>(json)?;
+        if report.version > REPORT_VERSION {
+            Err(ReportError::UnsupportedVersion(report.version))
+        } else {
+            Ok(report)
+        }
+    }
+}
+
+// ----------------------------------------------------------------------------
+// Output format
+// ----------------------------------------------------------------------------
+/// How the outcome of a resolution is printed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OutputFormat {
+    /// The human readable, pipe separated table
+    #[default]
+    Table,
+    /// One json report per line
+    Json,
+}
+impl FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "table" => Ok(Self::Table),
+            "json"  => Ok(Self::Json),
+            _       => Err(format!("unknown output format {} (expected table or json)", s)),
+        }
+    }
+}
+
+// ----------------------------------------------------------------------------
+// Durations as seconds
+// ----------------------------------------------------------------------------
+/// (De)serializes an optional duration as a (fractional) number of seconds
+pub(crate) mod seconds {
+    use std::time::Duration;
+
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(d: &Option<Duration>, s: S) -> Result<S::Ok, S::Error> {
+        match d {
+            Some(d) => s.serialize_some(&d.as_secs_f64()),
+            None    => s.serialize_none(),
+        }
+    }
+    pub fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<Option<Duration>, D::Error> {
+        let secs = Option::<f64>::deserialize(d)?;
+        secs.map(|s| Duration::try_from_secs_f64(s).map_err(serde::de::Error::custom))
+            .transpose()
+    }
+}
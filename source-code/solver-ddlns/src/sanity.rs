@@ -0,0 +1,315 @@
+//! This module provides a sanity-check harness for the `Problem` implementations.
+//! A bug in `transition`, `transition_cost` or `estimate` does not make the
+//! solvers crash: it silently corrupts their results. The harness below
+//! explores the complete state space of a (small) instance and it checks that:
+//!
+//! * `transition` and `transition_cost` are deterministic, and equal states
+//!   have equal hashes;
+//! * `evaluate` agrees with the values of the paths in the state space;
+//! * `SimpleMdd::exact` and `PureDp` both find the true optimum;
+//! * `estimate` never exceeds the true cost-to-go of a state.
+
+use std::{
+    hash::{Hash, Hasher},
+    mem::discriminant,
+    rc::Rc,
+    sync::{atomic::AtomicBool, Arc},
+    time::Instant,
+};
+
+use derive_builder::Builder;
+use rand::{seq::SliceRandom, SeedableRng};
+use rand_xoshiro::Xoshiro256Plus;
+use rustc_hash::{FxHashMap, FxHasher};
+
+use crate::{
+    Cost, Decision, Mdd, MinLP, Problem, PureDpBuilder, SimpleMddBuilder, Solution, VariableOrdering,
+};
+
+/// A problem detected by the sanity checks
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum SanityViolation<C> {
+    #[error("the instance has more than {0} states")]
+    InstanceTooLarge(usize),
+    #[error("transition is not deterministic (x{} <-- {} at depth {depth})", decision.var.id(), decision.val)]
+    NonDeterministicTransition { depth: usize, decision: Decision },
+    #[error("equal states have different hashes (x{} <-- {} at depth {depth})", decision.var.id(), decision.val)]
+    InconsistentHash { depth: usize, decision: Decision },
+    #[error("transition cost is not deterministic (x{} <-- {} at depth {depth})", decision.var.id(), decision.val)]
+    NonDeterministicCost { depth: usize, decision: Decision },
+    #[error("solution {solution}evaluates to {evaluated} instead of {expected}")]
+    EvaluationMismatch { solution: Solution, evaluated: C, expected: C },
+    #[error("{solver} finds {found:?} instead of the optimum {expected:?}")]
+    WrongOptimum { solver: &'static str, found: Option<C>, expected: Option<C> },
+    #[error("estimate {estimate} exceeds the cost-to-go {cost_to_go} (depth {depth})")]
+    OverEstimate { depth: usize, estimate: C, cost_to_go: C },
+}
+
+/// The outcome of the sanity checks
+#[derive(Debug, Clone)]
+pub struct SanityReport<C> {
+    /// The number of distinct states (per layer) in the state space
+    pub nb_states: usize,
+    /// The number of transitions in the state space
+    pub nb_transitions: usize,
+    /// The true optimum of the instance (None if it is infeasible)
+    pub optimum: Option<C>,
+    /// The problems detected in the model. Only the first occurrence of each
+    /// kind of violation is reported.
+    pub violations: Vec<SanityViolation<C>>,
+}
+impl<C> SanityReport<C> {
+    /// Returns true iff no violation was detected
+    pub fn is_ok(&self) -> bool {
+        self.violations.is_empty()
+    }
+}
+
+// ----------------------------------------------------------------------------
+// Sanity check harness
+// ----------------------------------------------------------------------------
+#[derive(Debug, Builder)]
+pub struct SanityCheck<P, V>
+where
+    P: Problem,
+    V: VariableOrdering<State = P::State> + Clone,
+    P::State: PartialEq + Eq + Hash,
+{
+    /// The problem that must be checked (on a small instance)
+    problem: P,
+    /// The variable ordering that will be imposed on the problem
+    var_ordering: V,
+    /// The maximum number of states explored before giving up
+    #[builder(default = "1_000_000")]
+    max_states: usize,
+    /// The number of random solutions whose evaluation is checked
+    #[builder(default = "64")]
+    nb_samples: usize,
+    #[builder(default = "0")]
+    seed: u64,
+}
+
+/// The complete state space of an instance. The states are numbered layer
+/// after layer, hence the transitions always go towards larger identifiers.
+struct StateSpace<S, C> {
+    states: Vec<Rc<S>>,
+    depths: Vec<usize>,
+    /// The outgoing transitions of each state: decision, cost, target
+    edges: Vec<Vec<(Decision, C, usize)>>,
+    /// The states of the last layer (where no variable remains to be assigned)
+    terminal: Vec<bool>,
+}
+
+impl<P, V> SanityCheck<P, V>
+where
+    P: Problem,
+    V: VariableOrdering<State = P::State> + Clone,
+    P::State: PartialEq + Eq + Hash,
+{
+    pub fn run(&self) -> SanityReport<P::Cost> {
+        let mut violations = vec![];
+        let Some(space) = self.explore(&mut violations) else {
+            return SanityReport { nb_states: 0, nb_transitions: 0, optimum: None, violations };
+        };
+
+        let cost_to_go = Self::cost_to_go(&space);
+        let optimum    = Some(cost_to_go[0])
+            .filter(|c| *c != P::Cost::MAX)
+            .map(|c| self.problem.initial_value().saturating_add(c));
+
+        self.check_estimates(&space, &cost_to_go, &mut violations);
+        self.check_samples(&space, &cost_to_go, &mut violations);
+        self.check_solvers(optimum, &mut violations);
+
+        SanityReport {
+            nb_states: space.states.len(),
+            nb_transitions: space.edges.iter().map(|e| e.len()).sum(),
+            optimum,
+            violations,
+        }
+    }
+
+    /// Explores the complete state space layer by layer, and checks the
+    /// determinism of the transitions along the way
+    fn explore(&self, violations: &mut Vec<SanityViolation<P::Cost>>) -> Option<StateSpace<P::State, P::Cost>> {
+        let mut space = StateSpace {
+            states: vec![Rc::new(self.problem.initial_state())],
+            depths: vec![0],
+            edges: vec![],
+            terminal: vec![],
+        };
+        let mut start = 0;
+        let mut depth = 0;
+        loop {
+            let layer = start..space.states.len();
+            let var   = if layer.is_empty() {
+                None
+            } else {
+                self.var_ordering.next(&mut space.states[layer.clone()].iter().map(|s| s.as_ref()))
+            };
+            let Some(var) = var else {
+                space.terminal.resize(space.states.len(), true);
+                space.edges.resize(space.states.len(), vec![]);
+                return Some(space);
+            };
+            space.terminal.resize(space.states.len(), false);
+
+            let mut next = FxHashMap::<Rc<P::State>, usize>::default();
+            for id in layer.clone() {
+                let state = Rc::clone(&space.states[id]);
+                let mut out = vec![];
+                self.problem.for_each_in_domain(state.as_ref(), var, |decision| {
+                    let child = self.check_transition(state.as_ref(), decision, depth, violations);
+                    let cost  = self.problem.transition_cost(state.as_ref(), decision);
+                    if cost != self.problem.transition_cost(state.as_ref(), decision) {
+                        Self::report(violations, SanityViolation::NonDeterministicCost { depth, decision });
+                    }
+                    let fresh = layer.end + next.len();
+                    let to    = *next.entry(Rc::new(child)).or_insert(fresh);
+                    out.push((decision, cost, to));
+                });
+                space.edges.push(out);
+            }
+
+            if layer.end + next.len() > self.max_states {
+                Self::report(violations, SanityViolation::InstanceTooLarge(self.max_states));
+                return None;
+            }
+            let mut children = next.into_iter().map(|(s, id)| (id, s)).collect::<Vec<_>>();
+            children.sort_unstable_by_key(|(id, _)| *id);
+            space.states.extend(children.into_iter().map(|(_, s)| s));
+            space.depths.resize(space.states.len(), depth + 1);
+
+            start = layer.end;
+            depth += 1;
+        }
+    }
+
+    /// Performs the transition twice and checks both results are the same
+    fn check_transition(&self, state: &P::State, decision: Decision, depth: usize, violations: &mut Vec<SanityViolation<P::Cost>>) -> P::State {
+        let a = self.problem.transition(state, decision);
+        let b = self.problem.transition(state, decision);
+        if a != b {
+            Self::report(violations, SanityViolation::NonDeterministicTransition { depth, decision });
+        } else if Self::hash(&a) != Self::hash(&b) {
+            Self::report(violations, SanityViolation::InconsistentHash { depth, decision });
+        }
+        a
+    }
+
+    /// Computes the exact cost-to-go of all states (`Cost::MAX` for the states
+    /// which cannot reach the last layer)
+    fn cost_to_go(space: &StateSpace<P::State, P::Cost>) -> Vec<P::Cost> {
+        let mut cost_to_go = vec![P::Cost::MAX; space.states.len()];
+        for id in (0..space.states.len()).rev() {
+            cost_to_go[id] = if space.terminal[id] {
+                P::Cost::ZERO
+            } else {
+                space.edges[id].iter()
+                    .filter(|(_, _, to)| cost_to_go[*to] != P::Cost::MAX)
+                    .map(|(_, cost, to)| cost.saturating_add(cost_to_go[*to]))
+                    .min()
+                    .unwrap_or(P::Cost::MAX)
+            };
+        }
+        cost_to_go
+    }
+
+    /// Checks that the estimate of a state never exceeds its cost-to-go
+    fn check_estimates(&self, space: &StateSpace<P::State, P::Cost>, cost_to_go: &[P::Cost], violations: &mut Vec<SanityViolation<P::Cost>>) {
+        for (id, state) in space.states.iter().enumerate() {
+            let cost_to_go = cost_to_go[id];
+            if cost_to_go == P::Cost::MAX {
+                continue;
+            }
+            let estimate = self.problem.estimate(state.as_ref());
+            if estimate > cost_to_go {
+                let depth = space.depths[id];
+                Self::report(violations, SanityViolation::OverEstimate { depth, estimate, cost_to_go });
+            }
+        }
+    }
+
+    /// Checks that `evaluate` agrees with the value of random feasible paths
+    fn check_samples(&self, space: &StateSpace<P::State, P::Cost>, cost_to_go: &[P::Cost], violations: &mut Vec<SanityViolation<P::Cost>>) {
+        if cost_to_go[0] == P::Cost::MAX {
+            return;
+        }
+        let mut rng = Xoshiro256Plus::seed_from_u64(self.seed);
+        for _ in 0..self.nb_samples {
+            let mut id        = 0;
+            let mut value     = self.problem.initial_value();
+            let mut decisions = vec![];
+            while !space.terminal[id] {
+                let feasible = space.edges[id].iter()
+                    .filter(|(_, _, to)| cost_to_go[*to] != P::Cost::MAX)
+                    .collect::<Vec<_>>();
+                let (decision, cost, to) = **feasible.choose(&mut rng).expect("feasible edge");
+                decisions.push(decision);
+                value = value.saturating_add(cost);
+                id    = to;
+            }
+            self.check_evaluation(Solution::from(decisions.into_iter()), value, violations);
+        }
+    }
+
+    /// Checks that `SimpleMdd::exact` and `PureDp` find the true optimum, and
+    /// that the solutions they return evaluate to that optimum
+    fn check_solvers(&self, optimum: Option<P::Cost>, violations: &mut Vec<SanityViolation<P::Cost>>) {
+        let kill_switch = Arc::new(AtomicBool::new(false));
+
+        let mdd = SimpleMddBuilder::default()
+            .problem(&self.problem)
+            .var_ordering(self.var_ordering.clone())
+            .node_selection(MinLP::new())
+            .proba(0.0)
+            .kill_switch(Arc::clone(&kill_switch))
+            .build();
+        if let Ok(mut mdd) = mdd {
+            let found = mdd.exact();
+            if found != optimum {
+                Self::report(violations, SanityViolation::WrongOptimum { solver: "SimpleMdd::exact", found, expected: optimum });
+            }
+            if let (Some(value), Some(sol)) = (found, mdd.get_best_solution()) {
+                self.check_evaluation(sol, value, violations);
+            }
+        }
+
+        let dp = PureDpBuilder::default()
+            .problem(&self.problem)
+            .var_ordering(self.var_ordering.clone())
+            .start_time(Instant::now())
+            .kill_switch(kill_switch)
+            .build();
+        if let Ok(dp) = dp {
+            let outcome = dp.minimize();
+            if outcome.best_value != optimum {
+                Self::report(violations, SanityViolation::WrongOptimum { solver: "PureDp", found: outcome.best_value, expected: optimum });
+            }
+            if let (Some(value), Some(sol)) = (outcome.best_value, outcome.best_sol) {
+                self.check_evaluation(sol, value, violations);
+            }
+        }
+    }
+
+    /// Checks that the given solution evaluates to the expected value
+    fn check_evaluation(&self, solution: Solution, expected: P::Cost, violations: &mut Vec<SanityViolation<P::Cost>>) {
+        let evaluated = self.problem.evaluate(&self.var_ordering, &solution);
+        if evaluated != expected {
+            Self::report(violations, SanityViolation::EvaluationMismatch { solution, evaluated, expected });
+        }
+    }
+
+    /// Records a violation unless a violation of the same kind was recorded
+    fn report(violations: &mut Vec<SanityViolation<P::Cost>>, violation: SanityViolation<P::Cost>) {
+        if !violations.iter().any(|v| discriminant(v) == discriminant(&violation)) {
+            violations.push(violation);
+        }
+    }
+
+    fn hash(state: &P::State) -> u64 {
+        let mut hasher = FxHasher::default();
+        state.hash(&mut hasher);
+        hasher.finish()
+    }
+}
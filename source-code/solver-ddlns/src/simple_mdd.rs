@@ -1,8 +1,9 @@
 //! Ici je vais implémenter une stucture de MDD
 
 use crate::{
-    Decision, NodeSelectionHeuristic, NodeSource, Problem, SelectableNode, Solution,
-    VariableOrdering, Mdd, Var,
+    Cost, Decision, NodeSelectionHeuristic, NodeSource, Problem, SelectableNode, Solution,
+    VariableOrdering, Mdd, Var, StateArena, StateId, ArenaEntry, StateCodec, StateDistance, RolloutPolicy, Propagator,
+    ReducedMdd, MemoryMonitor, StateSize, ProofRecorder, ProofStep,
 };
 use derive_builder::Builder;
 use rand::{Rng, SeedableRng};
@@ -11,6 +12,8 @@ use rustc_hash::FxHashMap;
 use std::{
     collections::hash_map::Entry,
     hash::Hash,
+    io::Write,
+    mem::size_of,
     sync::{
         atomic::{AtomicBool, Ordering},
         Arc,
@@ -21,36 +24,40 @@ use std::{
 struct NodeId(usize);
 
 #[derive(Debug, Clone, Copy, Eq, PartialEq, Hash, Ord, PartialOrd)]
-struct Edge {
+struct Edge<C> {
     from: NodeId,
     to: NodeId,
     label: Decision,
-    weight: isize,
+    weight: C,
 }
 
 #[derive(Debug, Clone, Copy, Eq, PartialEq, Hash, Ord, PartialOrd)]
-struct Node {
+struct Node<C> {
     my_id: NodeId,
-    value: isize,
-    best_parent: Option<Edge>,
+    value: C,
+    best_parent: Option<Edge<C>>,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
-struct MiniNode<S> {
+struct MiniNode<S, C> {
     node_id: NodeId,
     state: S,
-    value: isize,
-    estimate: isize,
+    value: C,
+    estimate: C,
+    /// The cost of the completion found by a rollout (only computed when the
+    /// layer needs to be restricted in rollout mode)
+    rollout: C,
 }
 
 /// used to pass info related to the initial state and value
 struct Initial<P: Problem> {
     state: P::State,
-    value: isize,
+    value: P::Cost,
 }
-/// Pass information related to the incumbent best solution
-struct Incumbent<'a> {
-    best_val: isize,
+/// Pass information related to the incumbent best solution. When a rollout
+/// finds a better solution, `best_val` is tightened accordingly.
+struct Incumbent<'a, C> {
+    best_val: C,
     best_sol: &'a Option<Solution>,
 }
 /// Pass configuration information
@@ -58,7 +65,7 @@ struct Config<'a, P, V, N>
 where
     P: Problem,
     V: VariableOrdering,
-    N: NodeSelectionHeuristic,
+    N: NodeSelectionHeuristic<State = P::State>,
 {
     problem: &'a P,
     var_ord: &'a V,
@@ -71,6 +78,83 @@ where
     kill_switch: &'a AtomicBool,
     //
     start_depth: usize,
+    //
+    codec: Option<&'a dyn StateCodec<State = P::State>>,
+    //
+    diversity: Option<&'a dyn StateDistance<State = P::State>>,
+    nb_clusters: usize,
+    //
+    rollout: Option<&'a dyn RolloutPolicy<State = P::State>>,
+    rollout_incumbents: bool,
+    //
+    propagator: Option<&'a Propagator<P::State>>,
+    //
+    memory: Option<&'a dyn MemoryMonitor>,
+    //
+    budget: Option<&'a Budget<P::State>>,
+    //
+    proof: Option<&'a ProofRecorder>,
+}
+
+impl<P, V, N> Config<'_, P, V, N>
+where
+    P: Problem,
+    V: VariableOrdering,
+    N: NodeSelectionHeuristic<State = P::State>,
+{
+    fn is_memory_scarce(&self) -> bool {
+        self.memory.is_some_and(|m| m.is_over_soft_limit())
+    }
+    fn record(&self, step: ProofStep) {
+        if let Some(proof) = self.proof {
+            proof.record(step);
+        }
+    }
+}
+
+/// A memory budget which determines the width of the restricted layers. The
+/// width of a layer is the number of nodes (of the average size of the nodes
+/// in that layer) that fit in the budget. The `max_width` of the restriction
+/// remains an upper bound on the number of nodes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ByteBudget {
+    /// The nodes of each layer may use at most that many bytes
+    PerLayer(usize),
+    /// The nodes of the whole diagram may use at most that many bytes. What is
+    /// left of the budget is evenly shared among the layers to come.
+    PerDiagram(usize),
+}
+impl ByteBudget {
+    pub fn per_layer_mb(megabytes: f64) -> Self {
+        Self::PerLayer((megabytes * 1024.0 * 1024.0).ceil() as usize)
+    }
+    pub fn per_diagram_mb(megabytes: f64) -> Self {
+        Self::PerDiagram((megabytes * 1024.0 * 1024.0).ceil() as usize)
+    }
+}
+
+/// Some statistics about the latest compilation of an mdd
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CompilationStats {
+    /// The number of nodes of the diagram
+    pub nb_nodes: usize,
+    /// The width of each developed layer (once restricted)
+    pub widths: Vec<usize>,
+    /// The number of nodes dropped by the restrictions
+    pub dropped: usize,
+    /// Whether the diagram was exact (no node was dropped or left unexplored)
+    pub is_exact: bool,
+}
+
+/// A byte budget along with the means to measure the states
+struct Budget<S> {
+    bytes: ByteBudget,
+    size: fn(&S) -> usize,
+}
+impl<S> Clone for Budget<S> {
+    fn clone(&self) -> Self {
+        Self { bytes: self.bytes, size: self.size }
+    }
 }
 
 #[derive(Builder)]
@@ -79,7 +163,7 @@ where
     P: Problem,
     P::State: PartialEq + Eq + Hash,
     V: VariableOrdering<State = P::State>,
-    N: NodeSelectionHeuristic,
+    N: NodeSelectionHeuristic<State = P::State>,
 {
     problem: P,
     var_ordering: V,
@@ -91,13 +175,66 @@ where
     #[builder(default="Xoshiro256Plus::seed_from_u64(0)")]
     rng: Xoshiro256Plus,
     proba: f64,
+    /// An optional codec used to store the states of the next layer in a
+    /// compact form. This reduces the memory footprint of very wide diagrams.
+    #[builder(default, setter(strip_option))]
+    codec: Option<Arc<dyn StateCodec<State = P::State>>>,
+    /// An optional distance between states. When it is set, the restriction
+    /// clusters the layer and keeps the best nodes from each cluster rather
+    /// than the best nodes overall.
+    #[builder(default, setter(strip_option))]
+    diversity: Option<Arc<dyn StateDistance<State = P::State>>>,
+    /// The number of clusters formed during a diversity aware restriction
+    #[builder(default = "8")]
+    nb_clusters: usize,
+    /// An optional rollout policy. When it is set, the candidate nodes of a
+    /// layer that must be restricted are completed with that policy, and they
+    /// are ranked by the cost of their completion.
+    #[builder(default, setter(strip_option))]
+    rollout: Option<Arc<dyn RolloutPolicy<State = P::State>>>,
+    /// Should the rollout completions become the best solution of the mdd
+    /// when they improve the incumbent ?
+    #[builder(default = "true")]
+    rollout_incumbents: bool,
+    /// An optional set of constraints used to filter the domains and to
+    /// discard the dead-end states before they are inserted in the diagram
+    #[builder(default, setter(strip_option))]
+    propagator: Option<Arc<Propagator<P::State>>>,
+    /// An optional memory monitor. When the memory exceeds its soft limit,
+    /// the layers are narrowed (down to a single node if need be) rather than
+    /// letting the compilation exhaust the memory.
+    #[builder(default, setter(strip_option))]
+    memory: Option<Arc<dyn MemoryMonitor>>,
+    /// An optional memory budget. When it is set, the width of the restricted
+    /// layers is chosen according to the size of their states.
+    #[builder(default, setter(custom))]
+    budget: Option<Budget<P::State>>,
+    /// An optional recorder of the compilation steps. When the mdd is exact,
+    /// the steps of its latest compilation certify its optimum (see
+    /// `check_proof`).
+    #[builder(default, setter(strip_option))]
+    proof: Option<Arc<ProofRecorder>>,
+}
+impl<P, V, N> SimpleMddBuilder<P, V, N>
+where
+    P: Problem,
+    P::State: PartialEq + Eq + Hash + StateSize,
+    V: VariableOrdering<State = P::State>,
+    N: NodeSelectionHeuristic<State = P::State>,
+{
+    /// Restricts the layers according to the given memory budget (the size
+    /// of the states is measured with their `StateSize` implementation)
+    pub fn budget(&mut self, bytes: ByteBudget) -> &mut Self {
+        self.budget = Some(Some(Budget { bytes, size: P::State::state_size }));
+        self
+    }
 }
 impl <P, V, N> SimpleMdd<P, V, N> 
 where
     P: Problem,
     P::State: PartialEq + Eq + Hash,
     V: VariableOrdering<State = P::State>,
-    N: NodeSelectionHeuristic,
+    N: NodeSelectionHeuristic<State = P::State>,
 {
     pub fn get_proba(&self) -> f64 {
         self.proba
@@ -105,17 +242,48 @@ where
     pub fn set_proba(&mut self, proba: f64) {
         self.proba = proba;
     }
+    /// Compiles the reduced exact diagram of the problem (using the same
+    /// variable ordering and propagator as this mdd). Unlike `exact`, this
+    /// keeps a queryable structure once the compilation is over.
+    pub fn reduced(&self) -> ReducedMdd<P::Cost> {
+        ReducedMdd::compile(&self.problem, &self.var_ordering, self.propagator.as_deref())
+    }
+    /// Returns some statistics about the latest compilation
+    pub fn stats(&self) -> CompilationStats {
+        CompilationStats {
+            nb_nodes: self.diagram.nodes.len(),
+            widths: self.diagram.widths.clone(),
+            dropped: self.diagram.dropped,
+            is_exact: self.diagram.is_exact,
+        }
+    }
+    /// Dumps the latest compilation in the graphviz format. Each node shows
+    /// its id and value; only the best edge reaching each node is kept, and
+    /// the best terminal node is highlighted.
+    pub fn write_dot<W: Write>(&self, mut out: W) -> std::io::Result<()> {
+        writeln!(out, "digraph mdd {{")?;
+        for node in self.diagram.nodes.iter() {
+            let shape = if Some(node.my_id) == self.diagram.best_terminal_node { "doublecircle" } else { "circle" };
+            writeln!(out, "  {} [shape = {}, label = \"{}\\n{}\"];", node.my_id.0, shape, node.my_id.0, node.value)?;
+            if let Some(edge) = node.best_parent {
+                writeln!(out, "  {} -> {} [label = \"x{} = {}\\n{}\"];",
+                    edge.from.0, edge.to.0, edge.label.var.id(), edge.label.val, edge.weight)?;
+            }
+        }
+        writeln!(out, "}}")
+    }
 }
 impl<P, V, N> Mdd for SimpleMdd<P, V, N>
 where
     P: Problem,
     P::State: PartialEq + Eq + Hash,
     V: VariableOrdering<State = P::State>,
-    N: NodeSelectionHeuristic,
+    N: NodeSelectionHeuristic<State = P::State>,
 {
     type State = P::State;
+    type Cost = P::Cost;
 
-    fn get_best_value(&self) -> Option<isize> {
+    fn get_best_value(&self) -> Option<P::Cost> {
         self.diagram.get_best_value()
     }
     fn get_best_solution(&self) -> Option<Solution> {
@@ -125,7 +293,7 @@ where
         self.diagram.is_exact
     }
 
-    fn exact(&mut self) -> Option<isize> {
+    fn exact(&mut self) -> Option<P::Cost> {
         let config = Config {
             problem: &self.problem,
             var_ord: &self.var_ordering,
@@ -137,6 +305,22 @@ where
             max_width: usize::MAX,
             kill_switch: self.kill_switch.as_ref(),
             start_depth: 0,
+            //
+            codec: self.codec.as_deref(),
+            //
+            diversity: self.diversity.as_deref(),
+            nb_clusters: self.nb_clusters,
+            //
+            rollout: self.rollout.as_deref(),
+            rollout_incumbents: self.rollout_incumbents,
+            //
+            propagator: self.propagator.as_deref(),
+            //
+            memory: self.memory.as_deref(),
+            //
+            budget: self.budget.as_ref(),
+            //
+            proof: self.proof.as_deref(),
         };
 
         let initial = Initial {
@@ -145,7 +329,7 @@ where
         };
         //
         let incumbent = Incumbent {
-            best_val: isize::MAX,
+            best_val: P::Cost::MAX,
             best_sol: &None,
         };
         //
@@ -156,11 +340,11 @@ where
     fn restricted(
         &mut self,
         max_width: usize,
-        best_val: isize,
+        best_val: P::Cost,
         best_sol: &Option<Solution>,
         //
         start_depth: usize
-    ) -> Option<isize> {
+    ) -> Option<P::Cost> {
         let config = Config {
             problem: &self.problem,
             var_ord: &self.var_ordering,
@@ -171,7 +355,23 @@ where
             //
             max_width,
             kill_switch: self.kill_switch.as_ref(),
-            start_depth
+            start_depth,
+            //
+            codec: self.codec.as_deref(),
+            //
+            diversity: self.diversity.as_deref(),
+            nb_clusters: self.nb_clusters,
+            //
+            rollout: self.rollout.as_deref(),
+            rollout_incumbents: self.rollout_incumbents,
+            //
+            propagator: self.propagator.as_deref(),
+            //
+            memory: self.memory.as_deref(),
+            //
+            budget: self.budget.as_ref(),
+            //
+            proof: self.proof.as_deref(),
         };
 
         let initial = Initial {
@@ -184,6 +384,13 @@ where
         self.diagram.compile(config, initial, incumbent);
         self.diagram.get_best_value()
     }
+
+    fn rng_state(&self) -> Option<Xoshiro256Plus> {
+        Some(self.rng.clone())
+    }
+    fn set_rng_state(&mut self, rng: Xoshiro256Plus) {
+        self.rng = rng;
+    }
 }
 
 /// This structure represents the diagram, and the diagram only. It has
@@ -200,10 +407,29 @@ where
     P: Problem,
     P::State: PartialEq + Eq + Hash,
 {
-    nodes: Vec<Node>,
-    next_layer_states: FxHashMap<P::State, NodeId>,
+    nodes: Vec<Node<P::Cost>>,
+    /// The nodes of the next layer along with the (cached) estimate of their state
+    next_layer_states: FxHashMap<P::State, (NodeId, P::Cost)>,
     best_terminal_node: Option<NodeId>,
     is_exact: bool,
+    /// The best solution found by a rollout (if it improved the incumbent)
+    best_rollout: Option<(P::Cost, Solution)>,
+    /// When a codec is used, the states of the next layer are interned in this
+    /// arena rather than in `next_layer_states`
+    arena: StateArena,
+    /// The node associated with each state of the arena (and its estimate)
+    arena_nodes: Vec<(NodeId, P::Cost)>,
+    /// A scratch buffer used to encode the states
+    buffer: Vec<u8>,
+    /// The number of decisions leading to the current layer
+    depth: usize,
+    /// The memory used by the nodes of the layers compiled so far (only
+    /// tracked when the diagram is compiled under a byte budget)
+    spent: usize,
+    /// The width of each developed layer (once restricted)
+    widths: Vec<usize>,
+    /// The number of nodes dropped by the restrictions
+    dropped: usize,
 }
 
 impl<P> Default for Diagram<P>
@@ -217,6 +443,14 @@ where
             next_layer_states: FxHashMap::default(),
             best_terminal_node: None,
             is_exact: true,
+            best_rollout: None,
+            arena: StateArena::default(),
+            arena_nodes: vec![],
+            buffer: vec![],
+            depth: 0,
+            spent: 0,
+            widths: vec![],
+            dropped: 0,
         }
     }
 }
@@ -231,13 +465,36 @@ where
         self.next_layer_states.clear();
         self.best_terminal_node = None;
         self.is_exact = true;
+        self.best_rollout = None;
+        self.arena.clear();
+        self.arena_nodes.clear();
+        self.depth = 0;
+        self.spent = 0;
+        self.widths.clear();
+        self.dropped = 0;
+    }
+
+    /// Gives the spare capacity of the diagram back to the allocator
+    fn release(&mut self) {
+        self.nodes.shrink_to_fit();
+        self.next_layer_states.shrink_to_fit();
+        self.arena = StateArena::default();
+        self.arena_nodes.shrink_to_fit();
+        self.buffer.shrink_to_fit();
     }
 
-    fn get_best_value(&self) -> Option<isize> {
-        self.best_terminal_node.map(|n| self.nodes[n.0].value)
+    fn get_best_value(&self) -> Option<P::Cost> {
+        if let Some((value, _)) = self.best_rollout.as_ref().filter(|_| self.rollout_is_best()) {
+            Some(*value)
+        } else {
+            self.best_terminal_node.map(|n| self.nodes[n.0].value)
+        }
     }
 
     fn get_best_solution(&self) -> Option<Solution> {
+        if let Some((_, sol)) = self.best_rollout.as_ref().filter(|_| self.rollout_is_best()) {
+            return Some(sol.clone());
+        }
         self.best_terminal_node.map(|best_id| {
             let mut decisions = vec![];
             let mut curr = self.nodes[best_id.0].best_parent;
@@ -245,10 +502,19 @@ where
                 decisions.push(edge.label);
                 curr = self.nodes[edge.from.0].best_parent;
             }
-            Solution::from(decisions.iter().copied())
+            Solution::from(decisions.iter().rev().copied())
         })
     }
 
+    /// Tells whether the best rollout is better than the best terminal node
+    fn rollout_is_best(&self) -> bool {
+        match (&self.best_rollout, self.best_terminal_node) {
+            (None, _) => false,
+            (Some(_), None) => true,
+            (Some((value, _)), Some(id)) => *value < self.nodes[id.0].value,
+        }
+    }
+
     fn compile<'a, V, N>(
         &mut self,
         // meta stuffs
@@ -256,12 +522,18 @@ where
         // initial
         initial: Initial<P>,
         // incumbent
-        incumbent: Incumbent,
+        mut incumbent: Incumbent<P::Cost>,
     ) where
         V: VariableOrdering<State = P::State>,
-        N: NodeSelectionHeuristic,
+        N: NodeSelectionHeuristic<State = P::State>,
     {
         self.clear();
+        if let Some(proof) = config.proof {
+            proof.start(config.propagator.is_some());
+        }
+        if config.is_memory_scarce() {
+            self.release();
+        }
 
         self.nodes.push(Node {
             my_id: NodeId(0),
@@ -273,6 +545,7 @@ where
             node_id: NodeId(0),
             value: initial.value,
             estimate: config.problem.estimate(&initial.state),
+            rollout: P::Cost::MAX,
             state: initial.state,
         }];
 
@@ -293,6 +566,7 @@ where
             let mut mininodes_states = mininodes.iter().map(|n| &n.state);
     
             if let Some(var) = config.var_ord.next(&mut mininodes_states) {
+                config.record(ProofStep::Layer { var });
                 // develop this layer
                 for mininode in mininodes.drain(..) {
                     // kill switch short cut
@@ -310,29 +584,27 @@ where
                             .problem
                             .for_each_in_domain(&mininode.state, var, |decision| {
                                 self.branch_on(
-                                    config.kill_switch,
+                                    &config,
                                     true,
-                                    config.problem,
+                                    incumbent.best_val,
                                     &mininode,
                                     decision,
                                 );
                             });
+                    } else {
+                        config.record(ProofStep::Skip { id: mininode.node_id.0 });
                     }
                 }
                 // The next layer has been fully expanded. Let us now drain the hash
                 // map and restrict that next layer if needed (to that end, we first
-                // need to populate the mininodes vector)
-                for e in self.next_layer_states.drain() {
-                    mininodes.push(MiniNode {
-                        node_id: e.1,
-                        estimate: config.problem.estimate(&e.0),
-                        value: self.nodes[e.1 .0].value,
-                        state: e.0,
-                    });
+                // need to populate the mininodes vector). When the states are
+                // encoded, only those that survive the restriction are decoded.
+                if let Some(codec) = config.codec {
+                    self.restrict_encoded(var, codec, &mut config, &mut incumbent, &mut mininodes);
+                } else {
+                    self.drain_next_layer(None, &mut mininodes);
+                    self.restrict(var, &mut config, &mut incumbent, &mut mininodes);
                 }
-
-                // perform the restriction
-                self.restrict(var, &mut config, &incumbent, &mut mininodes);
             } else {
                 break;
             }
@@ -354,13 +626,19 @@ where
     fn restrict<V, N>(&mut self, 
         var: Var,
         config: &mut Config<P, V, N>, 
-        incumbent: &Incumbent,
-        mininodes: &mut Vec<MiniNode<<P as Problem>::State>>) 
+        incumbent: &mut Incumbent<P::Cost>,
+        mininodes: &mut Vec<MiniNode<P::State, P::Cost>>) 
     where
         V: VariableOrdering<State = P::State>,
-        N: NodeSelectionHeuristic,
+        N: NodeSelectionHeuristic<State = P::State>,
     {
-        if mininodes.len() > config.max_width {
+        let layer_width = mininodes.len();
+        let node_size = config.budget.map(|budget| {
+            let states = mininodes.iter().map(|n| (budget.size)(&n.state)).sum::<usize>();
+            Self::node_size(states, layer_width)
+        });
+        let max_width = self.layer_max_width(config, layer_width, node_size);
+        if mininodes.len() > max_width {
             // we are going to truncate the next layer. it is no longer an exact dd
             self.is_exact = false;
             // first, make sure to move all the mandatory nodes at the beginning
@@ -381,124 +659,515 @@ where
             // then sort the rest of the vector and keep only the max_width most
             // relevant ones
             let (_keep, sort) = mininodes.split_at_mut(frontier);
-            sort.sort_unstable_by(|a, b| config.node_sel.compare(self, a, b));
-            let limit = config.max_width.max(frontier);
+            if let Some(policy) = config.rollout {
+                for node in sort.iter_mut() {
+                    self.rollout(config, policy, incumbent, node);
+                }
+            }
+            self.rank(config, sort);
+            let limit = max_width.max(frontier);
+            if let Some(metric) = config.diversity {
+                Self::diversify(metric, config.nb_clusters, sort, limit - frontier);
+            }
             mininodes.truncate(limit);
         }
+        self.account(layer_width, mininodes.len(), node_size);
+    }
+
+    /// Restricts the next layer when its states are interned in the arena, and
+    /// moves the surviving nodes into `mininodes`. This yields the same
+    /// selection as `restrict`, except that the states are decoded one at a
+    /// time and that only the best candidates seen so far (at most twice the
+    /// width) are kept decoded. Hence the decoded layer never coexists with the
+    /// arena. A diversity aware restriction however needs all the states of the
+    /// layer, it decodes them all.
+    fn restrict_encoded<V, N>(&mut self,
+        var: Var,
+        codec: &dyn StateCodec<State = P::State>,
+        config: &mut Config<P, V, N>,
+        incumbent: &mut Incumbent<P::Cost>,
+        mininodes: &mut Vec<MiniNode<P::State, P::Cost>>)
+    where
+        V: VariableOrdering<State = P::State>,
+        N: NodeSelectionHeuristic<State = P::State>,
+    {
+        if config.diversity.is_some() {
+            self.drain_next_layer(Some(codec), mininodes);
+            self.restrict(var, config, incumbent, mininodes);
+            return;
+        }
+        self.depth += 1;
+        let layer_width = self.arena.len();
+        let node_size = config.budget.map(|budget| {
+            let states = (0..layer_width)
+                .map(|i| (budget.size)(&codec.decode(self.arena.get(StateId::new(i)))))
+                .sum::<usize>();
+            Self::node_size(states, layer_width)
+        });
+        let max_width = self.layer_max_width(config, layer_width, node_size);
+        if layer_width <= max_width {
+            for i in 0..layer_width {
+                mininodes.push(self.decode_node(codec, StateId::new(i)));
+            }
+        } else {
+            // we are going to truncate the next layer. it is no longer an exact dd
+            self.is_exact = false;
+            // the mandatory nodes are kept right away, the others compete
+            // for the remaining places
+            let mut candidates = vec![];
+            for i in 0..layer_width {
+                let mut node  = self.decode_node(codec, StateId::new(i));
+                let mandatory = config.node_sel.is_mandatory(self, &node, var, incumbent.best_sol);
+                if mandatory || config.rng.gen_bool(config.proba) {
+                    mininodes.push(node);
+                    continue;
+                }
+                if let Some(policy) = config.rollout {
+                    self.rollout(config, policy, incumbent, &mut node);
+                }
+                candidates.push(node);
+                if candidates.len() >= max_width.saturating_mul(2) {
+                    self.rank(config, &mut candidates);
+                    candidates.truncate(max_width);
+                }
+            }
+            self.rank(config, &mut candidates);
+            candidates.truncate(max_width.saturating_sub(mininodes.len()));
+            mininodes.append(&mut candidates);
+        }
+        self.arena.clear();
+        self.arena_nodes.clear();
+        self.account(layer_width, mininodes.len(), node_size);
+    }
+
+    /// Returns the maximum width of a layer having `layer_width` nodes. This is
+    /// the configured width, unless memory is getting scarce or a memory
+    /// budget narrows it.
+    fn layer_max_width<V, N>(&self,
+        config: &mut Config<P, V, N>,
+        layer_width: usize,
+        node_size: Option<usize>) -> usize
+    where
+        V: VariableOrdering<State = P::State>,
+        N: NodeSelectionHeuristic<State = P::State>,
+    {
+        if config.is_memory_scarce() {
+            // memory is getting scarce: keep narrower layers for the rest of
+            // this compilation
+            config.max_width = (config.max_width.min(layer_width) / 2).max(1);
+        }
+        // a memory budget may further narrow this layer
+        match (config.budget, node_size) {
+            (Some(budget), Some(node_size)) => {
+                let allowance = match budget.bytes {
+                    ByteBudget::PerLayer(bytes)   => bytes,
+                    ByteBudget::PerDiagram(bytes) => {
+                        let layers_left = (config.problem.nb_vars() + 1).saturating_sub(self.depth);
+                        bytes.saturating_sub(self.spent) / layers_left.max(1)
+                    }
+                };
+                config.max_width.min((allowance / node_size).max(1))
+            }
+            _ => config.max_width,
+        }
+    }
+
+    /// Sorts the given nodes from the most to the least relevant one (when a
+    /// rollout policy is set, the nodes have been rolled out beforehand)
+    fn rank<V, N>(&self, config: &Config<P, V, N>, nodes: &mut [MiniNode<P::State, P::Cost>])
+    where
+        V: VariableOrdering<State = P::State>,
+        N: NodeSelectionHeuristic<State = P::State>,
+    {
+        if config.rollout.is_some() {
+            nodes.sort_unstable_by(|a, b| a.rollout.cmp(&b.rollout)
+                .then_with(|| config.node_sel.compare(self, a, b)));
+        } else {
+            nodes.sort_unstable_by(|a, b| config.node_sel.compare(self, a, b));
+        }
+    }
+
+    /// Updates the statistics (and the memory spent) once a layer of
+    /// `layer_width` nodes has been restricted to `kept` nodes
+    fn account(&mut self, layer_width: usize, kept: usize, node_size: Option<usize>) {
+        if let Some(node_size) = node_size {
+            self.spent += kept * node_size;
+        }
+        self.dropped += layer_width - kept;
+        self.widths.push(kept);
+    }
+
+    /// Returns the average memory footprint of the nodes of a layer (the size
+    /// of their state plus the bookkeeping of the diagram) given the total
+    /// size of the states of that layer
+    fn node_size(states: usize, layer_width: usize) -> usize {
+        let overhead = size_of::<Node<P::Cost>>()
+            + size_of::<MiniNode<P::State, P::Cost>>() - size_of::<P::State>();
+        overhead + states / layer_width.max(1)
+    }
+
+    /// Decodes the node of the next layer whose state is interned with the
+    /// given id
+    fn decode_node(&self, codec: &dyn StateCodec<State = P::State>, id: StateId) -> MiniNode<P::State, P::Cost> {
+        let (node_id, estimate) = self.arena_nodes[id.id()];
+        MiniNode {
+            node_id,
+            estimate,
+            rollout: P::Cost::MAX,
+            value: self.nodes[node_id.0].value,
+            state: codec.decode(self.arena.get(id)),
+        }
+    }
+
+    /// Completes the partial solution ending at the given node with the rollout
+    /// policy and remembers the cost of that completion in the node. When
+    /// allowed, the completion also becomes the best solution of the diagram if
+    /// it improves the incumbent. The decisions of the policy are checked just
+    /// like the edges of the diagram (against the domains and the propagator):
+    /// the rollout gives up as soon as one of them is infeasible.
+    fn rollout<V, N>(&mut self,
+        config: &Config<P, V, N>,
+        policy: &dyn RolloutPolicy<State = P::State>,
+        incumbent: &mut Incumbent<P::Cost>,
+        node: &mut MiniNode<P::State, P::Cost>)
+    where
+        V: VariableOrdering<State = P::State>,
+        N: NodeSelectionHeuristic<State = P::State>,
+    {
+        node.rollout    = P::Cost::MAX;
+        let mut value   = node.value;
+        let mut current = None;
+        let mut decisions = vec![];
+        loop {
+            let state = current.as_ref().unwrap_or(&node.state);
+            if let Some(var) = config.var_ord.next(&mut std::iter::once(state)) {
+                let Some(decision) = policy.choose(state, var) else { return };
+                let feasible = decision.var == var
+                    && config.problem.is_in_domain(state, decision)
+                    && config.propagator.is_none_or(|p| p.allows(state, decision));
+                if !feasible {
+                    return;
+                }
+                let next = config.problem.transition(state, decision);
+                if config.propagator.is_some_and(|p| p.is_dead_end(&next)) {
+                    return;
+                }
+                value = value.saturating_add(config.problem.transition_cost(state, decision));
+                current = Some(next);
+                decisions.push(decision);
+            } else {
+                break;
+            }
+        }
+        node.rollout = value;
+
+        if config.rollout_incumbents && value < incumbent.best_val {
+            incumbent.best_val = value;
+            let mut path = self.path(node).collect::<Vec<Decision>>();
+            path.reverse();
+            path.extend(decisions);
+            self.best_rollout = Some((value, Solution::from(path.into_iter())));
+        }
+    }
+
+    /// Moves a diverse selection of `budget` nodes at the beginning of the
+    /// (sorted) `nodes` slice. To that end, the nodes are grouped in clusters
+    /// around centers picked by farthest point sampling (starting from the
+    /// best node). Then the best nodes of each cluster are selected, and the
+    /// remaining budget goes to the best nodes that were not selected yet.
+    fn diversify(
+        metric: &dyn StateDistance<State = P::State>,
+        nb_clusters: usize,
+        nodes: &mut [MiniNode<P::State, P::Cost>],
+        budget: usize,
+    ) {
+        let n = nodes.len();
+        if budget == 0 || n <= budget {
+            return;
+        }
+        // pick the cluster centers
+        let k           = nb_clusters.clamp(1, budget);
+        let mut centers = vec![0];
+        let mut cluster = vec![0; n];
+        let mut closest = nodes.iter()
+            .map(|node| metric.distance(&nodes[0].state, &node.state))
+            .collect::<Vec<f64>>();
+
+        while centers.len() < k {
+            let (far, dist) = closest.iter().copied().enumerate()
+                .fold((0, 0.0), |best, x| if x.1 > best.1 { x } else { best });
+            // all nodes are equivalent to some center
+            if dist <= 0.0 {
+                break;
+            }
+            let id = centers.len();
+            centers.push(far);
+            for (i, node) in nodes.iter().enumerate() {
+                let d = metric.distance(&nodes[far].state, &node.state);
+                if d < closest[i] {
+                    closest[i] = d;
+                    cluster[i] = id;
+                }
+            }
+        }
+        // keep the best nodes from each cluster
+        let quota     = budget / centers.len();
+        let mut taken = vec![0; centers.len()];
+        let mut keep  = vec![false; n];
+        let mut kept  = 0;
+        for (i, c) in cluster.iter().copied().enumerate() {
+            if taken[c] < quota {
+                taken[c] += 1;
+                keep[i]   = true;
+                kept     += 1;
+            }
+        }
+        // and fill the remaining budget with the best ones
+        for k in keep.iter_mut() {
+            if kept == budget {
+                break;
+            }
+            if !*k {
+                *k    = true;
+                kept += 1;
+            }
+        }
+        // move the selected nodes upfront (preserving their relative order)
+        let mut front = 0;
+        for (i, k) in keep.iter().copied().enumerate() {
+            if k {
+                nodes.swap(i, front);
+                front += 1;
+            }
+        }
     }
 
     fn dive_if_needed<V, N>(&mut self, 
         config: &Config<P, V, N>, 
-        incumbent: &Incumbent, 
-        mininodes: &mut Vec<MiniNode<<P as Problem>::State>>) 
+        incumbent: &Incumbent<P::Cost>, 
+        mininodes: &mut Vec<MiniNode<P::State, P::Cost>>) 
     where
         V: VariableOrdering<State = P::State>,
-        N: NodeSelectionHeuristic,
+        N: NodeSelectionHeuristic<State = P::State>,
     {
         if let Some(sol) = incumbent.best_sol {
             // cant be exact otherwise
             self.is_exact = config.start_depth == 0;
 
-            for _ in 0..config.start_depth {
-                let var      = config.var_ord.next(&mut mininodes.iter().map(|n| n.state())).unwrap();
+            for depth in 0..config.start_depth {
+                let var      = sol.var_at(depth)
+                    .or_else(|| config.var_ord.next(&mut mininodes.iter().map(|n| n.state())))
+                    .unwrap();
                 let val      = sol[var];
                 let decision = Decision { var, val };
                 for mininode in mininodes.drain(..) {
                     self.branch_on(
-                        config.kill_switch,
+                        config,
                         false,
-                        config.problem,
+                        P::Cost::MAX,
                         &mininode,
                         decision,
                     );
                 }
                 // collect the single one node into a mininode (keep it uniform
                 // with the non dive case
-                for e in self.next_layer_states.drain() {
-                    mininodes.push(MiniNode {
-                        node_id: e.1,
-                        estimate: config.problem.estimate(&e.0),
-                        value: self.nodes[e.1 .0].value,
-                        state: e.0,
-                    });
-                }    
+                self.drain_next_layer(config.codec, mininodes);
+            }
+        }
+    }
+
+    /// Moves all the nodes of the next layer into the `mininodes` vector
+    fn drain_next_layer(
+        &mut self,
+        codec: Option<&dyn StateCodec<State = P::State>>,
+        mininodes: &mut Vec<MiniNode<P::State, P::Cost>>,
+    ) {
+        self.depth += 1;
+        if let Some(codec) = codec {
+            for i in 0..self.arena.len() {
+                mininodes.push(self.decode_node(codec, StateId::new(i)));
+            }
+            self.arena.clear();
+            self.arena_nodes.clear();
+        } else {
+            for (state, (node_id, estimate)) in self.next_layer_states.drain() {
+                mininodes.push(MiniNode {
+                    node_id,
+                    estimate,
+                    rollout: P::Cost::MAX,
+                    value: self.nodes[node_id.0].value,
+                    state,
+                });
             }
         }
     }
 
-    fn branch_on(
+    /// Adds the edge reached from `from` by taking the given decision to the
+    /// next layer. That edge is pruned right away when the cost of the path
+    /// going through it plus the estimate of its destination cannot improve
+    /// the `best_val` bound. The estimate of each state that gets a node is
+    /// computed only once per layer: it is cached along with that node. The
+    /// states that are pruned are not remembered (that would keep the states
+    /// the diagram discards), hence their estimate is recomputed each time
+    /// another edge reaches them. When a propagator is configured, the edges it
+    /// forbids and the edges leading to a dead-end state are discarded as well.
+    fn branch_on<V, N>(
         &mut self,
-        kill_switch: &AtomicBool,
+        config: &Config<P, V, N>,
         failible: bool,
-        problem: &P,
-        from: &MiniNode<P::State>,
+        best_val: P::Cost,
+        from: &MiniNode<P::State, P::Cost>,
         decision: Decision,
-    ) {
-        if failible && kill_switch.load(Ordering::Relaxed) {
+    ) where
+        V: VariableOrdering<State = P::State>,
+        N: NodeSelectionHeuristic<State = P::State>,
+    {
+        if failible && config.kill_switch.load(Ordering::Relaxed) {
             self.is_exact = false;
             return;
         }
+        let from_id = from.node_id.0;
+        let val     = decision.val;
+        if let Some(propagator) = config.propagator {
+            if !propagator.allows(&from.state, decision) {
+                config.record(ProofStep::Discard { from: from_id, val });
+                return;
+            }
+        }
         //
+        let problem = config.problem;
         let state = problem.transition(&from.state, decision);
         let cost = problem.transition_cost(&from.state, decision);
 
+        if let Some(propagator) = config.propagator {
+            if propagator.is_dead_end(&state) {
+                config.record(ProofStep::Discard { from: from_id, val });
+                return;
+            }
+        }
+
         let total = from.value.saturating_add(cost);
 
         // do I need to create a new node ?
-        match self.next_layer_states.entry(state) {
-            // yes, i do
-            Entry::Vacant(e) => {
-                let new_node_id = NodeId(self.nodes.len());
-
-                let edge = Edge {
-                    from: from.node_id,
-                    to: new_node_id,
-                    label: decision,
-                    weight: cost,
-                };
-
-                let node = Node {
-                    my_id: new_node_id,
-                    value: total,
-                    best_parent: Some(edge),
-                };
-
-                self.nodes.push(node);
-                e.insert(new_node_id);
+        if let Some(codec) = config.codec {
+            self.buffer.clear();
+            codec.encode(&state, &mut self.buffer);
+            match self.arena.entry(&self.buffer) {
+                ArenaEntry::Occupied(id) => {
+                    let (reused_node_id, estimate) = self.arena_nodes[id.id()];
+                    if total.saturating_add(estimate) < best_val {
+                        Self::relax_node(&mut self.nodes, reused_node_id, from, decision, cost, total);
+                        config.record(ProofStep::Merge { id: reused_node_id.0, from: from_id, val });
+                    } else {
+                        config.record(ProofStep::Prune { from: from_id, val });
+                    }
+                }
+                ArenaEntry::Vacant(slot) => {
+                    let estimate = problem.estimate(&state);
+                    if total.saturating_add(estimate) < best_val {
+                        slot.insert();
+                        let new_node_id = Self::create_node(&mut self.nodes, from, decision, cost, total);
+                        self.arena_nodes.push((new_node_id, estimate));
+                        config.record(ProofStep::Node { id: new_node_id.0, from: from_id, val });
+                    } else {
+                        config.record(ProofStep::Prune { from: from_id, val });
+                    }
+                }
             }
-            // No i don't but i still need to add an edge (if it improves the path)
-            Entry::Occupied(e) => {
-                let reused_node_id = *e.get();
-                let reused_node = &mut self.nodes[reused_node_id.0];
-
-                if reused_node.value > total {
-                    // we do improve the best path, hence we must adapt the best parent
-                    let edge = Edge {
-                        from: from.node_id,
-                        to: reused_node_id,
-                        label: decision,
-                        weight: cost,
-                    };
-                    reused_node.value = total;
-                    reused_node.best_parent = Some(edge);
+        } else {
+            match self.next_layer_states.entry(state) {
+                // yes, i do (unless the edge is pruned)
+                Entry::Vacant(e) => {
+                    let estimate = problem.estimate(e.key());
+                    if total.saturating_add(estimate) < best_val {
+                        let new_node_id = Self::create_node(&mut self.nodes, from, decision, cost, total);
+                        e.insert((new_node_id, estimate));
+                        config.record(ProofStep::Node { id: new_node_id.0, from: from_id, val });
+                    } else {
+                        config.record(ProofStep::Prune { from: from_id, val });
+                    }
+                }
+                // No i don't but i still need to add an edge (if it improves the path)
+                Entry::Occupied(e) => {
+                    let (reused_node_id, estimate) = *e.get();
+                    if total.saturating_add(estimate) < best_val {
+                        Self::relax_node(&mut self.nodes, reused_node_id, from, decision, cost, total);
+                        config.record(ProofStep::Merge { id: reused_node_id.0, from: from_id, val });
+                    } else {
+                        config.record(ProofStep::Prune { from: from_id, val });
+                    }
                 }
             }
         }
     }
+
+    /// Creates a new node which is reached from `from` by taking the given
+    /// decision
+    fn create_node(
+        nodes: &mut Vec<Node<P::Cost>>,
+        from: &MiniNode<P::State, P::Cost>,
+        decision: Decision,
+        cost: P::Cost,
+        total: P::Cost,
+    ) -> NodeId {
+        let new_node_id = NodeId(nodes.len());
+
+        let edge = Edge {
+            from: from.node_id,
+            to: new_node_id,
+            label: decision,
+            weight: cost,
+        };
+
+        let node = Node {
+            my_id: new_node_id,
+            value: total,
+            best_parent: Some(edge),
+        };
+
+        nodes.push(node);
+        new_node_id
+    }
+
+    /// Adds an edge from `from` to an existing node if it improves the best
+    /// path to that node
+    fn relax_node(
+        nodes: &mut [Node<P::Cost>],
+        reused_node_id: NodeId,
+        from: &MiniNode<P::State, P::Cost>,
+        decision: Decision,
+        cost: P::Cost,
+        total: P::Cost,
+    ) {
+        let reused_node = &mut nodes[reused_node_id.0];
+
+        if reused_node.value > total {
+            // we do improve the best path, hence we must adapt the best parent
+            let edge = Edge {
+                from: from.node_id,
+                to: reused_node_id,
+                label: decision,
+                weight: cost,
+            };
+            reused_node.value = total;
+            reused_node.best_parent = Some(edge);
+        }
+    }
 }
 
-impl<S> SelectableNode for MiniNode<S> {
+impl<S, C: Cost> SelectableNode for MiniNode<S, C> {
     type State = S;
+    type Cost = C;
 
     fn state(&self) -> &Self::State {
         &self.state
     }
 
-    fn value(&self) -> isize {
+    fn value(&self) -> C {
         self.value
     }
 
-    fn estimate(&self) -> isize {
+    fn estimate(&self) -> C {
         self.estimate
     }
 }
@@ -508,32 +1177,29 @@ where
     P: Problem,
 {
     type State = P::State;
-    type Node = MiniNode<P::State>;
-    type Path = PathIter<P>;
+    type Node = MiniNode<P::State, P::Cost>;
+    type Path<'a> = PathIter<'a, P> where Self: 'a;
 
-    fn path(&self, node: &Self::Node) -> Self::Path {
-        let me = self as *const Diagram<P>;
-        let cur = self.nodes[node.node_id.0].best_parent;
+    fn path(&self, node: &Self::Node) -> Self::Path<'_> {
         PathIter {
-            diagram: me,
-            current: cur,
+            diagram: self,
+            current: self.nodes[node.node_id.0].best_parent,
         }
     }
 }
 
-/// This iterator is unsafe as it borrows the diagram without explicitly
-/// mentioning its lifetime. This could be fixed if generic associated types
-/// became generic
+/// This iterator walks the best path from a node back to the root of the
+/// diagram. It borrows the diagram, hence it cannot outlive it.
 #[derive(Clone, Copy)]
-struct PathIter<P>
+struct PathIter<'a, P>
 where
     P: Problem,
     P::State: Eq + PartialEq + Hash,
 {
-    diagram: *const Diagram<P>,
-    current: Option<Edge>,
+    diagram: &'a Diagram<P>,
+    current: Option<Edge<P::Cost>>,
 }
-impl<P> Iterator for PathIter<P>
+impl<P> Iterator for PathIter<'_, P>
 where
     P: Problem,
     P::State: Eq + PartialEq + Hash,
@@ -542,10 +1208,7 @@ where
 
     fn next(&mut self) -> Option<Self::Item> {
         if let Some(edge) = self.current {
-            unsafe {
-                let diagram = &*self.diagram;
-                self.current = diagram.nodes[edge.from.0].best_parent;
-            }
+            self.current = self.diagram.nodes[edge.from.0].best_parent;
             Some(edge.label)
         } else {
             None
@@ -0,0 +1,198 @@
+//! This module provides the tiny problems used by the unit tests of the crate:
+//! a 0-1 knapsack whose states remember which items have been decided (hence,
+//! its variables are not positional: the items can be decided in any order)
+//! and a walk on a cycle whose states are reached by many different paths.
+//! Both problems count their transitions, which lets the tests check how much
+//! work a solver does.
+
+use std::cell::Cell;
+
+use crate::{Decision, FreeVariables, Problem, Var, VariableOrdering};
+
+/// A 0-1 knapsack: the objective is the opposite of the total profit
+#[derive(Debug, Clone)]
+pub struct Knapsack {
+    pub capacity: isize,
+    pub weight: Vec<isize>,
+    pub profit: Vec<isize>,
+    /// The number of transitions computed so far
+    pub transitions: Cell<usize>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct KnapsackState {
+    /// The set of items that have been decided (one bit per item)
+    pub decided: u32,
+    /// The capacity that is left
+    pub room: isize,
+}
+
+impl Knapsack {
+    pub fn new(capacity: isize, weight: &[isize], profit: &[isize]) -> Self {
+        Self {
+            capacity,
+            weight: weight.to_vec(),
+            profit: profit.to_vec(),
+            transitions: Cell::new(0),
+        }
+    }
+    /// A small instance having a fair amount of equivalent states
+    pub fn small() -> Self {
+        Self::new(
+            50,
+            &[ 5, 12,  9, 14,  8, 11,  6, 15,  7, 10,  4, 13],
+            &[10, 21, 16, 27, 13, 20,  9, 29, 12, 17,  6, 24],
+        )
+    }
+    /// The optimum of the instance, found by enumerating all the subsets
+    pub fn brute_force(&self) -> isize {
+        let n = self.weight.len();
+        (0_u32..(1 << n))
+            .filter(|set| (0..n).filter(|i| set & (1 << i) != 0).map(|i| self.weight[i]).sum::<isize>() <= self.capacity)
+            .map(|set| -(0..n).filter(|i| set & (1 << i) != 0).map(|i| self.profit[i]).sum::<isize>())
+            .min()
+            .unwrap_or(0)
+    }
+}
+
+impl Problem for Knapsack {
+    type State = KnapsackState;
+    type Cost = isize;
+
+    fn nb_vars(&self) -> usize {
+        self.weight.len()
+    }
+    fn initial_state(&self) -> KnapsackState {
+        KnapsackState { decided: 0, room: self.capacity }
+    }
+    fn initial_value(&self) -> isize {
+        0
+    }
+    fn for_each_in_domain(&self, state: &KnapsackState, var: Var, mut f: impl FnMut(Decision)) {
+        if state.decided & (1 << var.id()) != 0 {
+            return;
+        }
+        f(Decision::new(var, 0));
+        if self.weight[var.id()] <= state.room {
+            f(Decision::new(var, 1));
+        }
+    }
+    fn transition(&self, state: &KnapsackState, decision: Decision) -> KnapsackState {
+        self.transitions.set(self.transitions.get() + 1);
+        KnapsackState {
+            decided: state.decided | (1 << decision.var.id()),
+            room: state.room - decision.val * self.weight[decision.var.id()],
+        }
+    }
+    fn transition_cost(&self, _state: &KnapsackState, decision: Decision) -> isize {
+        -decision.val * self.profit[decision.var.id()]
+    }
+    // taking all the remaining items that fit cannot be beaten
+    fn estimate(&self, state: &KnapsackState) -> isize {
+        -(0..self.weight.len())
+            .filter(|i| state.decided & (1 << i) == 0 && self.weight[*i] <= state.room)
+            .map(|i| self.profit[i])
+            .sum::<isize>()
+    }
+}
+
+impl FreeVariables for Knapsack {
+    type State = KnapsackState;
+
+    fn for_each_free_var(&self, state: &KnapsackState, mut f: impl FnMut(Var)) {
+        (0..self.weight.len())
+            .filter(|i| state.decided & (1 << i) == 0)
+            .for_each(|i| f(Var::new(i)))
+    }
+}
+
+/// Decides the items in the order of their index
+#[derive(Debug, Clone, Copy)]
+pub struct InOrder(pub usize);
+impl VariableOrdering for InOrder {
+    type State = KnapsackState;
+
+    fn next(&self, states: &mut dyn Iterator<Item = &KnapsackState>) -> Option<Var> {
+        let decided = states.next()?.decided;
+        (0..self.0).find(|i| decided & (1 << i) == 0).map(Var::new)
+    }
+}
+
+/// A walk on a cycle of `k` positions: each step moves 0, 1 or 2 positions
+/// forward and its cost depends on the position, the move and the step. The
+/// walk has no estimate, hence nothing gets pruned by a bound.
+#[derive(Debug, Clone)]
+pub struct Walk {
+    pub steps: usize,
+    pub k: usize,
+    /// The number of transitions computed so far
+    pub transitions: Cell<usize>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct WalkState {
+    pub depth: usize,
+    pub pos: usize,
+}
+
+impl Walk {
+    pub fn new(steps: usize, k: usize) -> Self {
+        Self { steps, k, transitions: Cell::new(0) }
+    }
+    fn cost(&self, state: &WalkState, val: isize) -> isize {
+        ((state.pos * 7 + val as usize * 13 + state.depth * 3) % 10) as isize
+    }
+    /// The optimum of the walk, found by a plain forward dynamic program
+    pub fn optimum(&self) -> isize {
+        let mut best = vec![isize::MAX; self.k];
+        best[0] = 0;
+        for depth in 0..self.steps {
+            let mut next = vec![isize::MAX; self.k];
+            for pos in (0..self.k).filter(|pos| best[*pos] < isize::MAX) {
+                for val in 0..3 {
+                    let to = (pos + val as usize) % self.k;
+                    next[to] = next[to].min(best[pos] + self.cost(&WalkState { depth, pos }, val));
+                }
+            }
+            best = next;
+        }
+        best.into_iter().min().unwrap_or(0)
+    }
+}
+
+impl Problem for Walk {
+    type State = WalkState;
+    type Cost = isize;
+
+    fn nb_vars(&self) -> usize {
+        self.steps
+    }
+    fn initial_state(&self) -> WalkState {
+        WalkState { depth: 0, pos: 0 }
+    }
+    fn initial_value(&self) -> isize {
+        0
+    }
+    fn for_each_in_domain(&self, _state: &WalkState, var: Var, mut f: impl FnMut(Decision)) {
+        (0..3).for_each(|val| f(Decision::new(var, val)))
+    }
+    fn transition(&self, state: &WalkState, decision: Decision) -> WalkState {
+        self.transitions.set(self.transitions.get() + 1);
+        WalkState { depth: state.depth + 1, pos: (state.pos + decision.val as usize) % self.k }
+    }
+    fn transition_cost(&self, state: &WalkState, decision: Decision) -> isize {
+        self.cost(state, decision.val)
+    }
+}
+
+/// Decides the steps of the walk in order
+#[derive(Debug, Clone, Copy)]
+pub struct StepByStep(pub usize);
+impl VariableOrdering for StepByStep {
+    type State = WalkState;
+
+    fn next(&self, states: &mut dyn Iterator<Item = &WalkState>) -> Option<Var> {
+        let depth = states.next()?.depth;
+        (depth < self.0).then(|| Var::new(depth))
+    }
+}
@@ -3,19 +3,23 @@
 
 use std::{
     alloc::{GlobalAlloc, System},
+    cell::Cell,
     fmt::{Debug, Display},
     ops::{Index, IndexMut},
-    sync::atomic::{AtomicUsize, Ordering},
+    ptr::null_mut,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
 };
 
-use signal_hook::{consts::SIGINT, low_level::raise};
-
 /// This structure implements a 2D matrix of size [ n X m ].
 ///
 ///
 /// # Example
 /// ```
-/// # use psp-parsing::Matrix;
+/// # use papier_lns::Matrix;
 ///
 /// let mut adjacency = Matrix::new_default(5, 5, None);
 ///
@@ -127,12 +131,27 @@ impl<T: Display> Display for Matrix<T> {
 }
 
 // ----------------------------------------------------------------------------
-/// Un allocateur qui envoie un signal lorsqu'une limite de mémoire a été
-/// allouée.
+/// Un allocateur qui garde la trace de la mémoire allouée et qui permet de
+/// réagir lorsque celle-ci devient rare.
+///
+/// It enforces two limits:
+/// * the *soft* limit is only informative: crossing it does not change the
+///   behavior of the allocator, but `is_over_soft_limit` tells it. The solvers
+///   which are given this allocator as a `MemoryMonitor` react to it (e.g. by
+///   narrowing their diagrams).
+/// * the *hard* limit is enforced: any allocation that would cross it fails.
+///   The runtime then aborts the process with an allocation error, which
+///   makes an out-of-memory run easy to tell apart from an interrupted one.
+///
+/// The allocator keeps its historical name (it used to raise a SIGINT when
+/// the limit was crossed) because the binaries and the scripts that drive
+/// them know it under that name.
 // ----------------------------------------------------------------------------
 pub struct SigLimitAllocator<A = System> {
+    /// The number of bytes above which the memory is considered scarce
+    soft_limit: AtomicUsize,
     /// The max number of bytes that may be allocated
-    limit: AtomicUsize,
+    hard_limit: AtomicUsize,
     /// The current amount of ram which is being currently allocated
     used: AtomicUsize,
     /// Some bookkeeping to keep track of the max amount of ram which has ever
@@ -144,26 +163,44 @@ pub struct SigLimitAllocator<A = System> {
 }
 #[allow(dead_code)]
 impl<A> SigLimitAllocator<A> {
+    /// Creates an allocator with the given hard limit (and no soft limit)
     pub const fn new(alloc: A, limit: usize) -> Self {
         Self {
-            limit: AtomicUsize::new(limit),
+            soft_limit: AtomicUsize::new(usize::MAX),
+            hard_limit: AtomicUsize::new(limit),
             used: AtomicUsize::new(0),
             peak: AtomicUsize::new(0),
             alloc,
         }
     }
 
-    pub fn set_limit_gb(&self, limit: f64) {
-        self.set_limit_mb(1024.0 * limit)
+    pub fn set_soft_limit_gb(&self, limit: f64) {
+        self.set_soft_limit_mb(1024.0 * limit)
+    }
+    pub fn set_soft_limit_mb(&self, limit: f64) {
+        self.set_soft_limit_kb(limit * 1024.0)
+    }
+    pub fn set_soft_limit_kb(&self, limit: f64) {
+        self.set_soft_limit((limit * 1024.0).ceil() as usize)
     }
-    pub fn set_limit_mb(&self, limit: f64) {
-        self.set_limit_kb(limit * 1024.0)
+    pub fn set_soft_limit(&self, limit: usize) {
+        self.soft_limit.store(limit, Ordering::Relaxed)
     }
-    pub fn set_limit_kb(&self, limit: f64) {
-        self.set_limit((limit * 1024.0).ceil() as usize)
+    pub fn set_hard_limit_gb(&self, limit: f64) {
+        self.set_hard_limit_mb(1024.0 * limit)
     }
-    pub fn set_limit(&self, limit: usize) {
-        self.limit.store(limit, Ordering::Relaxed)
+    pub fn set_hard_limit_mb(&self, limit: f64) {
+        self.set_hard_limit_kb(limit * 1024.0)
+    }
+    pub fn set_hard_limit_kb(&self, limit: f64) {
+        self.set_hard_limit((limit * 1024.0).ceil() as usize)
+    }
+    pub fn set_hard_limit(&self, limit: usize) {
+        self.hard_limit.store(limit, Ordering::Relaxed)
+    }
+    /// Returns true iff more memory than the soft limit is currently allocated
+    pub fn is_over_soft_limit(&self) -> bool {
+        self.get_usage() > self.soft_limit.load(Ordering::Relaxed)
     }
     pub fn get_usage(&self) -> usize {
         self.used.load(Ordering::Relaxed)
@@ -199,23 +236,117 @@ unsafe impl<A: GlobalAlloc> GlobalAlloc for SigLimitAllocator<A> {
         let old_used = self.used.fetch_add(size, Ordering::SeqCst);
         let used = old_used + size;
 
-        if old_used > self.limit.load(Ordering::Relaxed) {
-            // It is not allowed to fail during an allocation.
-            // Thus, we simply ignore the potential problems that might cause
-            // the system's inability to send the signal; and we assume that
-            // it will be rethrown some time later
-            let _ = raise(SIGINT);
+        if used > self.hard_limit.load(Ordering::Relaxed) {
+            // refuse the allocation: the caller is told there is no memory
+            // left (which usually means the process aborts)
+            self.used.fetch_sub(size, Ordering::SeqCst);
+            return null_mut();
         }
 
-        // keep track of the max quantity of allocated memory
-        self.peak.fetch_max(used, Ordering::SeqCst);
-
         // actually proceed to allocation
-        self.alloc.alloc(layout)
+        let ptr = self.alloc.alloc(layout);
+        if ptr.is_null() {
+            self.used.fetch_sub(size, Ordering::SeqCst);
+        } else {
+            // keep track of the max quantity of allocated memory
+            self.peak.fetch_max(used, Ordering::SeqCst);
+            track_thread_usage(size as isize);
+        }
+        ptr
     }
 
     unsafe fn dealloc(&self, ptr: *mut u8, layout: std::alloc::Layout) {
         self.used.fetch_sub(layout.size(), Ordering::SeqCst);
+        track_thread_usage(-(layout.size() as isize));
         self.alloc.dealloc(ptr, layout)
     }
 }
+
+// ----------------------------------------------------------------------------
+// Per thread accounting
+// ----------------------------------------------------------------------------
+thread_local! {
+    /// The net amount of memory allocated by the current thread through a
+    /// `SigLimitAllocator`. (The initializer is const and the cell needs no
+    /// destructor: accessing it never allocates, which makes it usable from
+    /// within the allocator itself)
+    static THREAD_USAGE: Cell<isize> = const { Cell::new(0) };
+}
+
+fn track_thread_usage(delta: isize) {
+    // the accounting is silently skipped while the thread is being torn down
+    let _ = THREAD_USAGE.try_with(|usage| usage.set(usage.get() + delta));
+}
+
+/// Returns the net amount of memory (in bytes) which has been allocated by the
+/// current thread through a `SigLimitAllocator`. This amount is negative when
+/// the thread has released more memory than it allocated (e.g. because it has
+/// dropped data that was allocated by some other thread).
+pub fn thread_usage() -> isize {
+    THREAD_USAGE.try_with(Cell::get).unwrap_or(0)
+}
+
+/// Measures the net amount of memory allocated by the current thread between
+/// the moment the scope was entered and now.
+///
+/// # Example
+/// ```
+/// # use papier_lns::MemoryScope;
+/// let scope = MemoryScope::enter();
+/// let data  = vec![0_u8; 1024];
+/// // scope.usage() is 1024 when a `SigLimitAllocator` is the global allocator
+/// # drop(data); let _ = scope.usage();
+/// ```
+#[derive(Debug, Clone, Copy)]
+pub struct MemoryScope {
+    start: isize,
+}
+impl MemoryScope {
+    pub fn enter() -> Self {
+        Self { start: thread_usage() }
+    }
+    /// The net number of bytes allocated by this thread since the scope began
+    pub fn usage(&self) -> isize {
+        thread_usage() - self.start
+    }
+}
+
+// ----------------------------------------------------------------------------
+// Memory monitor
+// ----------------------------------------------------------------------------
+/// Tells the solvers whether or not memory is getting scarce. The solvers
+/// which can trade quality for memory (e.g. by narrowing their diagrams) use
+/// it to avoid exhausting the available memory.
+pub trait MemoryMonitor {
+    /// Returns true iff the memory usage exceeds the soft limit
+    fn is_over_soft_limit(&self) -> bool;
+}
+impl<A> MemoryMonitor for SigLimitAllocator<A> {
+    fn is_over_soft_limit(&self) -> bool {
+        SigLimitAllocator::is_over_soft_limit(self)
+    }
+}
+impl<T: MemoryMonitor + ?Sized> MemoryMonitor for &T {
+    fn is_over_soft_limit(&self) -> bool {
+        (**self).is_over_soft_limit()
+    }
+}
+
+/// Spawns a thread that turns the kill switch on as soon as the monitored
+/// memory exceeds its soft limit. This is meant for the solvers that have no
+/// way to reduce their memory footprint: they are stopped gracefully (and
+/// report their outcome) rather than running out of memory. The thread exits
+/// once the kill switch is on.
+pub fn kill_on_soft_limit<M>(monitor: &'static M, kill_switch: Arc<AtomicBool>, period: Duration)
+where
+    M: MemoryMonitor + Sync + ?Sized,
+{
+    std::thread::spawn(move || {
+        while !kill_switch.load(Ordering::Relaxed) {
+            if monitor.is_over_soft_limit() {
+                kill_switch.store(true, Ordering::Relaxed);
+            }
+            std::thread::sleep(period);
+        }
+    });
+}